@@ -0,0 +1,7 @@
+#!/bin/sh
+# Build the unstripped libnotes.so fixture, with DWARF debug info and a GNU build ID. The inlined
+# `title_length` lets the tests check the expansion of inlined frames. The output is committed, as
+# its build ID and addresses depend on the toolchain that built it.
+set -e
+cd "$(dirname "$0")"
+gcc -shared -ffile-prefix-map="$(pwd)"=/src -fPIC -O2 -g -Wl,--build-id=sha1 -o libnotes.so libnotes.c
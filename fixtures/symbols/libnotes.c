@@ -0,0 +1,29 @@
+/* Source of the libnotes.so fixture, see build.sh. */
+
+struct note {
+    const char *title;
+    int length;
+};
+
+static inline int title_length(const struct note *note)
+{
+    int length = 0;
+    while (note->title[length] != '\0') {
+        length++;
+    }
+    return length;
+}
+
+__attribute__((noinline)) int render_note(const struct note *note)
+{
+    return title_length(note) + note->length;
+}
+
+int draw(const struct note *notes, int count)
+{
+    int total = 0;
+    for (int i = 0; i < count; i++) {
+        total += render_note(&notes[i]);
+    }
+    return total;
+}
@@ -0,0 +1,54 @@
+CREATE TABLE users (
+    id       BIGSERIAL NOT NULL PRIMARY KEY,
+    username TEXT      NOT NULL UNIQUE,
+    password TEXT      NOT NULL
+);
+
+CREATE TABLE apps (
+    id       BIGSERIAL NOT NULL PRIMARY KEY,
+    user_id  BIGINT    NOT NULL REFERENCES users(id),
+    name     TEXT      NOT NULL,
+    username TEXT      NOT NULL,
+    password TEXT      NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE TABLE versions (
+    id     BIGSERIAL NOT NULL PRIMARY KEY,
+    app_id BIGINT    NOT NULL REFERENCES apps(id),
+    name   TEXT      NOT NULL,
+    code   BIGINT    NOT NULL,
+    UNIQUE (app_id, name, code)
+);
+
+CREATE TABLE issues (
+    id          BIGSERIAL NOT NULL PRIMARY KEY,
+    app_id      BIGINT    NOT NULL REFERENCES apps(id),
+    fingerprint TEXT      NOT NULL,
+    title       TEXT      NOT NULL,
+    first_seen  TIMESTAMP NOT NULL,
+    last_seen   TIMESTAMP NOT NULL,
+    UNIQUE (app_id, fingerprint)
+);
+
+CREATE TABLE reports (
+    id          BIGSERIAL NOT NULL PRIMARY KEY,
+    version_id  BIGINT    NOT NULL REFERENCES versions(id),
+    issue_id    BIGINT    REFERENCES issues(id),
+    report_id   TEXT      NOT NULL,
+    crash_date  TEXT      NOT NULL,
+    received_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX reports_report_id_idx ON reports(report_id);
+
+CREATE TABLE notification_settings (
+    user_id   BIGINT  NOT NULL PRIMARY KEY REFERENCES users(id),
+    email     TEXT    NOT NULL,
+    immediate BOOLEAN NOT NULL DEFAULT FALSE,
+    digest    BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO users (username, password) VALUES ('admin', 'admin');
+INSERT INTO apps (user_id, name, username, password) VALUES (1, 'Test', 'test', 'test');
+INSERT INTO versions (app_id, name, code) VALUES (1, '1.0.0', 1);
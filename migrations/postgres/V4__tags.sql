@@ -0,0 +1,15 @@
+CREATE TABLE tag_keys (
+    app_id BIGINT  NOT NULL REFERENCES apps(id),
+    source TEXT    NOT NULL,
+    key    TEXT    NOT NULL,
+    PRIMARY KEY (app_id, source, key)
+);
+
+CREATE TABLE report_tags (
+    report_id TEXT NOT NULL,
+    key       TEXT NOT NULL,
+    value     TEXT NOT NULL,
+    PRIMARY KEY (report_id, key)
+);
+
+CREATE INDEX report_tags_key_value_idx ON report_tags(key, value);
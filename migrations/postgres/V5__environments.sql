@@ -0,0 +1,13 @@
+ALTER TABLE reports ADD COLUMN environment TEXT NOT NULL DEFAULT 'production';
+
+CREATE INDEX reports_issue_id_environment_idx ON reports(issue_id, environment);
+
+CREATE TABLE environment_rules (
+    app_id      BIGINT  NOT NULL REFERENCES apps(id),
+    position    BIGINT  NOT NULL,
+    source      TEXT    NOT NULL,
+    key         TEXT    NOT NULL,
+    value       TEXT    NOT NULL,
+    environment TEXT    NOT NULL,
+    PRIMARY KEY (app_id, position)
+);
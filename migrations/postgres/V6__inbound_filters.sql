@@ -0,0 +1,7 @@
+CREATE TABLE inbound_filters (
+    app_id   BIGINT  NOT NULL REFERENCES apps(id),
+    position BIGINT  NOT NULL,
+    rule     TEXT    NOT NULL,
+    dropped  BIGINT  NOT NULL DEFAULT 0,
+    PRIMARY KEY (app_id, rule)
+);
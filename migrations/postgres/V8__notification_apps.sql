@@ -0,0 +1,10 @@
+-- Alerts about new issues are chosen per app. Users that already get alerts keep getting them for
+-- all existing apps.
+CREATE TABLE notification_apps (
+    user_id BIGINT NOT NULL REFERENCES notification_settings(user_id),
+    app_id  BIGINT NOT NULL REFERENCES apps(id),
+    PRIMARY KEY (user_id, app_id)
+);
+
+INSERT INTO notification_apps (user_id, app_id)
+SELECT n.user_id, a.id FROM notification_settings n, apps a WHERE n.immediate;
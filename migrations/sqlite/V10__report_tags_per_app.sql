@@ -0,0 +1,19 @@
+-- Report IDs are only unique within an app, so tags are kept per app as well.
+CREATE TABLE report_tags_per_app (
+    app_id    INTEGER NOT NULL,
+    report_id TEXT    NOT NULL,
+    key       TEXT    NOT NULL,
+    value     TEXT    NOT NULL,
+    PRIMARY KEY (app_id, report_id, key)
+);
+
+INSERT OR IGNORE INTO report_tags_per_app (app_id, report_id, key, value)
+SELECT v.app_id, t.report_id, t.key, t.value
+FROM report_tags t
+JOIN reports r ON r.report_id = t.report_id
+JOIN versions v ON v.id = r.version_id;
+
+DROP TABLE report_tags;
+ALTER TABLE report_tags_per_app RENAME TO report_tags;
+
+CREATE INDEX report_tags_key_value_idx ON report_tags(key, value);
@@ -0,0 +1,32 @@
+-- Native crashes and ANRs in the DropBox entries of a report are issues of their own. Instead of
+-- storing the report once more for each of them, they're linked to it.
+CREATE TABLE report_issues (
+    report_id INTEGER NOT NULL REFERENCES reports(id),
+    issue_id  INTEGER NOT NULL REFERENCES issues(id),
+    PRIMARY KEY (report_id, issue_id)
+);
+
+CREATE INDEX report_issues_issue_id_idx ON report_issues(issue_id);
+
+INSERT OR IGNORE INTO report_issues (report_id, issue_id)
+SELECT r.id, c.issue_id
+FROM reports c
+JOIN reports r
+    ON r.report_id = c.report_id
+    AND r.version_id = c.version_id
+    AND r.crash_type IN ('fatal', 'non_fatal')
+WHERE c.crash_type IN ('native', 'anr');
+
+DELETE FROM reports WHERE crash_type IN ('native', 'anr');
+
+-- All reports of each issue, either its own ones or linked ones.
+CREATE VIEW issue_reports AS
+SELECT id, version_id, issue_id, report_id, crash_date, crash_type, config_changes, low_memory,
+    environment, received_at
+FROM reports
+UNION ALL
+SELECT r.id, r.version_id, l.issue_id, r.report_id, r.crash_date, i.crash_type, r.config_changes,
+    r.low_memory, r.environment, r.received_at
+FROM report_issues l
+JOIN reports r ON r.id = l.report_id
+JOIN issues i ON i.id = l.issue_id;
@@ -0,0 +1,19 @@
+CREATE TABLE issues (
+    id          INTEGER NOT NULL PRIMARY KEY,
+    app_id      INTEGER NOT NULL REFERENCES apps(id),
+    fingerprint TEXT    NOT NULL,
+    title       TEXT    NOT NULL,
+    first_seen  TEXT    NOT NULL,
+    last_seen   TEXT    NOT NULL,
+    UNIQUE (app_id, fingerprint)
+);
+
+ALTER TABLE reports ADD COLUMN issue_id INTEGER REFERENCES issues(id);
+ALTER TABLE reports ADD COLUMN received_at TEXT NOT NULL DEFAULT '1970-01-01 00:00:00';
+
+CREATE TABLE notification_settings (
+    user_id   INTEGER NOT NULL PRIMARY KEY REFERENCES users(id),
+    email     TEXT    NOT NULL,
+    immediate INTEGER NOT NULL DEFAULT 0,
+    digest    INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+ALTER TABLE issues ADD COLUMN crash_type TEXT NOT NULL DEFAULT 'fatal';
+ALTER TABLE reports ADD COLUMN crash_type TEXT NOT NULL DEFAULT 'fatal';
@@ -0,0 +1,2 @@
+ALTER TABLE reports ADD COLUMN config_changes TEXT    NOT NULL DEFAULT '';
+ALTER TABLE reports ADD COLUMN low_memory     INTEGER NOT NULL DEFAULT 0;
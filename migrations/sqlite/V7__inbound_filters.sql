@@ -0,0 +1,7 @@
+CREATE TABLE inbound_filters (
+    app_id   INTEGER NOT NULL REFERENCES apps(id),
+    position INTEGER NOT NULL,
+    rule     TEXT    NOT NULL,
+    dropped  INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (app_id, rule)
+);
@@ -0,0 +1,24 @@
+-- The initial migration seeded the user `admin` and the app `Test` with well-known credentials.
+-- They're removed unless something was added to them, and their passwords replaced otherwise.
+-- Instances are set up with the `user add` and `app add` commands instead.
+DELETE FROM versions
+WHERE app_id IN (SELECT id FROM apps WHERE username = 'test' AND password = 'test')
+  AND id NOT IN (SELECT version_id FROM reports);
+
+DELETE FROM apps
+WHERE username = 'test' AND password = 'test'
+  AND id NOT IN (SELECT app_id FROM versions)
+  AND id NOT IN (SELECT app_id FROM issues)
+  AND id NOT IN (SELECT app_id FROM tag_keys)
+  AND id NOT IN (SELECT app_id FROM environment_rules)
+  AND id NOT IN (SELECT app_id FROM inbound_filters);
+
+DELETE FROM users
+WHERE username = 'admin' AND password = 'admin'
+  AND id NOT IN (SELECT user_id FROM apps)
+  AND id NOT IN (SELECT user_id FROM notification_settings);
+
+UPDATE apps SET password = lower(hex(randomblob(16)))
+WHERE username = 'test' AND password = 'test';
+UPDATE users SET password = lower(hex(randomblob(16)))
+WHERE username = 'admin' AND password = 'admin';
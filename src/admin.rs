@@ -0,0 +1,215 @@
+//! Administrative commands that operate directly on the database, without a running server.
+
+use std::{
+    io::{self, BufRead},
+    path::Path,
+};
+
+use anyhow::{Context, Result, ensure};
+use rand::{Rng, distr::Alphanumeric};
+
+use crate::{
+    backup,
+    cli::{AppCommand, BackupCommand, ReportsCommand, UserCommand},
+    db::{
+        DbConnPool,
+        models::{NewApp, NewUser, User},
+        repositories::{self, UserRepository},
+    },
+    dirs::DIRS,
+    filters::FilterCache,
+    ingest, settings,
+    storage::Storage,
+};
+
+/// Length of the generated app usernames.
+const APP_USERNAME_LEN: usize = 16;
+/// Length of the generated app passwords.
+const APP_PASSWORD_LEN: usize = 32;
+
+pub async fn user(pool: DbConnPool, command: UserCommand) -> Result<()> {
+    let repo = repositories::user_repo(pool);
+
+    match command {
+        UserCommand::Add { username, password } => {
+            let password = password_or_stdin(password)?;
+            let id = repo
+                .save(NewUser {
+                    username: username.clone(),
+                    password,
+                })
+                .await?;
+
+            println!("created user `{username}` with ID {id}");
+        }
+        UserCommand::Passwd { username, password } => {
+            let user = find_user(&*repo, username).await?;
+            let password = password_or_stdin(password)?;
+            repo.update_password(user.id, password).await?;
+
+            println!("changed password of user `{}`", user.username);
+        }
+        UserCommand::Delete {
+            username,
+            reassign_to,
+        } => {
+            let user = find_user(&*repo, username).await?;
+            let reassign_to = match reassign_to {
+                Some(username) => Some(find_user(&*repo, username).await?.id),
+                None => None,
+            };
+
+            ensure!(
+                reassign_to != Some(user.id),
+                "can't hand over apps to the deleted user itself"
+            );
+
+            repo.delete(user.id, reassign_to)
+                .await
+                .with_context(|| format!("failed deleting user `{}`", user.username))?;
+
+            println!("deleted user `{}`", user.username);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn app(pool: DbConnPool, command: AppCommand) -> Result<()> {
+    let repo = repositories::app_repo(pool.clone());
+
+    match command {
+        AppCommand::Add { name, owner } => {
+            let owner = find_user(&*repositories::user_repo(pool), owner).await?;
+            let (username, password) = generate_credentials();
+            let id = repo
+                .save(NewApp {
+                    user_id: owner.id,
+                    name: name.clone(),
+                    username: username.clone(),
+                    password: password.clone(),
+                })
+                .await
+                .with_context(|| format!("failed creating app `{name}`"))?;
+
+            println!("created app `{name}` with ID {id}");
+            print_credentials(&username, &password);
+        }
+        AppCommand::RotateCredentials { id } => {
+            let app = repo
+                .get(id)
+                .await
+                .with_context(|| format!("app with ID {id} not found"))?;
+            let (username, password) = generate_credentials();
+            repo.update_credentials(app.id, username.clone(), password.clone())
+                .await?;
+
+            println!("rotated credentials of app `{}`", app.name);
+            print_credentials(&username, &password);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn reports(
+    pool: DbConnPool,
+    storage: Storage,
+    in_app: &[String],
+    command: ReportsCommand,
+) -> Result<()> {
+    match command {
+        ReportsCommand::Import { dir, app } => {
+            if let Some(id) = app {
+                repositories::app_repo(pool.clone())
+                    .get(id)
+                    .await
+                    .with_context(|| format!("app with ID {id} not found"))?;
+            }
+
+            let summary =
+                ingest::import_dir(&pool, &storage, &dir, app, in_app, &FilterCache::default())
+                    .await?;
+
+            println!(
+                "imported {} reports, skipped {}, failed {}, filtered {}, removed {} empty issues",
+                summary.imported,
+                summary.skipped,
+                summary.failed,
+                summary.filtered,
+                summary.removed_issues
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn backup(
+    settings: Option<&settings::Backup>,
+    storage: &Storage,
+    command: BackupCommand,
+    open_db: impl FnOnce() -> Result<DbConnPool>,
+) -> Result<()> {
+    match command {
+        BackupCommand::Create { dir } => {
+            let dir = dir
+                .or_else(|| settings.map(|backup| backup.dir.clone()))
+                .context("no backup directory given and none configured in the settings")?;
+            let path =
+                backup::create(open_db()?, dir, storage.local_dir().map(Path::to_owned)).await?;
+
+            println!("created backup {}", path.display());
+        }
+        BackupCommand::Restore { archive } => {
+            backup::restore(&archive, DIRS.db_file().as_std_path(), storage.local_dir())?;
+            println!("restored backup {}", archive.display());
+        }
+    }
+
+    Ok(())
+}
+
+async fn find_user(repo: &(dyn UserRepository + Send + Sync), username: String) -> Result<User> {
+    repo.get_by_username(username.clone())
+        .await
+        .with_context(|| format!("user `{username}` not found"))
+}
+
+/// Use the given password, or read it as a single line from standard input instead, so it doesn't
+/// have to show up in the process list or shell history.
+fn password_or_stdin(password: Option<String>) -> Result<String> {
+    let password = if let Some(password) = password {
+        password
+    } else {
+        let mut line = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .context("failed reading password from standard input")?;
+        line.trim_end_matches(['\r', '\n']).to_owned()
+    };
+
+    ensure!(!password.is_empty(), "password must not be empty");
+    Ok(password)
+}
+
+fn generate_credentials() -> (String, String) {
+    (
+        random_string(APP_USERNAME_LEN).to_lowercase(),
+        random_string(APP_PASSWORD_LEN),
+    )
+}
+
+fn random_string(len: usize) -> String {
+    rand::rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn print_credentials(username: &str, password: &str) {
+    println!("username: {username}");
+    println!("password: {password}");
+}
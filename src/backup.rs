@@ -0,0 +1,391 @@
+//! Online backups of the database together with all raw reports, attachments and mapping files into
+//! a single archive, and restoring of those archives.
+//!
+//! Files are only included for the local storage. Objects in an S3 bucket are best backed up with
+//! the tools of the object storage itself, like bucket versioning or replication.
+
+use std::{
+    fs::{self, File},
+    io, iter,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, ensure};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use rusqlite::{Connection, DatabaseName};
+use time::{OffsetDateTime, format_description::FormatItem, macros::format_description};
+use tracing::{error, info, instrument, warn};
+
+use crate::{db::DbConnPool, settings};
+
+const ARCHIVE_PREFIX: &str = "acralite-backup-";
+const ARCHIVE_SUFFIX: &str = ".tar.gz";
+/// Format of the timestamp in archive names. It sorts in the same order as the creation time.
+const TIMESTAMP_FORMAT: &[FormatItem<'_>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+const DB_ENTRY: &str = "data.db";
+/// Directories and files of the local storage that are part of a backup.
+const STORAGE_ENTRIES: [&str; 5] = [
+    "reports",
+    "attachments",
+    "mapping.txt",
+    "mappings",
+    "symbols",
+];
+
+/// Create a new backup archive in the given directory, while the database stays fully usable.
+/// Files of the local storage in `storage_dir` are included, if given.
+///
+/// Returns the location of the created archive.
+#[instrument(skip_all)]
+pub async fn create(
+    pool: DbConnPool,
+    dir: PathBuf,
+    storage_dir: Option<PathBuf>,
+) -> Result<PathBuf> {
+    tokio::task::spawn_blocking(move || create_blocking(&pool, &dir, storage_dir.as_deref()))
+        .await?
+}
+
+fn create_blocking(pool: &DbConnPool, dir: &Path, storage_dir: Option<&Path>) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed creating backup directory {}", dir.display()))?;
+
+    let timestamp = OffsetDateTime::now_utc().format(TIMESTAMP_FORMAT)?;
+    let path = dir.join(format!("{ARCHIVE_PREFIX}{timestamp}{ARCHIVE_SUFFIX}"));
+    let snapshot = dir.join(format!(".{timestamp}.db"));
+
+    pool.as_sqlite()
+        .context("backups are only supported for SQLite, use pg_dump for PostgreSQL")?
+        .get()?
+        .backup(DatabaseName::Main, &snapshot, None)
+        .context("failed creating database snapshot")?;
+
+    let result = write_archive(&path, &snapshot, storage_dir);
+    fs::remove_file(&snapshot).ok();
+
+    if result.is_err() {
+        fs::remove_file(&path).ok();
+    }
+
+    result.map(|()| path)
+}
+
+fn write_archive(path: &Path, snapshot: &Path, storage_dir: Option<&Path>) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("failed creating archive {}", path.display()))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    archive.append_path_with_name(snapshot, DB_ENTRY)?;
+
+    if let Some(dir) = storage_dir {
+        for name in STORAGE_ENTRIES {
+            let path = dir.join(name);
+
+            if path.is_dir() {
+                archive.append_dir_all(name, path)?;
+            } else if path.is_file() {
+                archive.append_path_with_name(path, name)?;
+            }
+        }
+    }
+
+    archive.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+/// Delete the oldest backup archives in the given directory, so that only `keep` remain.
+fn prune(dir: &Path, keep: usize) -> Result<()> {
+    let mut archives = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+
+            (name.starts_with(ARCHIVE_PREFIX) && name.ends_with(ARCHIVE_SUFFIX)).then_some(path)
+        })
+        .collect::<Vec<_>>();
+
+    archives.sort_unstable();
+
+    for path in archives.iter().rev().skip(keep) {
+        info!(path = %path.display(), "deleting old backup");
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Start a background task that periodically creates backups and cleans up old ones.
+pub fn spawn_scheduled(pool: DbConnPool, settings: settings::Backup, storage_dir: Option<PathBuf>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_hours(settings.interval_hours));
+        // Don't create a backup right at startup, which could quickly pile up archives if the
+        // server is restarted repeatedly.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            match create(pool.clone(), settings.dir.clone(), storage_dir.clone()).await {
+                Ok(path) => info!(path = %path.display(), "created backup"),
+                Err(e) => {
+                    error!("failed creating backup: {e:?}");
+                    continue;
+                }
+            }
+
+            if let Err(e) = prune(&settings.dir, settings.keep) {
+                warn!("failed deleting old backups: {e:?}");
+            }
+        }
+    });
+}
+
+/// Replace the database at `db_file` and the files of the local storage in `storage_dir`, if
+/// given, with the content of a backup archive. The server must not be running while restoring.
+///
+/// The archive is fully extracted and its database checked for compatibility, before any of the
+/// current data is touched. Files are extracted next to their final location, so they can be moved
+/// in place even if the storage is on a different file system than the database.
+pub fn restore(archive: &Path, db_file: &Path, storage_dir: Option<&Path>) -> Result<()> {
+    let staging = db_file.with_file_name(".restore");
+    let storage_staging = storage_dir.map(|dir| dir.join(".restore"));
+    let staging_dirs = iter::once(staging.as_path()).chain(storage_staging.as_deref());
+
+    for dir in staging_dirs.clone() {
+        remove_dir(dir)?;
+        fs::create_dir_all(dir)?;
+    }
+
+    let result = unpack(archive, &staging, storage_staging.as_deref()).and_then(|()| {
+        replace_data(
+            &staging,
+            db_file,
+            storage_dir.zip(storage_staging.as_deref()),
+        )
+    });
+
+    for dir in staging_dirs {
+        remove_dir(dir).ok();
+    }
+
+    result
+}
+
+fn unpack(archive: &Path, staging: &Path, storage_staging: Option<&Path>) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("failed opening archive {}", archive.display()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    for entry in archive.entries().context("failed reading archive")? {
+        let mut entry = entry.context("failed reading archive")?;
+        let target = if entry.path()?.as_ref() == Path::new(DB_ENTRY) {
+            staging
+        } else if let Some(storage_staging) = storage_staging {
+            storage_staging
+        } else {
+            continue;
+        };
+
+        entry
+            .unpack_in(target)
+            .context("failed extracting archive")?;
+    }
+
+    let db = staging.join(DB_ENTRY);
+    ensure!(db.is_file(), "archive doesn't contain a database");
+
+    let mut conn = Connection::open(&db)?;
+    crate::db::verify_migrations(&mut conn).context("incompatible database in archive")?;
+
+    Ok(())
+}
+
+fn replace_data(staging: &Path, db_file: &Path, storage: Option<(&Path, &Path)>) -> Result<()> {
+    // Leftovers of the write-ahead log belong to the old database and would corrupt the new one.
+    for suffix in ["-wal", "-shm"] {
+        let mut path = db_file.as_os_str().to_owned();
+        path.push(suffix);
+        remove_file(Path::new(&path))?;
+    }
+    fs::rename(staging.join(DB_ENTRY), db_file)?;
+
+    let Some((dir, staging)) = storage else {
+        return Ok(());
+    };
+
+    for name in STORAGE_ENTRIES {
+        let path = dir.join(name);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            remove_file(&path)?;
+        }
+
+        if staging.join(name).exists() {
+            fs::rename(staging.join(name), path)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn remove_dir(path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use rusqlite::Connection;
+
+    use super::{create, prune, restore};
+    use crate::{
+        db::{DbConnPool, models::NewApp, repositories},
+        fixtures,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("acralite-backup-{name}-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn pool() -> DbConnPool {
+        let pool = fixtures::memory_pool();
+        crate::db::run_migrations(&pool).unwrap();
+        fixtures::test_app(&pool).await;
+        pool
+    }
+
+    fn app_names(db_file: &Path) -> Vec<String> {
+        Connection::open(db_file)
+            .unwrap()
+            .prepare("SELECT name FROM apps ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn create_and_restore() {
+        let dir = temp_dir("restore");
+        let storage = dir.join("storage");
+        fs::create_dir_all(storage.join("reports")).unwrap();
+        fs::write(storage.join("reports/kept.json"), "{}").unwrap();
+        fs::write(storage.join("mapping.txt"), "backed up").unwrap();
+
+        let pool = pool().await;
+        let archive = create(pool.clone(), dir.join("backups"), Some(storage.clone()))
+            .await
+            .unwrap();
+
+        // Everything that changed after the backup is undone by restoring it.
+        repositories::app_repo(pool.clone())
+            .save(NewApp {
+                user_id: 1,
+                name: "Later".to_owned(),
+                username: "later".to_owned(),
+                password: "later".to_owned(),
+            })
+            .await
+            .unwrap();
+        fs::remove_file(storage.join("reports/kept.json")).unwrap();
+        fs::write(storage.join("reports/later.json"), "{}").unwrap();
+        fs::write(storage.join("mapping.txt"), "changed").unwrap();
+
+        let db_file = dir.join("acralite.db");
+        restore(&archive, &db_file, Some(&storage)).unwrap();
+        assert_eq!(vec!["Test"], app_names(&db_file));
+        assert!(storage.join("reports/kept.json").is_file());
+        assert!(!storage.join("reports/later.json").exists());
+        assert_eq!(
+            "backed up",
+            fs::read_to_string(storage.join("mapping.txt")).unwrap()
+        );
+        assert!(!dir.join(".restore").exists() && !storage.join(".restore").exists());
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn reject_newer_database() {
+        let dir = temp_dir("newer");
+        let pool = pool().await;
+        pool.as_sqlite()
+            .unwrap()
+            .get()
+            .unwrap()
+            .execute(
+                "INSERT INTO refinery_schema_history (version, name, applied_on, checksum)
+                VALUES (999, 'future', '2030-01-01T00:00:00Z', '0')",
+                [],
+            )
+            .unwrap();
+        let archive = create(pool, dir.join("backups"), None).await.unwrap();
+
+        let db_file = dir.join("acralite.db");
+        fs::write(&db_file, "current").unwrap();
+        let error = restore(&archive, &db_file, None).unwrap_err();
+        assert!(
+            format!("{error:#}").contains("created by a newer version"),
+            "{error:#}"
+        );
+        // The current database is left alone.
+        assert_eq!("current", fs::read_to_string(&db_file).unwrap());
+
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn prune_oldest() {
+        let dir = temp_dir("prune");
+        let names = [
+            "acralite-backup-20250218T060000Z.tar.gz",
+            "acralite-backup-20250220T060000Z.tar.gz",
+            "acralite-backup-20250219T060000Z.tar.gz",
+            "notes.tar.gz",
+        ];
+        for name in names {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        prune(&dir, 2).unwrap();
+
+        let mut left = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(
+            vec![
+                "acralite-backup-20250219T060000Z.tar.gz",
+                "acralite-backup-20250220T060000Z.tar.gz",
+                "notes.tar.gz",
+            ],
+            left
+        );
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
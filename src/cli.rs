@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+
+/// Lightweight backend for ACRA crash reports.
+///
+/// Settings are merged from built-in defaults, the settings file, `ACRALITE_*` environment
+/// variables and `--set` flags, with later sources overriding earlier ones. Nested keys are
+/// separated by `__` in environment variables, like `ACRALITE_SMTP__HOST` for `smtp.host`.
+#[derive(Parser)]
+#[command(about, version)]
+pub struct Cli {
+    /// Location of the settings file, instead of the platform specific default.
+    #[arg(short, long, global = true, env = "ACRALITE_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Override a single setting, like `smtp.host=mail.example.com`. Can be repeated.
+    #[arg(
+        short = 's',
+        long = "set",
+        global = true,
+        value_name = "KEY=VALUE",
+        value_parser = parse_key_value
+    )]
+    pub overrides: Vec<(String, String)>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the server. This is the default if no command is given.
+    Serve,
+    /// Inspect the settings.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Apply all pending database migrations and exit.
+    Migrate,
+    /// Manage users.
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+    /// Manage apps.
+    App {
+        #[command(subcommand)]
+        command: AppCommand,
+    },
+    /// Manage reports.
+    Reports {
+        #[command(subcommand)]
+        command: ReportsCommand,
+    },
+    /// Back up and restore all data.
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Validate the merged settings and print the effective values, with secrets masked.
+    Check,
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Create a new user.
+    Add {
+        username: String,
+        /// Password of the user. Read from standard input if not given.
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Change the password of an existing user.
+    Passwd {
+        username: String,
+        /// New password of the user. Read from standard input if not given.
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Delete a user. Fails if the user still owns any apps.
+    Delete {
+        username: String,
+        /// Hand over all apps of the user to this user, before deleting it.
+        #[arg(long, value_name = "USERNAME")]
+        reassign_to: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AppCommand {
+    /// Create a new app with freshly generated credentials.
+    Add {
+        name: String,
+        /// User that owns the new app.
+        #[arg(long, value_name = "USERNAME")]
+        owner: String,
+    },
+    /// Replace the credentials of an app with freshly generated ones.
+    RotateCredentials {
+        /// ID of the app.
+        id: i64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReportsCommand {
+    /// Feed all JSON files of a directory through the ingestion pipeline.
+    ///
+    /// Files can either contain raw reports, as kept in the reports directory, or reports exported
+    /// from other ACRA backends like Acrarium. Reports that were received before are grouped again.
+    Import {
+        dir: PathBuf,
+        /// ID of the app that reports are assigned to, if they weren't received before.
+        #[arg(long, value_name = "ID")]
+        app: Option<i64>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BackupCommand {
+    /// Create an archive of the database, raw reports and mapping files. This is safe to run
+    /// while the server is running.
+    Create {
+        /// Directory to place the archive in. Defaults to the directory of the scheduled backups,
+        /// if configured.
+        dir: Option<PathBuf>,
+    },
+    /// Replace all current data with the content of a backup archive. The server must be stopped
+    /// while restoring.
+    Restore { archive: PathBuf },
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String)> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.trim().to_owned(), value.to_owned()))
+        .context("expected the format KEY=VALUE")
+}
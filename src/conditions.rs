@@ -0,0 +1,321 @@
+//! Conditions of the device at the time of a crash: configuration changes since the app started,
+//! and how much memory was left.
+//!
+//! Crashes right after a configuration change usually come from state that didn't survive the
+//! recreation of an activity, while crashes on devices that ran low on memory are often caused
+//! by the system rather than the app.
+
+use crate::report::{Configuration, Orientation, Report, UiMode};
+
+/// Share of available memory in percent, below which a device counts as low on memory.
+const LOW_MEMORY_PERCENT: u64 = 10;
+
+/// A setting of the configuration that can change while the app runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigChange {
+    Orientation,
+    Locale,
+    NightMode,
+    ScreenSize,
+    FontScale,
+}
+
+impl ConfigChange {
+    pub const ALL: [Self; 5] = [
+        Self::Orientation,
+        Self::Locale,
+        Self::NightMode,
+        Self::ScreenSize,
+        Self::FontScale,
+    ];
+
+    /// Name as stored in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Orientation => "orientation",
+            Self::Locale => "locale",
+            Self::NightMode => "night_mode",
+            Self::ScreenSize => "screen_size",
+            Self::FontScale => "font_scale",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|change| change.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Orientation => "Orientation",
+            Self::Locale => "Locale",
+            Self::NightMode => "Night mode",
+            Self::ScreenSize => "Screen size",
+            Self::FontScale => "Font scale",
+        }
+    }
+
+    /// The setting in the given configuration, in a readable form.
+    fn value(self, config: &Configuration) -> String {
+        match self {
+            Self::Orientation => match config.orientation {
+                Orientation::Landscape => "Landscape".to_owned(),
+                Orientation::Portrait => "Portrait".to_owned(),
+            },
+            Self::Locale => config.locale.clone(),
+            Self::NightMode => if config.ui_mode.contains(UiMode::NIGHT_YES) {
+                "Night"
+            } else {
+                "Day"
+            }
+            .to_owned(),
+            Self::ScreenSize => {
+                format!("{}x{} dp", config.screen_width_dp, config.screen_height_dp)
+            }
+            Self::FontScale => config.font_scale.to_string(),
+        }
+    }
+}
+
+/// A setting that differs between the start of the app and the crash.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Change {
+    pub setting: ConfigChange,
+    pub initial: String,
+    pub crash: String,
+}
+
+/// All settings that changed between the initial and the crash configuration of a report.
+pub fn config_changes(report: &Report) -> Vec<Change> {
+    ConfigChange::ALL
+        .into_iter()
+        .filter_map(|setting| {
+            let initial = setting.value(&report.initial_configuration);
+            let crash = setting.value(&report.crash_configuration);
+
+            (initial != crash).then_some(Change {
+                setting,
+                initial,
+                crash,
+            })
+        })
+        .collect()
+}
+
+/// Memory of the device, as estimated by ACRA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Memory {
+    pub total: u64,
+    pub available: u64,
+}
+
+impl Memory {
+    pub fn of(report: &Report) -> Self {
+        Self {
+            total: report.total_mem_size,
+            available: report.available_mem_size,
+        }
+    }
+
+    /// Available memory in percent of the total.
+    pub fn available_percent(self) -> u64 {
+        (self.available.saturating_mul(100))
+            .checked_div(self.total)
+            .unwrap_or_default()
+    }
+
+    pub fn is_low(self) -> bool {
+        self.total > 0 && self.available_percent() < LOW_MEMORY_PERCENT
+    }
+}
+
+/// Format a byte count with a decimal unit, like `61.8 GB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+
+    // Tenths of the unit, to get a single decimal without going through floats.
+    let mut tenths = bytes / 100;
+    let mut unit = 0;
+    while tenths >= 10_000 && unit < UNITS.len() - 1 {
+        tenths /= 1000;
+        unit += 1;
+    }
+
+    format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+}
+
+/// The table of an app process in the output of `dumpsys meminfo`, with values in kilobytes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    pub pid: Option<u32>,
+    pub process: Option<String>,
+    /// Column names, like `Pss Total`.
+    pub columns: Vec<String>,
+    pub rows: Vec<MemRow>,
+}
+
+/// A single row of the meminfo table. Values are missing for columns that don't apply.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MemRow {
+    pub name: String,
+    pub values: Vec<Option<u64>>,
+}
+
+impl MemInfo {
+    /// Parse the output of `dumpsys meminfo <pid>`, or `None` if it doesn't contain a table.
+    pub fn parse(text: &str) -> Option<Self> {
+        let lines = text.lines().map(str::trim).collect::<Vec<_>>();
+        let mut info = Self::default();
+
+        if let Some(rest) = lines
+            .iter()
+            .find_map(|line| line.strip_prefix("** MEMINFO in pid "))
+        {
+            let rest = rest.trim_end_matches(" **");
+            let (pid, process) = rest.split_once(' ').unwrap_or((rest, ""));
+            info.pid = pid.parse().ok();
+            info.process = Some(process.trim_matches(['[', ']']).to_owned())
+                .filter(|process| !process.is_empty());
+        }
+
+        // Column names are split over two header lines, followed by a line of dashes.
+        let header = lines.iter().position(|line| line.starts_with("Pss"))?;
+        let first = lines[header].split_whitespace();
+        let second = lines.get(header + 1)?.split_whitespace();
+        info.columns = first
+            .zip(second)
+            .map(|(first, second)| format!("{first} {second}"))
+            .collect();
+
+        for line in lines[header + 2..]
+            .iter()
+            .skip_while(|line| line.starts_with('-'))
+        {
+            // The table ends at the first empty line or the next section.
+            let Some(row) = parse_row(line, info.columns.len()) else {
+                break;
+            };
+            info.rows.push(row);
+        }
+
+        (!info.rows.is_empty()).then_some(info)
+    }
+
+    /// Value of the given row and column, like `Dalvik Heap` and `Heap Alloc`.
+    pub fn value(&self, row: &str, column: &str) -> Option<u64> {
+        let column = self.columns.iter().position(|c| c == column)?;
+        self.rows
+            .iter()
+            .find(|r| r.name == row)
+            .and_then(|r| r.values.get(column).copied().flatten())
+    }
+
+    /// Used share of the Java heap in percent, which is close to 100 before running out of it.
+    pub fn java_heap_percent(&self) -> Option<u64> {
+        let size = self.value("Dalvik Heap", "Heap Size")?;
+        let alloc = self.value("Dalvik Heap", "Heap Alloc")?;
+
+        (alloc * 100).checked_div(size)
+    }
+}
+
+/// Parse a row like `Native Heap    14231    14148        0`, with the name on the left.
+fn parse_row(line: &str, columns: usize) -> Option<MemRow> {
+    let split = line.find(|c: char| c.is_ascii_digit())?;
+    let name = line[..split].trim();
+    if name.is_empty() || name.ends_with(':') {
+        return None;
+    }
+
+    let mut values = line[split..]
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Vec<_>>();
+    values.resize(columns, None);
+
+    Some(MemRow {
+        name: name.to_owned(),
+        values,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigChange, MemInfo, Memory, config_changes, format_size};
+    use crate::{
+        fixtures::{GALAXY_TAB, report},
+        report::Report,
+    };
+
+    #[test]
+    fn night_mode_change() {
+        let report = report(|_| {});
+        let changes = config_changes(&report);
+
+        assert_eq!(1, changes.len());
+        assert_eq!(ConfigChange::NightMode, changes[0].setting);
+        assert_eq!(
+            ("Day", "Night"),
+            (changes[0].initial.as_str(), changes[0].crash.as_str())
+        );
+
+        let report = serde_json::from_str::<Report>(GALAXY_TAB).unwrap();
+        assert!(config_changes(&report).is_empty());
+    }
+
+    #[test]
+    fn memory() {
+        let report = report(|_| {});
+        let memory = Memory::of(&report);
+
+        assert_eq!(53, memory.available_percent());
+        assert!(!memory.is_low());
+        assert!(
+            Memory {
+                total: 100,
+                available: 9
+            }
+            .is_low()
+        );
+        assert!(
+            !Memory {
+                total: 0,
+                available: 0
+            }
+            .is_low()
+        );
+
+        assert_eq!("999 B", format_size(999));
+        assert_eq!("1.5 kB", format_size(1500));
+        assert_eq!("61.8 GB", format_size(memory.available));
+        assert_eq!("1234.5 TB", format_size(1_234_500_000_000_000));
+    }
+
+    #[test]
+    fn parse_meminfo() {
+        let report = report(|_| {});
+        let info = MemInfo::parse(report.dumpsys_meminfo.as_deref().unwrap()).unwrap();
+
+        assert_eq!(Some(12345), info.pid);
+        assert_eq!(Some("com.example.notes"), info.process.as_deref());
+        assert_eq!(8, info.columns.len());
+        assert_eq!("Pss Total", info.columns[0]);
+        assert_eq!("Heap Free", info.columns[7]);
+        assert_eq!(3, info.rows.len());
+        assert_eq!(Some(51234), info.value("TOTAL", "Pss Total"));
+        assert_eq!(Some(6406), info.value("Dalvik Heap", "Heap Alloc"));
+        assert_eq!(Some(50), info.java_heap_percent());
+
+        let info = MemInfo::parse(
+            "** MEMINFO in pid 7 [app] **\n   Pss  Heap\n Total  Size\n ----  ----\n  Stack   \
+             60\n\n App Summary\n",
+        )
+        .unwrap();
+        assert_eq!(vec![Some(60), None], info.rows[0].values);
+        assert_eq!(None, info.java_heap_percent());
+        assert_eq!(None, MemInfo::parse("dumpsys failed"));
+    }
+}
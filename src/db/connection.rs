@@ -3,18 +3,62 @@
 use std::{
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{Context, Result};
 use r2d2::{ManageConnection, Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
+use tracing::{error, info};
 
-use crate::dirs::DIRS;
+use crate::{dirs::DIRS, settings};
 
-pub struct DbConnPool(Arc<Pool<SqliteConnectionManager>>);
+/// Time a connection waits for a lock held by another connection, before failing with
+/// `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval in which the query planner statistics are refreshed and free pages are released.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_hours(6);
+
+/// Connection pool for one of the supported database backends.
+#[derive(Clone)]
+pub enum DbConnPool {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(super::postgres::PgPool),
+}
 
 impl DbConnPool {
+    pub fn state(&self) -> r2d2::State {
+        match self {
+            Self::Sqlite(pool) => pool.0.state(),
+            #[cfg(feature = "postgres")]
+            Self::Postgres(pool) => pool.state(),
+        }
+    }
+
+    pub fn max_size(&self) -> u32 {
+        match self {
+            Self::Sqlite(pool) => pool.0.max_size(),
+            #[cfg(feature = "postgres")]
+            Self::Postgres(pool) => pool.max_size(),
+        }
+    }
+
+    /// Get the underlying SQLite pool, for features that are specific to it.
+    #[cfg_attr(not(feature = "postgres"), allow(clippy::unnecessary_wraps))]
+    pub fn as_sqlite(&self) -> Option<&SqlitePool> {
+        match self {
+            Self::Sqlite(pool) => Some(pool),
+            #[cfg(feature = "postgres")]
+            Self::Postgres(_) => None,
+        }
+    }
+}
+
+pub struct SqlitePool(Arc<Pool<SqliteConnectionManager>>);
+
+impl SqlitePool {
     pub fn get(&self) -> Result<DbConn, r2d2::Error> {
         self.0.get().map(DbConn)
     }
@@ -36,7 +80,7 @@ impl DbConnPool {
     }
 }
 
-impl Clone for DbConnPool {
+impl Clone for SqlitePool {
     fn clone(&self) -> Self {
         Self(Arc::clone(&self.0))
     }
@@ -58,25 +102,223 @@ impl DerefMut for DbConn {
     }
 }
 
-pub fn create_pool() -> Result<DbConnPool> {
+pub fn create_pool(settings: &settings::Database) -> Result<DbConnPool> {
+    #[cfg(feature = "postgres")]
+    if let Some(url) = &settings.url {
+        return super::postgres::create_pool(url, settings).map(DbConnPool::Postgres);
+    }
+    #[cfg(not(feature = "postgres"))]
+    anyhow::ensure!(
+        settings.url.is_none(),
+        "PostgreSQL support is not enabled in this build"
+    );
+
     let manager = if cfg!(test) {
         SqliteConnectionManager::memory()
     } else {
         std::fs::create_dir_all(DIRS.data_dir())?;
         SqliteConnectionManager::file(DIRS.db_file())
-    }
-    .with_init(init_connection);
+    };
+
+    build_pool(manager, settings).map(DbConnPool::Sqlite)
+}
+
+pub(super) fn build_pool(
+    manager: SqliteConnectionManager,
+    settings: &settings::Database,
+) -> Result<SqlitePool> {
+    let cache_size = settings.cache_size;
+    let manager = manager.with_init(move |conn| init_connection(conn, cache_size));
 
     // First create a single connection to make sure all eventually locking PRAGMAs are run,
     // so we don't get any errors when spinning up the pool.
-    manager.connect().context("failed to initialize database")?;
+    let mut conn = manager.connect().context("failed to initialize database")?;
+    init_database(&mut conn).context("failed to initialize database")?;
 
-    let pool = Pool::builder().build(manager)?;
+    let pool = Pool::builder()
+        .max_size(settings.pool_size)
+        .build(manager)?;
 
-    Ok(DbConnPool(Arc::new(pool)))
+    Ok(SqlitePool(Arc::new(pool)))
 }
 
-fn init_connection(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+/// Settings that are persisted in the database file, and only need to be applied once.
+fn init_database(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    // Readers don't block writers in WAL mode, and writes are much cheaper as they only append to
+    // the log.
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+    let auto_vacuum = conn.pragma_query_value(None, "auto_vacuum", |row| row.get::<_, u8>(0))?;
+    if auto_vacuum != AUTO_VACUUM_INCREMENTAL {
+        // Switching the mode of an existing database only takes effect after a full vacuum.
+        info!("enabling incremental vacuum, this may take a while for large databases");
+        conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        conn.execute_batch("VACUUM")?;
+    }
+
+    Ok(())
+}
+
+/// Value of the `auto_vacuum` PRAGMA for incremental mode.
+const AUTO_VACUUM_INCREMENTAL: u8 = 2;
+
+fn init_connection(conn: &mut Connection, cache_size: u32) -> Result<(), rusqlite::Error> {
     conn.pragma_update(None, "foreign_keys", "ON")?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // Only a power loss can lose the most recent transactions in WAL mode, but never corrupt the
+    // database.
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    // Negative values are the size in KiB, instead of the amount of pages.
+    conn.pragma_update(None, "cache_size", -i64::from(cache_size))?;
     Ok(())
 }
+
+/// Start a background task that periodically keeps the database in shape, by refreshing the
+/// statistics of the query planner and releasing unused pages back to the file system.
+///
+/// Only SQLite needs this, other databases take care of it on their own.
+pub fn spawn_maintenance(pool: &DbConnPool) {
+    let Some(pool) = pool.as_sqlite().cloned() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MAINTENANCE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let result = pool
+                .run(|conn| {
+                    conn.execute_batch("PRAGMA optimize; PRAGMA incremental_vacuum;")
+                        .map_err(anyhow::Error::from)
+                })
+                .await;
+
+            if let Err(e) = result {
+                error!("failed running database maintenance: {e:?}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use r2d2::Pool;
+    use r2d2_sqlite::SqliteConnectionManager;
+    use tokio::task::JoinSet;
+
+    use super::{DbConnPool, SqlitePool};
+    use crate::{
+        db::{
+            models::{CrashType, NewIssue, NewReport, NewVersion},
+            repositories,
+        },
+        settings,
+    };
+
+    const REPORTS: usize = 2000;
+    const CONCURRENCY: usize = 32;
+
+    /// Compare the throughput of concurrently ingested reports, between the previous connection
+    /// setup with only foreign keys enabled, and the current tuned one. Run it with:
+    ///
+    /// ```sh
+    /// cargo test --release -- --ignored --nocapture load_test
+    /// ```
+    #[tokio::test]
+    #[ignore = "load test that takes a while, only run on demand"]
+    async fn load_test() {
+        let dir = std::env::temp_dir().join(format!("acralite-load-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let baseline = {
+            let path = dir.join("baseline.db");
+            let manager = SqliteConnectionManager::file(&path)
+                .with_init(|conn| conn.pragma_update(None, "foreign_keys", "ON"));
+            DbConnPool::Sqlite(SqlitePool(Arc::new(
+                Pool::builder().build(manager).unwrap(),
+            )))
+        };
+        let tuned = super::build_pool(
+            SqliteConnectionManager::file(dir.join("tuned.db")),
+            &settings::Database::default(),
+        )
+        .map(DbConnPool::Sqlite)
+        .unwrap();
+
+        for (name, pool) in [("baseline", baseline), ("tuned", tuned)] {
+            let (elapsed, failed) = ingest(pool).await;
+            #[allow(clippy::cast_precision_loss)]
+            let throughput = (REPORTS - failed) as f64 / elapsed.as_secs_f64();
+
+            println!("{name}: {throughput:.0} reports/s, {failed} failed, took {elapsed:?}");
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    async fn ingest(pool: DbConnPool) -> (Duration, usize) {
+        crate::db::run_migrations(&pool).unwrap();
+        crate::fixtures::test_app(&pool).await;
+
+        let start = Instant::now();
+        let mut tasks = JoinSet::new();
+
+        for task in 0..CONCURRENCY {
+            let pool = pool.clone();
+            tasks.spawn(async move {
+                let mut failed = 0;
+
+                for i in 0..REPORTS / CONCURRENCY {
+                    if ingest_one(&pool, task * REPORTS + i).await.is_err() {
+                        failed += 1;
+                    }
+                }
+
+                failed
+            });
+        }
+
+        let failed = tasks.join_all().await.into_iter().sum();
+
+        (start.elapsed(), failed)
+    }
+
+    async fn ingest_one(pool: &DbConnPool, n: usize) -> anyhow::Result<()> {
+        let version_id = repositories::version_repo(pool.clone())
+            .get_or_create(NewVersion {
+                app_id: 1,
+                name: format!("1.0.{}", n % 5),
+                code: i64::try_from(n % 5)?,
+            })
+            .await?;
+        let (issue_id, _) = repositories::issue_repo(pool.clone())
+            .get_or_create(NewIssue {
+                app_id: 1,
+                fingerprint: format!("{:016x}", n % 50),
+                title: format!("java.lang.IllegalStateException: {}", n % 50),
+                crash_type: CrashType::Fatal,
+            })
+            .await?;
+        repositories::report_repo(pool.clone())
+            .save(NewReport {
+                version_id,
+                issue_id,
+                report_id: format!("report-{n}"),
+                crash_date: "2025-02-20T12:00:00.000+01:00".to_owned(),
+                crash_type: CrashType::Fatal,
+                config_changes: Vec::new(),
+                low_memory: false,
+                environment: "production".to_owned(),
+            })
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,12 +1,99 @@
-use anyhow::Result;
+use anyhow::{Context, Result, ensure};
+use rusqlite::Connection;
 
 use super::DbConnPool;
 
 mod embedded {
-    refinery::embed_migrations!("migrations");
+    refinery::embed_migrations!("migrations/sqlite");
+}
+
+#[cfg(feature = "postgres")]
+mod embedded_postgres {
+    refinery::embed_migrations!("migrations/postgres");
 }
 
 pub fn run(pool: &DbConnPool) -> Result<()> {
-    embedded::migrations::runner().run(&mut *pool.get()?)?;
+    match pool {
+        DbConnPool::Sqlite(pool) => {
+            embedded::migrations::runner().run(&mut *pool.get()?)?;
+        }
+        #[cfg(feature = "postgres")]
+        DbConnPool::Postgres(pool) => {
+            super::postgres::unblock(|| -> Result<()> {
+                embedded_postgres::migrations::runner().run(&mut *pool.get()?)?;
+                Ok(())
+            })?;
+        }
+    }
+
     Ok(())
 }
+
+/// Make sure all migrations that were applied to the given SQLite database are known to this build,
+/// so it can be migrated further to the current schema.
+pub fn verify(conn: &mut Connection) -> Result<()> {
+    let runner = embedded::migrations::runner();
+    let applied = runner
+        .get_applied_migrations(conn)
+        .context("failed reading the migration history")?;
+
+    for migration in applied {
+        let known = runner
+            .get_migrations()
+            .iter()
+            .find(|known| known.version() == migration.version())
+            .with_context(|| {
+                format!(
+                    "unknown migration {migration}, the database was created by a newer version"
+                )
+            })?;
+
+        ensure!(
+            known.checksum() == migration.checksum(),
+            "migration {migration} differs from the one known to this version"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use refinery::Target;
+    use rusqlite::Connection;
+
+    use super::embedded;
+
+    #[test]
+    fn remove_seed_data() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        embedded::migrations::runner()
+            .set_target(Target::Version(7))
+            .run(&mut conn)
+            .unwrap();
+        // The seeded app was taken into use, so it must stay.
+        conn.execute_batch(
+            "INSERT INTO reports (version_id, report_id, crash_date) VALUES (1, 'a', 'today')",
+        )
+        .unwrap();
+
+        embedded::migrations::runner().run(&mut conn).unwrap();
+
+        let password = conn
+            .query_row(
+                "SELECT password FROM apps WHERE username = 'test'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .unwrap();
+        assert_eq!(32, password.len());
+        let password = conn
+            .query_row(
+                "SELECT password FROM users WHERE username = 'admin'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .unwrap();
+        assert_ne!("admin", password);
+    }
+}
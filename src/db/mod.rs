@@ -1,8 +1,8 @@
 #![allow(clippy::module_name_repetitions)]
 
 pub use self::{
-    connection::{DbConnPool, create_pool},
-    migrations::run as run_migrations,
+    connection::{DbConnPool, SqlitePool, create_pool, spawn_maintenance},
+    migrations::{run as run_migrations, verify as verify_migrations},
 };
 
 pub mod models;
@@ -10,3 +10,5 @@ pub mod repositories;
 
 mod connection;
 mod migrations;
+#[cfg(feature = "postgres")]
+mod postgres;
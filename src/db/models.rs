@@ -1,3 +1,7 @@
+use std::str::FromStr;
+
+use crate::conditions::ConfigChange;
+
 #[derive(Debug)]
 pub struct User {
     pub id: i64,
@@ -42,6 +46,264 @@ pub struct NewVersion {
 
 pub struct NewReport {
     pub version_id: i64,
+    pub issue_id: i64,
     pub report_id: String,
     pub crash_date: String,
+    pub crash_type: CrashType,
+    /// Settings that changed between the start of the app and the crash.
+    pub config_changes: Vec<ConfigChange>,
+    pub low_memory: bool,
+    /// Environment the report was sent from, like `production`.
+    pub environment: String,
+}
+
+/// A single received report, as listed for its issue.
+#[derive(Debug)]
+pub struct ReportEntry {
+    pub report_id: String,
+    pub version_name: String,
+    pub version_code: i64,
+    pub crash_date: String,
+    pub received_at: String,
+}
+
+#[derive(Debug)]
+pub struct Issue {
+    pub id: i64,
+    pub app_id: i64,
+    pub fingerprint: String,
+    pub title: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub crash_type: CrashType,
+}
+
+pub struct NewIssue {
+    pub app_id: i64,
+    pub fingerprint: String,
+    pub title: String,
+    pub crash_type: CrashType,
+}
+
+/// Kind of failure that a report is about. Each issue only groups reports of a single type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrashType {
+    /// Uncaught exception that terminated the app.
+    #[default]
+    Fatal,
+    /// Handled exception, sent with `ACRA.errorReporter.handleSilentException`.
+    NonFatal,
+    /// The app stopped responding, as recorded by the system.
+    Anr,
+    /// Crash in native code, as recorded by the system.
+    Native,
+}
+
+impl CrashType {
+    pub const ALL: [Self; 4] = [Self::Fatal, Self::NonFatal, Self::Anr, Self::Native];
+
+    /// Name as stored in the database and used in URLs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fatal => "fatal",
+            Self::NonFatal => "non_fatal",
+            Self::Anr => "anr",
+            Self::Native => "native",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Fatal => "Crash",
+            Self::NonFatal => "Non-fatal",
+            Self::Anr => "ANR",
+            Self::Native => "Native crash",
+        }
+    }
+}
+
+impl FromStr for CrashType {
+    type Err = UnknownCrashType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|ty| ty.as_str() == s)
+            .ok_or_else(|| UnknownCrashType(s.to_owned()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown crash type `{0}`")]
+pub struct UnknownCrashType(String);
+
+/// How many reports of an issue were sent under certain conditions of the device.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IssueConditions {
+    pub reports: u64,
+    pub low_memory: u64,
+    /// Report counts per changed setting, for all settings that changed at least once.
+    pub config_changes: Vec<(ConfigChange, u64)>,
+}
+
+impl IssueConditions {
+    /// Sum up report counts, grouped by their changed settings as stored in the database, and
+    /// whether the device was low on memory.
+    pub fn from_groups(groups: impl IntoIterator<Item = (String, bool, u64)>) -> Self {
+        let mut conditions = Self::default();
+        let mut changes = ConfigChange::ALL.map(|change| (change, 0));
+
+        for (config_changes, low_memory, count) in groups {
+            conditions.reports += count;
+            if low_memory {
+                conditions.low_memory += count;
+            }
+            for (change, total) in &mut changes {
+                if split_config_changes(&config_changes).any(|c| c == *change) {
+                    *total += count;
+                }
+            }
+        }
+
+        conditions.config_changes = changes
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        conditions
+    }
+
+    /// Share of reports in percent.
+    pub fn percent(&self, count: u64) -> u64 {
+        (count * 100).checked_div(self.reports).unwrap_or_default()
+    }
+}
+
+/// Join changed settings for storage in a single column.
+pub fn join_config_changes(changes: &[ConfigChange]) -> String {
+    changes
+        .iter()
+        .map(|change| change.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Split changed settings as joined by [`join_config_changes`], skipping unknown ones.
+fn split_config_changes(changes: &str) -> impl Iterator<Item = ConfigChange> {
+    changes.split(',').filter_map(ConfigChange::from_name)
+}
+
+/// Short overview of an issue together with the amount of reports received in a certain time
+/// frame.
+#[derive(Debug)]
+pub struct IssueSummary {
+    pub id: i64,
+    pub app_id: i64,
+    pub app_name: String,
+    pub title: String,
+    pub count: u64,
+}
+
+/// Report counts of a single version, for the current and the previous time frame.
+#[derive(Debug)]
+pub struct VersionTrend {
+    pub app_name: String,
+    pub version_name: String,
+    pub previous: u64,
+    pub current: u64,
+}
+
+#[derive(Debug)]
+pub struct NotificationSettings {
+    pub user_id: i64,
+    pub email: String,
+    pub immediate: bool,
+    pub digest: bool,
+}
+
+/// Field of a report that tags can be extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagSource {
+    CustomData,
+    BuildConfig,
+}
+
+impl TagSource {
+    pub const ALL: [Self; 2] = [Self::CustomData, Self::BuildConfig];
+
+    /// Name as stored in the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::CustomData => "custom_data",
+            Self::BuildConfig => "build_config",
+        }
+    }
+
+    /// Name of the field in the report, like `CUSTOM_DATA`.
+    pub fn field(self) -> &'static str {
+        match self {
+            Self::CustomData => "CUSTOM_DATA",
+            Self::BuildConfig => "BUILD_CONFIG",
+        }
+    }
+}
+
+impl FromStr for TagSource {
+    type Err = UnknownTagSource;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.as_str() == s)
+            .ok_or_else(|| UnknownTagSource(s.to_owned()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown tag source `{0}`")]
+pub struct UnknownTagSource(String);
+
+/// A key of `CUSTOM_DATA` or `BUILD_CONFIG`, whose values are extracted as tags of the reports of
+/// an app. The key is the name of the tag as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagKey {
+    pub source: TagSource,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+/// Amount of reports of an issue with a certain tag value.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TagCount {
+    pub key: String,
+    pub value: String,
+    pub count: u64,
+}
+
+/// Rule that puts the reports with a certain value of a `CUSTOM_DATA` or `BUILD_CONFIG` key into
+/// an environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvironmentRule {
+    pub key: TagKey,
+    pub value: String,
+    pub environment: String,
+}
+
+/// Settings of an app that are edited together on its settings page.
+pub struct AppSettings {
+    pub tag_keys: Vec<TagKey>,
+    pub environment_rules: Vec<EnvironmentRule>,
+    /// Rules of the inbound filters, in the order they are checked.
+    pub filter_rules: Vec<String>,
+}
+
+/// Inbound filter of an app, as configured, with the amount of reports it dropped so far.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InboundFilter {
+    pub rule: String,
+    pub dropped: u64,
 }
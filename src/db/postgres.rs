@@ -0,0 +1,88 @@
+//! Connections to a PostgreSQL database, as alternative to the default SQLite database for larger
+//! deployments.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use postgres::{Client, NoTls};
+use r2d2::{Pool, PooledConnection};
+use r2d2_postgres::PostgresConnectionManager;
+
+use crate::settings;
+
+type Manager = PostgresConnectionManager<NoTls>;
+
+pub struct PgPool(Arc<Inner>);
+
+/// Wrapper that closes all connections on a separate thread, once the last handle to the pool is
+/// dropped. Closing a client blocks, which isn't allowed on threads of the async runtime.
+struct Inner(Option<Pool<Manager>>);
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(pool) = self.0.take() {
+            unblock(move || drop(pool));
+        }
+    }
+}
+
+impl PgPool {
+    fn pool(&self) -> &Pool<Manager> {
+        // Unwrap: Only taken out when dropped.
+        self.0.0.as_ref().unwrap()
+    }
+
+    pub fn get(&self) -> Result<PooledConnection<Manager>, r2d2::Error> {
+        self.pool().get()
+    }
+
+    pub fn state(&self) -> r2d2::State {
+        self.pool().state()
+    }
+
+    pub fn max_size(&self) -> u32 {
+        self.pool().max_size()
+    }
+
+    /// Run the given closure with a connection from the pool. The client is blocking, so it's run
+    /// on a separate thread, the same way as for SQLite.
+    #[allow(clippy::trait_duplication_in_bounds)]
+    pub async fn run<F, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Client) -> Result<T, E> + Send + Sync + 'static,
+        T: Send + Sync + 'static,
+        E: Send + Sync + 'static + From<r2d2::Error> + From<tokio::task::JoinError>,
+    {
+        let pool = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+impl Clone for PgPool {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+pub fn create_pool(url: &str, settings: &settings::Database) -> Result<PgPool> {
+    let config = url.parse().context("invalid database URL")?;
+    let manager = PostgresConnectionManager::new(config, NoTls);
+
+    let pool = Pool::builder()
+        .max_size(settings.pool_size)
+        .build(manager)
+        .context("failed to connect to database")?;
+
+    Ok(PgPool(Arc::new(Inner(Some(pool)))))
+}
+
+/// Run blocking database work on a fresh thread, outside of the async runtime. The client drives
+/// its own runtime internally, which panics when started from a thread that already drives one.
+pub fn unblock<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|s| s.spawn(f).join()).unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
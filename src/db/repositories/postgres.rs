@@ -0,0 +1,1266 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use postgres::{Row, Transaction, types::ToSql};
+use tracing::instrument;
+
+use super::{
+    AppRepository, EnvironmentRepository, FilterRepository, IssueRepository, NotFound,
+    NotificationRepository, RISING_MIN_REPORTS, ReportRepository, TagRepository, UserRepository,
+    UserSaveError, VersionRepository,
+};
+use crate::db::{
+    models::{
+        App, AppSettings, CrashType, EnvironmentRule, InboundFilter, Issue, IssueConditions,
+        IssueSummary, NewApp, NewIssue, NewReport, NewUser, NewVersion, NotificationSettings,
+        ReportEntry, Tag, TagCount, TagKey, User, Version, VersionTrend, join_config_changes,
+    },
+    postgres::PgPool,
+};
+
+/// Convert a duration to seconds, for use with the `make_interval` function.
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs_f64()
+}
+
+/// Convert a count, which is always a signed 64-bit integer in Postgres.
+fn count(row: &Row, idx: usize) -> u64 {
+    u64::try_from(row.get::<_, i64>(idx)).unwrap_or_default()
+}
+
+/// Parse a crash type, which is only ever written by us and therefore always valid.
+fn crash_type(row: &Row, idx: usize) -> CrashType {
+    row.get::<_, &str>(idx).parse().unwrap_or_default()
+}
+
+pub(super) struct UserRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<User>> {
+        self.pool
+            .run(|conn| {
+                Ok(conn
+                    .query("SELECT id, username, password FROM users", &[])?
+                    .iter()
+                    .map(map_user)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<User> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, username, password FROM users WHERE id = $1",
+                    &[&id],
+                )?
+                .map(|row| map_user(&row))
+                .ok_or_else(|| NotFound("user").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_by_username(&self, username: String) -> Result<User> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, username, password FROM users WHERE username = $1",
+                    &[&username],
+                )?
+                .map(|row| map_user(&row))
+                .ok_or_else(|| NotFound("user").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, user: NewUser) -> Result<i64, UserSaveError> {
+        self.pool
+            .run(move |conn| {
+                let exists = conn
+                    .query_opt("SELECT 1 FROM users WHERE username = $1", &[&user.username])?
+                    .is_some();
+
+                if exists {
+                    return Err(UserSaveError::AlreadyExists(user.username));
+                }
+
+                conn.query_one(
+                    "INSERT INTO users(username, password) VALUES ($1,$2) RETURNING id",
+                    &[&user.username, &user.password],
+                )
+                .map(|row| row.get(0))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_password(&self, id: i64, password: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE users SET password = $1 WHERE id = $2",
+                    &[&password, &id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete(&self, id: i64, reassign_to: Option<i64>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                if let Some(new_id) = reassign_to {
+                    tx.execute(
+                        "UPDATE apps SET user_id = $1 WHERE user_id = $2",
+                        &[&new_id, &id],
+                    )?;
+                }
+
+                let apps = count(
+                    &tx.query_one("SELECT COUNT(*) FROM apps WHERE user_id = $1", &[&id])?,
+                    0,
+                );
+
+                if apps > 0 {
+                    bail!("user still owns {apps} apps");
+                }
+
+                tx.execute("DELETE FROM notification_apps WHERE user_id = $1", &[&id])?;
+                tx.execute(
+                    "DELETE FROM notification_settings WHERE user_id = $1",
+                    &[&id],
+                )?;
+                tx.execute("DELETE FROM users WHERE id = $1", &[&id])?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+fn map_user(row: &Row) -> User {
+    User {
+        id: row.get(0),
+        username: row.get(1),
+        password: row.get(2),
+    }
+}
+
+pub(super) struct AppRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl AppRepository for AppRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, app: NewApp) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.query_one(
+                    "INSERT INTO apps(user_id, name, username, password) VALUES ($1,$2,$3,$4)
+                    RETURNING id",
+                    &[&app.user_id, &app.name, &app.username, &app.password],
+                )
+                .map(|row| row.get(0))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<App>> {
+        self.pool
+            .run(|conn| {
+                Ok(conn
+                    .query(
+                        "SELECT id, user_id, name, username, password FROM apps",
+                        &[],
+                    )?
+                    .iter()
+                    .map(map_app)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<App> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, user_id, name, username, password FROM apps WHERE id = $1",
+                    &[&id],
+                )?
+                .map(|row| map_app(&row))
+                .ok_or_else(|| NotFound("app").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_by_username(&self, username: String) -> Result<App> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, user_id, name, username, password FROM apps WHERE username = $1",
+                    &[&username],
+                )?
+                .map(|row| map_app(&row))
+                .ok_or_else(|| NotFound("app").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_credentials(&self, id: i64, username: String, password: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE apps SET username = $1, password = $2 WHERE id = $3",
+                    &[&username, &password, &id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_settings(&self, id: i64, settings: AppSettings) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                tx.query_opt("SELECT 1 FROM apps WHERE id = $1", &[&id])?
+                    .ok_or(NotFound("app"))?;
+                replace_tag_keys(&mut tx, id, settings.tag_keys)?;
+                replace_environment_rules(&mut tx, id, settings.environment_rules)?;
+                replace_inbound_filters(&mut tx, id, settings.filter_rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+fn map_app(row: &Row) -> App {
+    App {
+        id: row.get(0),
+        user_id: row.get(1),
+        name: row.get(2),
+        username: row.get(3),
+        password: row.get(4),
+    }
+}
+
+pub(super) struct VersionRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl VersionRepository for VersionRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, version: NewVersion) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.query_one(
+                    "INSERT INTO versions(app_id, name, code) VALUES ($1,$2,$3) RETURNING id",
+                    &[&version.app_id, &version.name, &version.code],
+                )
+                .map(|row| row.get(0))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_or_create(&self, version: NewVersion) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                let params: [&(dyn postgres::types::ToSql + Sync); 3] =
+                    [&version.app_id, &version.name, &version.code];
+
+                if let Some(row) = conn.query_opt(
+                    "SELECT id FROM versions WHERE app_id = $1 AND name = $2 AND code = $3",
+                    &params,
+                )? {
+                    return Ok(row.get(0));
+                }
+
+                // The no-op update makes sure the ID is returned, in case another connection
+                // inserted the same version in the meantime.
+                conn.query_one(
+                    "INSERT INTO versions(app_id, name, code) VALUES ($1,$2,$3)
+                    ON CONFLICT (app_id, name, code) DO UPDATE SET code = excluded.code
+                    RETURNING id",
+                    &params,
+                )
+                .map(|row| row.get(0))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<Version>> {
+        self.pool
+            .run(|conn| {
+                Ok(conn
+                    .query("SELECT id, app_id, name, code FROM versions", &[])?
+                    .iter()
+                    .map(map_version)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<Version> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, app_id, name, code FROM versions WHERE id = $1",
+                    &[&id],
+                )?
+                .as_ref()
+                .map(map_version)
+                .ok_or_else(|| NotFound("version").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_app(&self, id: i64) -> Result<Vec<Version>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT id, app_id, name, code FROM versions WHERE app_id = $1",
+                        &[&id],
+                    )?
+                    .iter()
+                    .map(map_version)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_rising(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+    ) -> Result<Vec<VersionTrend>> {
+        let current = seconds(period);
+        let previous = seconds(period * 2);
+        let min = i64::try_from(RISING_MIN_REPORTS)?;
+
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "WITH bounds AS (
+                            SELECT timezone('UTC', now()) - make_interval(secs => $1) AS current,
+                                timezone('UTC', now()) - make_interval(secs => $2) AS previous
+                        )
+                        SELECT a.name, v.name,
+                            COUNT(*) FILTER (WHERE r.received_at < b.current),
+                            COUNT(*) FILTER (WHERE r.received_at >= b.current)
+                        FROM (
+                            SELECT DISTINCT version_id, report_id, received_at FROM issue_reports
+                            WHERE crash_type <> 'non_fatal'
+                                AND environment = COALESCE($4, environment)
+                        ) r
+                        CROSS JOIN bounds b
+                        JOIN versions v ON v.id = r.version_id
+                        JOIN apps a ON a.id = v.app_id
+                        WHERE r.received_at >= b.previous
+                        GROUP BY v.id, a.name
+                        HAVING COUNT(*) FILTER (WHERE r.received_at >= b.current) >= $3
+                            AND COUNT(*) FILTER (WHERE r.received_at >= b.current)
+                                > COUNT(*) FILTER (WHERE r.received_at < b.current) * 3 / 2
+                        ORDER BY 4 DESC",
+                        &[&current, &previous, &min, &environment],
+                    )?
+                    .iter()
+                    .map(|row| VersionTrend {
+                        app_name: row.get(0),
+                        version_name: row.get(1),
+                        previous: count(row, 2),
+                        current: count(row, 3),
+                    })
+                    .collect())
+            })
+            .await
+    }
+}
+
+fn map_version(row: &Row) -> Version {
+    Version {
+        id: row.get(0),
+        app_id: row.get(1),
+        name: row.get(2),
+        code: row.get(3),
+    }
+}
+
+pub(super) struct ReportRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl ReportRepository for ReportRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, report: NewReport) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.query_one(
+                    "INSERT INTO reports(version_id, issue_id, report_id, crash_date, crash_type, \
+                     config_changes, low_memory, environment, received_at)
+                    VALUES ($1,$2,$3,$4,$5,$6,$7,$8,timezone('UTC', now()))
+                    RETURNING id",
+                    &[
+                        &report.version_id,
+                        &report.issue_id,
+                        &report.report_id,
+                        &report.crash_date,
+                        &report.crash_type.as_str(),
+                        &join_config_changes(&report.config_changes),
+                        &report.low_memory,
+                        &report.environment,
+                    ],
+                )
+                .map(|row| row.get(0))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn replace(&self, report: NewReport) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                let app_versions = "SELECT id FROM versions
+                    WHERE app_id = (SELECT app_id FROM versions WHERE id = $2)";
+                tx.execute(
+                    &format!(
+                        "DELETE FROM report_issues WHERE report_id IN (
+                            SELECT id FROM reports
+                            WHERE report_id = $1 AND version_id IN ({app_versions})
+                        )"
+                    ),
+                    &[&report.report_id, &report.version_id],
+                )?;
+                tx.execute(
+                    &format!(
+                        "DELETE FROM reports WHERE report_id = $1 AND version_id IN \
+                         ({app_versions})"
+                    ),
+                    &[&report.report_id, &report.version_id],
+                )?;
+                let id = tx
+                    .query_one(
+                        "INSERT INTO reports(version_id, issue_id, report_id, crash_date, \
+                         crash_type, config_changes, low_memory, environment, received_at)
+                        VALUES ($1,$2,$3,$4,$5,$6,$7,$8,timezone('UTC', now()))
+                        RETURNING id",
+                        &[
+                            &report.version_id,
+                            &report.issue_id,
+                            &report.report_id,
+                            &report.crash_date,
+                            &report.crash_type.as_str(),
+                            &join_config_changes(&report.config_changes),
+                            &report.low_memory,
+                            &report.environment,
+                        ],
+                    )?
+                    .get(0);
+                tx.commit()?;
+
+                Ok(id)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete(&self, app_id: i64, report_id: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                tx.execute(
+                    "DELETE FROM report_tags WHERE app_id = $1 AND report_id = $2",
+                    &[&app_id, &report_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM report_issues WHERE report_id IN (
+                        SELECT id FROM reports WHERE report_id = $1 AND version_id IN (
+                            SELECT id FROM versions WHERE app_id = $2
+                        )
+                    )",
+                    &[&report_id, &app_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM reports WHERE report_id = $1 AND version_id IN (
+                        SELECT id FROM versions WHERE app_id = $2
+                    )",
+                    &[&report_id, &app_id],
+                )?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn link_issue(&self, id: i64, issue_id: i64) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "INSERT INTO report_issues(report_id, issue_id) VALUES ($1,$2)
+                    ON CONFLICT DO NOTHING",
+                    &[&id, &issue_id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_app_id(&self, report_id: String) -> Result<Option<i64>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query_opt(
+                        "SELECT v.app_id FROM reports r
+                        JOIN versions v ON v.id = r.version_id
+                        WHERE r.report_id = $1
+                        LIMIT 1",
+                        &[&report_id],
+                    )?
+                    .map(|row| row.get(0)))
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn latest_id(&self, version_id: i64) -> Result<Option<String>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query_opt(
+                        "SELECT report_id FROM reports
+                        WHERE version_id = $1
+                        ORDER BY received_at DESC, id DESC
+                        LIMIT 1",
+                        &[&version_id],
+                    )?
+                    .map(|row| row.get(0)))
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_issue(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+        tags: Vec<Tag>,
+        limit: u32,
+    ) -> Result<Vec<ReportEntry>> {
+        self.pool
+            .run(move |conn| {
+                let limit = i64::from(limit);
+                let mut params = vec![&issue_id as &(dyn ToSql + Sync), &environment];
+                for tag in &tags {
+                    params.extend([&tag.key as &(dyn ToSql + Sync), &tag.value]);
+                }
+                let filter = (0..tags.len())
+                    .map(|i| {
+                        format!(
+                            "AND EXISTS (SELECT 1 FROM report_tags t
+                                WHERE t.app_id = v.app_id AND t.report_id = r.report_id
+                                    AND t.key = ${} AND t.value = ${})",
+                            2 * i + 3,
+                            2 * i + 4
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                params.push(&limit);
+
+                Ok(conn
+                    .query(
+                        &format!(
+                            "SELECT r.report_id, v.name, v.code, r.crash_date,
+                                to_char(r.received_at, 'YYYY-MM-DD HH24:MI:SS')
+                            FROM issue_reports r
+                            JOIN versions v ON v.id = r.version_id
+                            WHERE r.issue_id = $1 AND r.environment = COALESCE($2, r.environment)
+                            {filter}
+                            ORDER BY r.received_at DESC, r.id DESC
+                            LIMIT ${}",
+                            params.len()
+                        ),
+                        &params,
+                    )?
+                    .into_iter()
+                    .map(|row| ReportEntry {
+                        report_id: row.get(0),
+                        version_name: row.get(1),
+                        version_code: row.get(2),
+                        crash_date: row.get(3),
+                        received_at: row.get(4),
+                    })
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn conditions(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+    ) -> Result<IssueConditions> {
+        self.pool
+            .run(move |conn| {
+                let groups = conn
+                    .query(
+                        "SELECT config_changes, low_memory, COUNT(*)
+                        FROM issue_reports
+                        WHERE issue_id = $1 AND environment = COALESCE($2, environment)
+                        GROUP BY config_changes, low_memory",
+                        &[&issue_id, &environment],
+                    )?
+                    .into_iter()
+                    .map(|row| (row.get(0), row.get(1), count(&row, 2)));
+
+                Ok(IssueConditions::from_groups(groups))
+            })
+            .await
+    }
+}
+
+pub(super) struct IssueRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl IssueRepository for IssueRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn get_or_create(&self, issue: NewIssue) -> Result<(i64, bool)> {
+        self.pool
+            .run(move |conn| {
+                // A row that was just inserted has no deleting transaction ID set yet, which tells
+                // it apart from an updated one.
+                conn.query_one(
+                    "INSERT INTO issues(app_id, fingerprint, title, crash_type, first_seen, \
+                     last_seen)
+                    VALUES ($1,$2,$3,$4,timezone('UTC', now()),timezone('UTC', now()))
+                    ON CONFLICT (app_id, fingerprint) DO UPDATE SET last_seen = excluded.last_seen
+                    RETURNING id, xmax = 0",
+                    &[
+                        &issue.app_id,
+                        &issue.fingerprint,
+                        &issue.title,
+                        &issue.crash_type.as_str(),
+                    ],
+                )
+                .map(|row| (row.get(0), row.get(1)))
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<Issue> {
+        self.pool
+            .run(move |conn| {
+                conn.query_opt(
+                    "SELECT id, app_id, fingerprint, title,
+                        to_char(first_seen, 'YYYY-MM-DD HH24:MI:SS'),
+                        to_char(last_seen, 'YYYY-MM-DD HH24:MI:SS'), crash_type
+                    FROM issues WHERE id = $1",
+                    &[&id],
+                )?
+                .map(|row| Issue {
+                    id: row.get(0),
+                    app_id: row.get(1),
+                    fingerprint: row.get(2),
+                    title: row.get(3),
+                    first_seen: row.get(4),
+                    last_seen: row.get(5),
+                    crash_type: crash_type(&row, 6),
+                })
+                .ok_or_else(|| NotFound("issue").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn find(&self, app_id: i64, fingerprint: String) -> Result<Option<Issue>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query_opt(
+                        "SELECT id, app_id, fingerprint, title,
+                            to_char(first_seen, 'YYYY-MM-DD HH24:MI:SS'),
+                            to_char(last_seen, 'YYYY-MM-DD HH24:MI:SS'), crash_type
+                        FROM issues WHERE app_id = $1 AND fingerprint = $2",
+                        &[&app_id, &fingerprint],
+                    )?
+                    .map(|row| Issue {
+                        id: row.get(0),
+                        app_id: row.get(1),
+                        fingerprint: row.get(2),
+                        title: row.get(3),
+                        first_seen: row.get(4),
+                        last_seen: row.get(5),
+                        crash_type: crash_type(&row, 6),
+                    }))
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_app(
+        &self,
+        app_id: i64,
+        crash_type: CrashType,
+        environment: Option<String>,
+    ) -> Result<Vec<IssueSummary>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                        FROM issues i
+                        JOIN apps a ON a.id = i.app_id
+                        LEFT JOIN issue_reports r
+                            ON r.issue_id = i.id AND r.environment = COALESCE($3, r.environment)
+                        WHERE i.app_id = $1 AND i.crash_type = $2
+                        GROUP BY i.id, a.name
+                        HAVING $3::TEXT IS NULL OR COUNT(r.id) > 0
+                        ORDER BY i.last_seen DESC, i.id DESC",
+                        &[&app_id, &crash_type.as_str(), &environment],
+                    )?
+                    .iter()
+                    .map(map_issue_summary)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_new(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+    ) -> Result<Vec<IssueSummary>> {
+        let since = seconds(period);
+
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                        FROM issues i
+                        JOIN apps a ON a.id = i.app_id
+                        LEFT JOIN issue_reports r
+                            ON r.issue_id = i.id AND r.environment = COALESCE($2, r.environment)
+                        WHERE i.first_seen >= timezone('UTC', now()) - make_interval(secs => $1)
+                        GROUP BY i.id, a.name
+                        HAVING $2::TEXT IS NULL OR COUNT(r.id) > 0
+                        ORDER BY i.first_seen DESC",
+                        &[&since, &environment],
+                    )?
+                    .iter()
+                    .map(map_issue_summary)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_top(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<IssueSummary>> {
+        let since = seconds(period);
+        let limit = i64::from(limit);
+
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                        FROM issues i
+                        JOIN apps a ON a.id = i.app_id
+                        JOIN issue_reports r ON r.issue_id = i.id
+                        WHERE r.received_at >= timezone('UTC', now()) - make_interval(secs => $1)
+                            AND r.environment = COALESCE($2, r.environment)
+                        GROUP BY i.id, a.name
+                        ORDER BY 5 DESC
+                        LIMIT $3",
+                        &[&since, &environment, &limit],
+                    )?
+                    .iter()
+                    .map(map_issue_summary)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_empty(&self) -> Result<u64> {
+        self.pool
+            .run(|conn| {
+                conn.execute(
+                    "DELETE FROM issues i
+                    WHERE NOT EXISTS (SELECT 1 FROM issue_reports r WHERE r.issue_id = i.id)",
+                    &[],
+                )
+                .map_err(Into::into)
+            })
+            .await
+    }
+}
+
+fn map_issue_summary(row: &Row) -> IssueSummary {
+    IssueSummary {
+        id: row.get(0),
+        app_id: row.get(1),
+        app_name: row.get(2),
+        title: row.get(3),
+        count: count(row, 4),
+    }
+}
+
+pub(super) struct NotificationRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl NotificationRepository for NotificationRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn get(&self, user_id: i64) -> Result<Option<NotificationSettings>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query_opt(
+                        "SELECT user_id, email, immediate, digest
+                        FROM notification_settings WHERE user_id = $1",
+                        &[&user_id],
+                    )?
+                    .as_ref()
+                    .map(map_notification_settings))
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, settings: NotificationSettings, apps: Vec<i64>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                tx.execute(
+                    "INSERT INTO notification_settings(user_id, email, immediate, digest)
+                    VALUES ($1,$2,$3,$4)
+                    ON CONFLICT (user_id) DO UPDATE SET
+                        email = excluded.email,
+                        immediate = excluded.immediate,
+                        digest = excluded.digest",
+                    &[
+                        &settings.user_id,
+                        &settings.email,
+                        &settings.immediate,
+                        &settings.digest,
+                    ],
+                )?;
+                tx.execute(
+                    "DELETE FROM notification_apps WHERE user_id = $1",
+                    &[&settings.user_id],
+                )?;
+                for app_id in apps {
+                    tx.execute(
+                        "INSERT INTO notification_apps(user_id, app_id) VALUES ($1,$2)
+                        ON CONFLICT DO NOTHING",
+                        &[&settings.user_id, &app_id],
+                    )?;
+                }
+
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_apps(&self, user_id: i64) -> Result<Vec<i64>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT app_id FROM notification_apps WHERE user_id = $1 ORDER BY app_id",
+                        &[&user_id],
+                    )?
+                    .iter()
+                    .map(|row| row.get(0))
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_immediate(&self, app_id: i64) -> Result<Vec<NotificationSettings>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT n.user_id, n.email, n.immediate, n.digest
+                        FROM notification_settings n
+                        JOIN notification_apps a ON a.user_id = n.user_id
+                        WHERE n.immediate AND a.app_id = $1",
+                        &[&app_id],
+                    )?
+                    .iter()
+                    .map(map_notification_settings)
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_digest(&self) -> Result<Vec<NotificationSettings>> {
+        self.pool
+            .run(|conn| {
+                Ok(conn
+                    .query(
+                        "SELECT user_id, email, immediate, digest
+                        FROM notification_settings WHERE digest",
+                        &[],
+                    )?
+                    .iter()
+                    .map(map_notification_settings)
+                    .collect())
+            })
+            .await
+    }
+}
+
+pub(super) struct TagRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl TagRepository for TagRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list_keys(&self, app_id: i64) -> Result<Vec<TagKey>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT source, key FROM tag_keys
+                        WHERE app_id = $1
+                        ORDER BY source, key",
+                        &[&app_id],
+                    )?
+                    .into_iter()
+                    .filter_map(|row| {
+                        Some(TagKey {
+                            source: row.get::<_, &str>(0).parse().ok()?,
+                            key: row.get(1),
+                        })
+                    })
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_keys(&self, app_id: i64, keys: Vec<TagKey>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+                replace_tag_keys(&mut tx, app_id, keys)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_report_tags(&self, app_id: i64, report_id: String, tags: Vec<Tag>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+
+                tx.execute(
+                    "DELETE FROM report_tags WHERE app_id = $1 AND report_id = $2",
+                    &[&app_id, &report_id],
+                )?;
+                for tag in tags {
+                    tx.execute(
+                        "INSERT INTO report_tags(app_id, report_id, key, value)
+                        VALUES ($1,$2,$3,$4)
+                        ON CONFLICT DO NOTHING",
+                        &[&app_id, &report_id, &tag.key, &tag.value],
+                    )?;
+                }
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn distribution(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+    ) -> Result<Vec<TagCount>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT t.key, t.value, COUNT(DISTINCT r.report_id) AS count
+                        FROM issue_reports r
+                        JOIN versions v ON v.id = r.version_id
+                        JOIN report_tags t ON t.app_id = v.app_id AND t.report_id = r.report_id
+                        WHERE r.issue_id = $1 AND r.environment = COALESCE($2, r.environment)
+                        GROUP BY t.key, t.value
+                        ORDER BY t.key, count DESC, t.value",
+                        &[&issue_id, &environment],
+                    )?
+                    .into_iter()
+                    .map(|row| TagCount {
+                        key: row.get(0),
+                        value: row.get(1),
+                        count: count(&row, 2),
+                    })
+                    .collect())
+            })
+            .await
+    }
+}
+
+pub(super) struct EnvironmentRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl EnvironmentRepository for EnvironmentRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list_rules(&self, app_id: i64) -> Result<Vec<EnvironmentRule>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT source, key, value, environment FROM environment_rules
+                        WHERE app_id = $1
+                        ORDER BY position",
+                        &[&app_id],
+                    )?
+                    .into_iter()
+                    .filter_map(|row| {
+                        Some(EnvironmentRule {
+                            key: TagKey {
+                                source: row.get::<_, &str>(0).parse().ok()?,
+                                key: row.get(1),
+                            },
+                            value: row.get(2),
+                            environment: row.get(3),
+                        })
+                    })
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_rules(&self, app_id: i64, rules: Vec<EnvironmentRule>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+                replace_environment_rules(&mut tx, app_id, rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self, app_id: i64) -> Result<Vec<String>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT DISTINCT r.environment
+                        FROM reports r
+                        JOIN versions v ON v.id = r.version_id
+                        WHERE v.app_id = $1
+                        ORDER BY r.environment",
+                        &[&app_id],
+                    )?
+                    .into_iter()
+                    .map(|row| row.get(0))
+                    .collect())
+            })
+            .await
+    }
+}
+
+pub(super) struct FilterRepositoryImpl {
+    pub(super) pool: PgPool,
+}
+
+#[async_trait]
+impl FilterRepository for FilterRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list(&self, app_id: i64) -> Result<Vec<InboundFilter>> {
+        self.pool
+            .run(move |conn| {
+                Ok(conn
+                    .query(
+                        "SELECT rule, dropped FROM inbound_filters
+                        WHERE app_id = $1
+                        ORDER BY position",
+                        &[&app_id],
+                    )?
+                    .into_iter()
+                    .map(|row| InboundFilter {
+                        rule: row.get(0),
+                        dropped: count(&row, 1),
+                    })
+                    .collect())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, app_id: i64, rules: Vec<String>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let mut tx = conn.transaction()?;
+                replace_inbound_filters(&mut tx, app_id, rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn record_drop(&self, app_id: i64, rule: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE inbound_filters SET dropped = dropped + 1
+                    WHERE app_id = $1 AND rule = $2",
+                    &[&app_id, &rule],
+                )?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+fn replace_tag_keys(tx: &mut Transaction<'_>, app_id: i64, keys: Vec<TagKey>) -> Result<()> {
+    tx.execute("DELETE FROM tag_keys WHERE app_id = $1", &[&app_id])?;
+    for key in keys {
+        tx.execute(
+            "INSERT INTO tag_keys(app_id, source, key) VALUES ($1,$2,$3)
+            ON CONFLICT DO NOTHING",
+            &[&app_id, &key.source.as_str(), &key.key],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn replace_environment_rules(
+    tx: &mut Transaction<'_>,
+    app_id: i64,
+    rules: Vec<EnvironmentRule>,
+) -> Result<()> {
+    tx.execute(
+        "DELETE FROM environment_rules WHERE app_id = $1",
+        &[&app_id],
+    )?;
+    for (position, rule) in (0_i64..).zip(rules) {
+        tx.execute(
+            "INSERT INTO environment_rules(app_id, position, source, key, value, environment)
+            VALUES ($1,$2,$3,$4,$5,$6)",
+            &[
+                &app_id,
+                &position,
+                &rule.key.source.as_str(),
+                &rule.key.key,
+                &rule.value,
+                &rule.environment,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn replace_inbound_filters(
+    tx: &mut Transaction<'_>,
+    app_id: i64,
+    rules: Vec<String>,
+) -> Result<()> {
+    // Mark all filters as removed, and remove the ones that aren't saved again.
+    tx.execute(
+        "UPDATE inbound_filters SET position = -1 WHERE app_id = $1",
+        &[&app_id],
+    )?;
+    for (position, rule) in (0_i64..).zip(rules) {
+        tx.execute(
+            "INSERT INTO inbound_filters(app_id, position, rule) VALUES ($1,$2,$3)
+            ON CONFLICT (app_id, rule) DO UPDATE SET position = excluded.position",
+            &[&app_id, &position, &rule],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM inbound_filters WHERE app_id = $1 AND position < 0",
+        &[&app_id],
+    )?;
+
+    Ok(())
+}
+
+fn map_notification_settings(row: &Row) -> NotificationSettings {
+    NotificationSettings {
+        user_id: row.get(0),
+        email: row.get(1),
+        immediate: row.get(2),
+        digest: row.get(3),
+    }
+}
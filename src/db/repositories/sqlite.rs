@@ -0,0 +1,1256 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, ToSql, params};
+use tracing::instrument;
+
+use super::{
+    AppRepository, EnvironmentRepository, FilterRepository, IssueRepository, NotFound,
+    NotificationRepository, RISING_MIN_REPORTS, ReportRepository, TagRepository, UserRepository,
+    UserSaveError, VersionRepository,
+};
+use crate::db::{
+    SqlitePool,
+    models::{
+        App, AppSettings, CrashType, EnvironmentRule, InboundFilter, Issue, IssueConditions,
+        IssueSummary, NewApp, NewIssue, NewReport, NewUser, NewVersion, NotificationSettings,
+        ReportEntry, Tag, TagCount, TagKey, TagSource, User, Version, VersionTrend,
+        join_config_changes,
+    },
+};
+
+/// Create a time modifier for the database's date and time functions, that goes back in time by the
+/// given duration.
+fn time_ago(duration: Duration) -> String {
+    format!("-{} seconds", duration.as_secs())
+}
+
+fn crash_type(row: &rusqlite::Row<'_>, idx: usize) -> rusqlite::Result<CrashType> {
+    row.get::<_, String>(idx)?.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn tag_source(row: &rusqlite::Row<'_>, idx: usize) -> rusqlite::Result<TagSource> {
+    row.get::<_, String>(idx)?.parse().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+pub(super) struct UserRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl UserRepository for UserRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<User>> {
+        self.pool
+            .run(|conn| {
+                conn.prepare("SELECT * FROM users")?
+                    .query_map([], |row| {
+                        Ok(User {
+                            id: row.get(0)?,
+                            username: row.get(1)?,
+                            password: row.get(2)?,
+                        })
+                    })?
+                    .map(|row| row.map_err(Into::into))
+                    .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<User> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM users WHERE id = ?")?
+                    .query_row([id], |row| {
+                        Ok(User {
+                            id: row.get(0)?,
+                            username: row.get(1)?,
+                            password: row.get(2)?,
+                        })
+                    })
+                    .optional()?
+                    .ok_or_else(|| NotFound("user").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_by_username(&self, username: String) -> Result<User> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM users WHERE username = ?")?
+                    .query_row([username], |row| {
+                        Ok(User {
+                            id: row.get(0)?,
+                            username: row.get(1)?,
+                            password: row.get(2)?,
+                        })
+                    })
+                    .optional()?
+                    .ok_or_else(|| NotFound("user").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, user: NewUser) -> Result<i64, UserSaveError> {
+        self.pool
+            .run(move |conn| {
+                let count = conn
+                    .prepare("SELECT COUNT(*) FROM users WHERE username = ?")?
+                    .query_row([&user.username], |row| row.get::<_, u64>(0))?;
+
+                if count > 0 {
+                    return Err(UserSaveError::AlreadyExists(user.username));
+                }
+
+                conn.prepare("INSERT INTO users(username, password) VALUES (?,?)")?
+                    .insert(params![user.username, user.password])
+                    .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_password(&self, id: i64, password: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE users SET password = ? WHERE id = ?",
+                    params![password, id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete(&self, id: i64, reassign_to: Option<i64>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                if let Some(new_id) = reassign_to {
+                    tx.execute(
+                        "UPDATE apps SET user_id = ? WHERE user_id = ?",
+                        params![new_id, id],
+                    )?;
+                }
+
+                let apps = tx
+                    .prepare("SELECT COUNT(*) FROM apps WHERE user_id = ?")?
+                    .query_row([id], |row| row.get::<_, u64>(0))?;
+
+                if apps > 0 {
+                    bail!("user still owns {apps} apps");
+                }
+
+                tx.execute("DELETE FROM notification_apps WHERE user_id = ?", [id])?;
+                tx.execute("DELETE FROM notification_settings WHERE user_id = ?", [id])?;
+                tx.execute("DELETE FROM users WHERE id = ?", [id])?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+pub(super) struct AppRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl AppRepository for AppRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, app: NewApp) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "INSERT INTO apps(user_id, name, username, password) VALUES (?,?,?,?)",
+                )?
+                .insert(params![app.user_id, app.name, app.username, app.password])
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<App>> {
+        self.pool
+            .run(|conn| {
+                conn.prepare("SELECT * FROM apps")?
+                    .query_map([], |row| {
+                        Ok(App {
+                            id: row.get(0)?,
+                            user_id: row.get(1)?,
+                            name: row.get(2)?,
+                            username: row.get(3)?,
+                            password: row.get(4)?,
+                        })
+                    })?
+                    .map(|row| row.map_err(Into::into))
+                    .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<App> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM apps WHERE id = ?")?
+                    .query_row([id], |row| {
+                        Ok(App {
+                            id: row.get(0)?,
+                            user_id: row.get(1)?,
+                            name: row.get(2)?,
+                            username: row.get(3)?,
+                            password: row.get(4)?,
+                        })
+                    })
+                    .optional()?
+                    .ok_or_else(|| NotFound("app").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_by_username(&self, username: String) -> Result<App> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM apps WHERE username = ?")?
+                    .query_row([username], |row| {
+                        Ok(App {
+                            id: row.get(0)?,
+                            user_id: row.get(1)?,
+                            name: row.get(2)?,
+                            username: row.get(3)?,
+                            password: row.get(4)?,
+                        })
+                    })
+                    .optional()?
+                    .ok_or_else(|| NotFound("app").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn update_credentials(&self, id: i64, username: String, password: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE apps SET username = ?, password = ? WHERE id = ?",
+                    params![username, password, id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_settings(&self, id: i64, settings: AppSettings) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                tx.query_row("SELECT 1 FROM apps WHERE id = ?", [id], |_| Ok(()))
+                    .optional()?
+                    .ok_or(NotFound("app"))?;
+                replace_tag_keys(&tx, id, settings.tag_keys)?;
+                replace_environment_rules(&tx, id, settings.environment_rules)?;
+                replace_inbound_filters(&tx, id, settings.filter_rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+pub(super) struct VersionRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl VersionRepository for VersionRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, version: NewVersion) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("INSERT INTO versions(app_id, name, code) VALUES (?,?,?)")?
+                    .insert(params![version.app_id, version.name, version.code])
+                    .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_or_create(&self, version: NewVersion) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                let params = params![version.app_id, version.name, version.code];
+
+                if let Some(id) = conn
+                    .query_row(
+                        "SELECT id FROM versions WHERE app_id = ? AND name = ? AND code = ?",
+                        params,
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                {
+                    return Ok(id);
+                }
+
+                // The no-op update makes sure the ID is returned, in case another connection
+                // inserted the same version in the meantime.
+                conn.query_row(
+                    "INSERT INTO versions(app_id, name, code) VALUES (?,?,?)
+                    ON CONFLICT (app_id, name, code) DO UPDATE SET code = excluded.code
+                    RETURNING id",
+                    params,
+                    |row| row.get(0),
+                )
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self) -> Result<Vec<Version>> {
+        self.pool
+            .run(|conn| {
+                conn.prepare("SELECT * FROM versions")?
+                    .query_map([], |row| {
+                        Ok(Version {
+                            id: row.get(0)?,
+                            app_id: row.get(1)?,
+                            name: row.get(2)?,
+                            code: row.get(3)?,
+                        })
+                    })?
+                    .map(|row| row.map_err(Into::into))
+                    .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<Version> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM versions WHERE id = ?")?
+                    .query_row([id], |row| {
+                        Ok(Version {
+                            id: row.get(0)?,
+                            app_id: row.get(1)?,
+                            name: row.get(2)?,
+                            code: row.get(3)?,
+                        })
+                    })
+                    .optional()?
+                    .ok_or_else(|| NotFound("version").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_app(&self, id: i64) -> Result<Vec<Version>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare("SELECT * FROM versions WHERE app_id = ?")?
+                    .query_map([id], |row| {
+                        Ok(Version {
+                            id: row.get(0)?,
+                            app_id: row.get(1)?,
+                            name: row.get(2)?,
+                            code: row.get(3)?,
+                        })
+                    })?
+                    .map(|row| row.map_err(Into::into))
+                    .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_rising(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+    ) -> Result<Vec<VersionTrend>> {
+        let current = time_ago(period);
+        let previous = time_ago(period * 2);
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT a.name, v.name,
+                        SUM(r.received_at < datetime('now', :current)),
+                        SUM(r.received_at >= datetime('now', :current))
+                    FROM (
+                        SELECT DISTINCT version_id, report_id, received_at FROM issue_reports
+                        WHERE crash_type <> 'non_fatal'
+                            AND environment = COALESCE(:environment, environment)
+                    ) r
+                    JOIN versions v ON v.id = r.version_id
+                    JOIN apps a ON a.id = v.app_id
+                    WHERE r.received_at >= datetime('now', :previous)
+                    GROUP BY v.id
+                    HAVING SUM(r.received_at >= datetime('now', :current)) >= :min
+                        AND SUM(r.received_at >= datetime('now', :current))
+                            > SUM(r.received_at < datetime('now', :current)) * 3 / 2
+                    ORDER BY 4 DESC",
+                )?
+                .query_map(
+                    rusqlite::named_params! {
+                        ":current": current,
+                        ":previous": previous,
+                        ":min": RISING_MIN_REPORTS,
+                        ":environment": environment,
+                    },
+                    |row| {
+                        Ok(VersionTrend {
+                            app_name: row.get(0)?,
+                            version_name: row.get(1)?,
+                            previous: row.get(2)?,
+                            current: row.get(3)?,
+                        })
+                    },
+                )?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+}
+
+pub(super) struct ReportRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl ReportRepository for ReportRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn save(&self, report: NewReport) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "INSERT INTO reports(version_id, issue_id, report_id, crash_date, crash_type, \
+                     config_changes, low_memory, environment, received_at)
+                    VALUES (?,?,?,?,?,?,?,?,datetime('now'))",
+                )?
+                .insert(params![
+                    report.version_id,
+                    report.issue_id,
+                    report.report_id,
+                    report.crash_date,
+                    report.crash_type.as_str(),
+                    join_config_changes(&report.config_changes),
+                    report.low_memory,
+                    report.environment,
+                ])
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn replace(&self, report: NewReport) -> Result<i64> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                let app_versions = "SELECT id FROM versions
+                    WHERE app_id = (SELECT app_id FROM versions WHERE id = ?)";
+                tx.execute(
+                    &format!(
+                        "DELETE FROM report_issues WHERE report_id IN (
+                            SELECT id FROM reports
+                            WHERE report_id = ? AND version_id IN ({app_versions})
+                        )"
+                    ),
+                    params![report.report_id, report.version_id],
+                )?;
+                tx.execute(
+                    &format!(
+                        "DELETE FROM reports WHERE report_id = ? AND version_id IN \
+                         ({app_versions})"
+                    ),
+                    params![report.report_id, report.version_id],
+                )?;
+                let id = tx
+                    .prepare(
+                        "INSERT INTO reports(version_id, issue_id, report_id, crash_date, \
+                         crash_type, config_changes, low_memory, environment, received_at)
+                        VALUES (?,?,?,?,?,?,?,?,datetime('now'))",
+                    )?
+                    .insert(params![
+                        report.version_id,
+                        report.issue_id,
+                        report.report_id,
+                        report.crash_date,
+                        report.crash_type.as_str(),
+                        join_config_changes(&report.config_changes),
+                        report.low_memory,
+                        report.environment,
+                    ])?;
+                tx.commit()?;
+
+                Ok(id)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete(&self, app_id: i64, report_id: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                tx.execute(
+                    "DELETE FROM report_tags WHERE app_id = ? AND report_id = ?",
+                    params![app_id, report_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM report_issues WHERE report_id IN (
+                        SELECT id FROM reports WHERE report_id = ? AND version_id IN (
+                            SELECT id FROM versions WHERE app_id = ?
+                        )
+                    )",
+                    params![report_id, app_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM reports WHERE report_id = ? AND version_id IN (
+                        SELECT id FROM versions WHERE app_id = ?
+                    )",
+                    params![report_id, app_id],
+                )?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn link_issue(&self, id: i64, issue_id: i64) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "INSERT INTO report_issues(report_id, issue_id) VALUES (?,?)
+                    ON CONFLICT DO NOTHING",
+                    params![id, issue_id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get_app_id(&self, report_id: String) -> Result<Option<i64>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT v.app_id FROM reports r
+                    JOIN versions v ON v.id = r.version_id
+                    WHERE r.report_id = ?
+                    LIMIT 1",
+                )?
+                .query_row([report_id], |row| row.get(0))
+                .optional()
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn latest_id(&self, version_id: i64) -> Result<Option<String>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT report_id FROM reports
+                    WHERE version_id = ?
+                    ORDER BY received_at DESC, id DESC
+                    LIMIT 1",
+                )?
+                .query_row([version_id], |row| row.get(0))
+                .optional()
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_issue(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+        tags: Vec<Tag>,
+        limit: u32,
+    ) -> Result<Vec<ReportEntry>> {
+        self.pool
+            .run(move |conn| {
+                let limit = i64::from(limit);
+                let mut params = vec![&issue_id as &dyn ToSql, &environment];
+                for tag in &tags {
+                    params.extend([&tag.key as &dyn ToSql, &tag.value]);
+                }
+                params.push(&limit);
+
+                conn.prepare(&format!(
+                    "SELECT r.report_id, v.name, v.code, r.crash_date, r.received_at
+                    FROM issue_reports r
+                    JOIN versions v ON v.id = r.version_id
+                    WHERE r.issue_id = ? AND r.environment = COALESCE(?, r.environment)
+                    {}
+                    ORDER BY r.received_at DESC, r.id DESC
+                    LIMIT ?",
+                    "AND EXISTS (SELECT 1 FROM report_tags t
+                        WHERE t.app_id = v.app_id AND t.report_id = r.report_id
+                            AND t.key = ? AND t.value = ?)\n"
+                        .repeat(tags.len())
+                ))?
+                .query_map(params.as_slice(), |row| {
+                    Ok(ReportEntry {
+                        report_id: row.get(0)?,
+                        version_name: row.get(1)?,
+                        version_code: row.get(2)?,
+                        crash_date: row.get(3)?,
+                        received_at: row.get(4)?,
+                    })
+                })?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn conditions(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+    ) -> Result<IssueConditions> {
+        self.pool
+            .run(move |conn| {
+                let groups = conn
+                    .prepare(
+                        "SELECT config_changes, low_memory, COUNT(*)
+                        FROM issue_reports
+                        WHERE issue_id = ? AND environment = COALESCE(?, environment)
+                        GROUP BY config_changes, low_memory",
+                    )?
+                    .query_map(params![issue_id, environment], |row| {
+                        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+
+                Ok(IssueConditions::from_groups(groups))
+            })
+            .await
+    }
+}
+
+pub(super) struct IssueRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl IssueRepository for IssueRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn get_or_create(&self, issue: NewIssue) -> Result<(i64, bool)> {
+        self.pool
+            .run(move |conn| {
+                let created = conn.execute(
+                    "INSERT INTO issues(app_id, fingerprint, title, crash_type, first_seen, \
+                     last_seen)
+                    VALUES (?,?,?,?,datetime('now'),datetime('now'))
+                    ON CONFLICT (app_id, fingerprint) DO NOTHING",
+                    params![
+                        issue.app_id,
+                        issue.fingerprint,
+                        issue.title,
+                        issue.crash_type.as_str()
+                    ],
+                )? == 1;
+
+                if created {
+                    return Ok((conn.last_insert_rowid(), true));
+                }
+
+                conn.query_row(
+                    "UPDATE issues SET last_seen = datetime('now')
+                    WHERE app_id = ? AND fingerprint = ?
+                    RETURNING id",
+                    params![issue.app_id, issue.fingerprint],
+                    |row| Ok((row.get(0)?, false)),
+                )
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn get(&self, id: i64) -> Result<Issue> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT id, app_id, fingerprint, title, first_seen, last_seen, crash_type
+                    FROM issues WHERE id = ?",
+                )?
+                .query_row([id], |row| {
+                    Ok(Issue {
+                        id: row.get(0)?,
+                        app_id: row.get(1)?,
+                        fingerprint: row.get(2)?,
+                        title: row.get(3)?,
+                        first_seen: row.get(4)?,
+                        last_seen: row.get(5)?,
+                        crash_type: crash_type(row, 6)?,
+                    })
+                })
+                .optional()?
+                .ok_or_else(|| NotFound("issue").into())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn find(&self, app_id: i64, fingerprint: String) -> Result<Option<Issue>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT id, app_id, fingerprint, title, first_seen, last_seen, crash_type
+                    FROM issues WHERE app_id = ? AND fingerprint = ?",
+                )?
+                .query_row(params![app_id, fingerprint], |row| {
+                    Ok(Issue {
+                        id: row.get(0)?,
+                        app_id: row.get(1)?,
+                        fingerprint: row.get(2)?,
+                        title: row.get(3)?,
+                        first_seen: row.get(4)?,
+                        last_seen: row.get(5)?,
+                        crash_type: crash_type(row, 6)?,
+                    })
+                })
+                .optional()
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_by_app(
+        &self,
+        app_id: i64,
+        crash_type: CrashType,
+        environment: Option<String>,
+    ) -> Result<Vec<IssueSummary>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                    FROM issues i
+                    JOIN apps a ON a.id = i.app_id
+                    LEFT JOIN issue_reports r
+                        ON r.issue_id = i.id AND r.environment = COALESCE(?3, r.environment)
+                    WHERE i.app_id = ?1 AND i.crash_type = ?2
+                    GROUP BY i.id
+                    HAVING ?3 IS NULL OR COUNT(r.id) > 0
+                    ORDER BY i.last_seen DESC, i.id DESC",
+                )?
+                .query_map(
+                    params![app_id, crash_type.as_str(), environment],
+                    map_issue_summary,
+                )?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_new(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+    ) -> Result<Vec<IssueSummary>> {
+        let since = time_ago(period);
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                    FROM issues i
+                    JOIN apps a ON a.id = i.app_id
+                    LEFT JOIN issue_reports r
+                        ON r.issue_id = i.id AND r.environment = COALESCE(?2, r.environment)
+                    WHERE i.first_seen >= datetime('now', ?1)
+                    GROUP BY i.id
+                    HAVING ?2 IS NULL OR COUNT(r.id) > 0
+                    ORDER BY i.first_seen DESC",
+                )?
+                .query_map(params![since, environment], map_issue_summary)?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_top(
+        &self,
+        period: Duration,
+        environment: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<IssueSummary>> {
+        let since = time_ago(period);
+
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT i.id, i.app_id, a.name, i.title, COUNT(r.id)
+                    FROM issues i
+                    JOIN apps a ON a.id = i.app_id
+                    JOIN issue_reports r ON r.issue_id = i.id
+                    WHERE r.received_at >= datetime('now', ?1)
+                        AND r.environment = COALESCE(?2, r.environment)
+                    GROUP BY i.id
+                    ORDER BY 5 DESC
+                    LIMIT ?3",
+                )?
+                .query_map(params![since, environment, limit], map_issue_summary)?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_empty(&self) -> Result<u64> {
+        self.pool
+            .run(|conn| {
+                let deleted = conn.execute(
+                    "DELETE FROM issues
+                    WHERE NOT EXISTS (SELECT 1 FROM issue_reports r WHERE r.issue_id = issues.id)",
+                    [],
+                )?;
+                Ok(deleted as u64)
+            })
+            .await
+    }
+}
+
+fn map_issue_summary(row: &rusqlite::Row<'_>) -> rusqlite::Result<IssueSummary> {
+    Ok(IssueSummary {
+        id: row.get(0)?,
+        app_id: row.get(1)?,
+        app_name: row.get(2)?,
+        title: row.get(3)?,
+        count: row.get(4)?,
+    })
+}
+
+pub(super) struct NotificationRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl NotificationRepository for NotificationRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn get(&self, user_id: i64) -> Result<Option<NotificationSettings>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT user_id, email, immediate, digest
+                    FROM notification_settings WHERE user_id = ?",
+                )?
+                .query_row([user_id], map_notification_settings)
+                .optional()
+                .map_err(Into::into)
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, settings: NotificationSettings, apps: Vec<i64>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                tx.execute(
+                    "INSERT INTO notification_settings(user_id, email, immediate, digest)
+                    VALUES (?,?,?,?)
+                    ON CONFLICT (user_id) DO UPDATE SET
+                        email = excluded.email,
+                        immediate = excluded.immediate,
+                        digest = excluded.digest",
+                    params![
+                        settings.user_id,
+                        settings.email,
+                        settings.immediate,
+                        settings.digest,
+                    ],
+                )?;
+                tx.execute(
+                    "DELETE FROM notification_apps WHERE user_id = ?",
+                    [settings.user_id],
+                )?;
+
+                {
+                    let mut insert = tx.prepare(
+                        "INSERT INTO notification_apps(user_id, app_id) VALUES (?,?)
+                        ON CONFLICT DO NOTHING",
+                    )?;
+                    for app_id in apps {
+                        insert.execute([settings.user_id, app_id])?;
+                    }
+                }
+
+                tx.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_apps(&self, user_id: i64) -> Result<Vec<i64>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT app_id FROM notification_apps WHERE user_id = ? ORDER BY app_id",
+                )?
+                .query_map([user_id], |row| row.get(0))?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_immediate(&self, app_id: i64) -> Result<Vec<NotificationSettings>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT n.user_id, n.email, n.immediate, n.digest
+                    FROM notification_settings n
+                    JOIN notification_apps a ON a.user_id = n.user_id
+                    WHERE n.immediate = 1 AND a.app_id = ?",
+                )?
+                .query_map([app_id], map_notification_settings)?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list_digest(&self) -> Result<Vec<NotificationSettings>> {
+        self.pool
+            .run(|conn| {
+                conn.prepare(
+                    "SELECT user_id, email, immediate, digest
+                    FROM notification_settings WHERE digest = 1",
+                )?
+                .query_map([], map_notification_settings)?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+}
+
+pub(super) struct TagRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl TagRepository for TagRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list_keys(&self, app_id: i64) -> Result<Vec<TagKey>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT source, key FROM tag_keys
+                    WHERE app_id = ?
+                    ORDER BY source, key",
+                )?
+                .query_map([app_id], |row| {
+                    Ok(TagKey {
+                        source: tag_source(row, 0)?,
+                        key: row.get(1)?,
+                    })
+                })?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_keys(&self, app_id: i64, keys: Vec<TagKey>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+                replace_tag_keys(&tx, app_id, keys)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_report_tags(&self, app_id: i64, report_id: String, tags: Vec<Tag>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+
+                tx.execute(
+                    "DELETE FROM report_tags WHERE app_id = ? AND report_id = ?",
+                    params![app_id, report_id],
+                )?;
+                {
+                    let mut insert = tx.prepare(
+                        "INSERT INTO report_tags(app_id, report_id, key, value) VALUES (?,?,?,?)
+                        ON CONFLICT DO NOTHING",
+                    )?;
+                    for tag in tags {
+                        insert.execute(params![app_id, report_id, tag.key, tag.value])?;
+                    }
+                }
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn distribution(
+        &self,
+        issue_id: i64,
+        environment: Option<String>,
+    ) -> Result<Vec<TagCount>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT t.key, t.value, COUNT(DISTINCT r.report_id) AS count
+                    FROM issue_reports r
+                    JOIN versions v ON v.id = r.version_id
+                    JOIN report_tags t ON t.app_id = v.app_id AND t.report_id = r.report_id
+                    WHERE r.issue_id = ? AND r.environment = COALESCE(?, r.environment)
+                    GROUP BY t.key, t.value
+                    ORDER BY t.key, count DESC, t.value",
+                )?
+                .query_map(params![issue_id, environment], |row| {
+                    Ok(TagCount {
+                        key: row.get(0)?,
+                        value: row.get(1)?,
+                        count: row.get(2)?,
+                    })
+                })?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+}
+
+pub(super) struct EnvironmentRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl EnvironmentRepository for EnvironmentRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list_rules(&self, app_id: i64) -> Result<Vec<EnvironmentRule>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT source, key, value, environment FROM environment_rules
+                    WHERE app_id = ?
+                    ORDER BY position",
+                )?
+                .query_map([app_id], |row| {
+                    Ok(EnvironmentRule {
+                        key: TagKey {
+                            source: tag_source(row, 0)?,
+                            key: row.get(1)?,
+                        },
+                        value: row.get(2)?,
+                        environment: row.get(3)?,
+                    })
+                })?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save_rules(&self, app_id: i64, rules: Vec<EnvironmentRule>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+                replace_environment_rules(&tx, app_id, rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn list(&self, app_id: i64) -> Result<Vec<String>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT DISTINCT r.environment
+                    FROM reports r
+                    JOIN versions v ON v.id = r.version_id
+                    WHERE v.app_id = ?
+                    ORDER BY r.environment",
+                )?
+                .query_map([app_id], |row| row.get(0))?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+}
+
+pub(super) struct FilterRepositoryImpl {
+    pub(super) pool: SqlitePool,
+}
+
+#[async_trait]
+impl FilterRepository for FilterRepositoryImpl {
+    #[instrument(skip_all)]
+    async fn list(&self, app_id: i64) -> Result<Vec<InboundFilter>> {
+        self.pool
+            .run(move |conn| {
+                conn.prepare(
+                    "SELECT rule, dropped FROM inbound_filters
+                    WHERE app_id = ?
+                    ORDER BY position",
+                )?
+                .query_map([app_id], |row| {
+                    Ok(InboundFilter {
+                        rule: row.get(0)?,
+                        dropped: row.get(1)?,
+                    })
+                })?
+                .map(|row| row.map_err(Into::into))
+                .collect()
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn save(&self, app_id: i64, rules: Vec<String>) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                let tx = conn.transaction()?;
+                replace_inbound_filters(&tx, app_id, rules)?;
+                tx.commit()?;
+
+                Ok(())
+            })
+            .await
+    }
+
+    #[instrument(skip_all)]
+    async fn record_drop(&self, app_id: i64, rule: String) -> Result<()> {
+        self.pool
+            .run(move |conn| {
+                conn.execute(
+                    "UPDATE inbound_filters SET dropped = dropped + 1
+                    WHERE app_id = ? AND rule = ?",
+                    params![app_id, rule],
+                )?;
+
+                Ok(())
+            })
+            .await
+    }
+}
+
+fn replace_tag_keys(conn: &Connection, app_id: i64, keys: Vec<TagKey>) -> Result<()> {
+    conn.execute("DELETE FROM tag_keys WHERE app_id = ?", [app_id])?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO tag_keys(app_id, source, key) VALUES (?,?,?)
+        ON CONFLICT DO NOTHING",
+    )?;
+    for key in keys {
+        insert.execute(params![app_id, key.source.as_str(), key.key])?;
+    }
+
+    Ok(())
+}
+
+fn replace_environment_rules(
+    conn: &Connection,
+    app_id: i64,
+    rules: Vec<EnvironmentRule>,
+) -> Result<()> {
+    conn.execute("DELETE FROM environment_rules WHERE app_id = ?", [app_id])?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO environment_rules(app_id, position, source, key, value, environment)
+        VALUES (?,?,?,?,?,?)",
+    )?;
+    for (position, rule) in (0_i64..).zip(rules) {
+        insert.execute(params![
+            app_id,
+            position,
+            rule.key.source.as_str(),
+            rule.key.key,
+            rule.value,
+            rule.environment,
+        ])?;
+    }
+
+    Ok(())
+}
+
+fn replace_inbound_filters(conn: &Connection, app_id: i64, rules: Vec<String>) -> Result<()> {
+    // Mark all filters as removed, and remove the ones that aren't saved again.
+    conn.execute(
+        "UPDATE inbound_filters SET position = -1 WHERE app_id = ?",
+        [app_id],
+    )?;
+    {
+        let mut upsert = conn.prepare(
+            "INSERT INTO inbound_filters(app_id, position, rule) VALUES (?,?,?)
+            ON CONFLICT (app_id, rule) DO UPDATE SET position = excluded.position",
+        )?;
+        for (position, rule) in (0_i64..).zip(rules) {
+            upsert.execute(params![app_id, position, rule])?;
+        }
+    }
+    conn.execute(
+        "DELETE FROM inbound_filters WHERE app_id = ? AND position < 0",
+        [app_id],
+    )?;
+
+    Ok(())
+}
+
+fn map_notification_settings(row: &rusqlite::Row<'_>) -> rusqlite::Result<NotificationSettings> {
+    Ok(NotificationSettings {
+        user_id: row.get(0)?,
+        email: row.get(1)?,
+        immediate: row.get(2)?,
+        digest: row.get(3)?,
+    })
+}
@@ -0,0 +1,418 @@
+//! Parsing of the system dropbox entries that ACRA collects in the `DROPBOX` field.
+//!
+//! The field is an object of dropbox tags, each with the text of all entries recorded shortly
+//! before the report was created:
+//!
+//! ```text
+//! @2024-03-02 14:21:07
+//! Text: Process: com.example.notes
+//! ...
+//! @2024-03-02 14:25:51
+//! Not Text!
+//! ```
+//!
+//! Native crashes and tombstones are turned into backtraces, ANRs into thread dumps. All other
+//! entries are kept as plain text.
+
+use serde_json::Value;
+
+use crate::symbols::SourceFrame;
+
+/// A single dropbox entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// dropbox tag, like `data_app_native_crash`.
+    pub tag: String,
+    /// Time the entry was recorded, as formatted by ACRA.
+    pub time: String,
+    pub content: Content,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    NativeCrash(NativeCrash),
+    Anr(Anr),
+    /// Any other entry, kept as is.
+    Text(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NativeCrash {
+    /// Name of the crashed process, which is the package name for apps.
+    pub process: Option<String>,
+    pub thread: Option<String>,
+    /// Received signal, like `signal 11 (SIGSEGV), code 1 (SEGV_MAPERR), fault addr 0x0`.
+    pub signal: Option<String>,
+    pub abort_message: Option<String>,
+    /// Backtrace of the crashed thread, innermost frame first.
+    pub frames: Vec<NativeFrame>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeFrame {
+    /// Program counter, relative to the start of the library.
+    pub pc: u64,
+    /// Path of the library or executable, like `/system/lib64/libc.so`.
+    pub library: String,
+    /// Function and offset as resolved on the device, like `abort+164`.
+    pub symbol: Option<String>,
+    pub build_id: Option<String>,
+    /// Source frames resolved with an uploaded debug file, innermost first. Empty if the frame
+    /// wasn't symbolicated.
+    pub resolved: Vec<SourceFrame>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Anr {
+    /// Name of the process that stopped responding, which is the package name for apps.
+    pub process: Option<String>,
+    /// Reason of the ANR, like `Input dispatching timed out (...)`.
+    pub subject: Option<String>,
+    pub threads: Vec<ThreadDump>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadDump {
+    pub name: String,
+    /// Like `Runnable`, `Blocked` or `Native`.
+    pub state: String,
+    /// Frames like `at com.example.Foo.bar(Foo.kt:12)`, mixed with lock information like
+    /// `- waiting to lock <0x0c4f3e21>`.
+    pub lines: Vec<String>,
+}
+
+impl Entry {
+    /// Whether the entry is about the given process, instead of another app or a system service.
+    pub fn is_process(&self, process: &str) -> bool {
+        let name = match &self.content {
+            Content::NativeCrash(crash) => crash.process.as_deref(),
+            Content::Anr(anr) => anr.process.as_deref(),
+            Content::Text(_) => None,
+        };
+
+        name == Some(process)
+    }
+}
+
+impl NativeCrash {
+    /// Short name of the signal, like `SIGSEGV`.
+    pub fn signal_name(&self) -> Option<&str> {
+        let signal = self.signal.as_deref()?;
+        let (_, rest) = signal.split_once('(')?;
+        rest.split_once(')').map(|(name, _)| name)
+    }
+}
+
+impl NativeFrame {
+    /// File name of the library, without its directory.
+    pub fn library_name(&self) -> &str {
+        self.library
+            .rsplit_once('/')
+            .map_or(&self.library, |(_, name)| name)
+    }
+
+    /// Resolved function, without the offset.
+    pub fn function(&self) -> Option<&str> {
+        let symbol = self.symbol.as_deref()?;
+        Some(
+            symbol
+                .rsplit_once('+')
+                .map_or(symbol, |(function, _)| function),
+        )
+    }
+}
+
+impl Anr {
+    pub fn main_thread(&self) -> Option<&ThreadDump> {
+        self.threads.iter().find(|thread| thread.name == "main")
+    }
+}
+
+/// Parse all entries of the `DROPBOX` field. Anything that isn't an object of texts is ignored.
+pub fn parse(dropbox: &Value) -> Vec<Entry> {
+    let Some(tags) = dropbox.as_object() else {
+        return Vec::new();
+    };
+
+    tags.iter()
+        .filter_map(|(tag, text)| Some((tag, text.as_str()?)))
+        .flat_map(|(tag, text)| {
+            split_entries(text).map(move |(time, text)| Entry {
+                tag: tag.clone(),
+                time: time.to_owned(),
+                content: parse_content(tag, text),
+            })
+        })
+        .collect()
+}
+
+/// Split the text of a single tag into the time and text of each entry. Entries without text are
+/// skipped.
+fn split_entries(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    text.split("\n@").filter_map(|entry| {
+        let entry = entry.strip_prefix('@').unwrap_or(entry);
+        let (time, text) = entry.split_once('\n')?;
+        Some((time.trim(), text.strip_prefix("Text: ")?))
+    })
+}
+
+fn parse_content(tag: &str, text: &str) -> Content {
+    let tag = tag.to_ascii_lowercase();
+
+    if tag.ends_with("native_crash") || tag.contains("tombstone") {
+        Content::NativeCrash(parse_native_crash(text))
+    } else if tag.ends_with("_anr") {
+        Content::Anr(parse_anr(text))
+    } else {
+        Content::Text(text.to_owned())
+    }
+}
+
+fn parse_native_crash(text: &str) -> NativeCrash {
+    let mut crash = NativeCrash::default();
+    // Tombstones list the backtraces of all threads, but only the first one is the crashed thread.
+    let mut backtrace_done = false;
+
+    for line in text.lines().map(str::trim) {
+        if let Some(frame) = parse_native_frame(line) {
+            if !backtrace_done {
+                crash.frames.push(frame);
+            }
+            continue;
+        }
+        backtrace_done |= !crash.frames.is_empty();
+
+        if let Some(process) = line.strip_prefix("Process: ") {
+            crash.process.get_or_insert_with(|| process.to_owned());
+        } else if line.starts_with("pid: ") && crash.thread.is_none() {
+            // Like `pid: 8123, tid: 8167, name: RenderThread  >>> com.example.notes <<<`.
+            let (thread, process) = line.split_once(">>>").unwrap_or((line, ""));
+            crash.thread = thread
+                .split_once("name: ")
+                .map(|(_, name)| name.trim().to_owned());
+
+            let process = process.trim_end_matches("<<<").trim();
+            if !process.is_empty() {
+                crash.process.get_or_insert_with(|| process.to_owned());
+            }
+        } else if line.starts_with("signal ") {
+            crash.signal.get_or_insert_with(|| line.to_owned());
+        } else if let Some(message) = line.strip_prefix("Abort message: ") {
+            crash
+                .abort_message
+                .get_or_insert_with(|| message.trim_matches('\'').to_owned());
+        }
+    }
+
+    crash
+}
+
+/// Parse a backtrace line like
+/// `#01 pc 00000000000b1b30  /system/lib64/libc.so (__start_thread+64) (BuildId: 2e3d8f)`.
+fn parse_native_frame(line: &str) -> Option<NativeFrame> {
+    let rest = line.strip_prefix('#')?;
+    let (index, rest) = rest.split_once(' ')?;
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let rest = rest.trim_start().strip_prefix("pc ")?.trim_start();
+    let (pc, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let pc = u64::from_str_radix(pc, 16).ok()?;
+
+    let rest = rest.trim();
+    let (library, groups_text) = rest.find(" (").map_or((rest, ""), |i| rest.split_at(i));
+
+    let mut frame = NativeFrame {
+        pc,
+        library: library.to_owned(),
+        symbol: None,
+        build_id: None,
+        resolved: Vec::new(),
+    };
+
+    for group in groups(groups_text) {
+        if let Some(build_id) = group.strip_prefix("BuildId: ") {
+            frame.build_id = Some(build_id.to_owned());
+        } else if !group.starts_with("offset ") && group != "deleted" {
+            frame.symbol.get_or_insert_with(|| group.to_owned());
+        }
+    }
+
+    Some(frame)
+}
+
+/// Top level parenthesized groups of a text, without the parentheses. Symbols can contain
+/// parentheses themselves, like `render_note(Note const*)+48`.
+fn groups(text: &str) -> Vec<&str> {
+    let mut groups = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => {
+                if depth == 0 {
+                    start = i + 1;
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    groups.push(&text[start..i]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    groups
+}
+
+fn parse_anr(text: &str) -> Anr {
+    let mut anr = Anr::default();
+    let mut thread = None::<ThreadDump>;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+
+        if let Some(header) = trimmed.strip_prefix('"') {
+            // Like `"main" prio=5 tid=1 Blocked`.
+            anr.threads.extend(thread.take());
+
+            let (name, rest) = header.split_once('"').unwrap_or((header, ""));
+            thread = Some(ThreadDump {
+                name: name.to_owned(),
+                state: rest
+                    .split_whitespace()
+                    .last()
+                    .unwrap_or_default()
+                    .to_owned(),
+                lines: Vec::new(),
+            });
+        } else if let Some(current) = &mut thread {
+            if trimmed.is_empty() {
+                anr.threads.extend(thread.take());
+            } else if trimmed.starts_with("at ")
+                || trimmed.starts_with("- ")
+                || trimmed.starts_with("native: ")
+            {
+                current.lines.push(trimmed.to_owned());
+            }
+        } else if let Some(process) = trimmed.strip_prefix("Process: ") {
+            anr.process.get_or_insert_with(|| process.to_owned());
+        } else if let Some(subject) = trimmed.strip_prefix("Subject: ") {
+            anr.subject.get_or_insert_with(|| subject.to_owned());
+        }
+    }
+
+    anr.threads.extend(thread);
+    anr
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Content, Entry, parse};
+
+    fn fixture() -> Vec<Entry> {
+        let dropbox =
+            serde_json::from_str(include_str!("../fixtures/dropbox/dropbox.json")).unwrap();
+        parse(&dropbox)
+    }
+
+    fn entry<'a>(entries: &'a [Entry], tag: &str) -> &'a Content {
+        &entries
+            .iter()
+            .find(|entry| entry.tag == tag)
+            .unwrap()
+            .content
+    }
+
+    #[test]
+    fn parse_native_crash() {
+        let entries = fixture();
+        let Content::NativeCrash(crash) = entry(&entries, "data_app_native_crash") else {
+            panic!("not a native crash");
+        };
+
+        assert_eq!(Some("com.example.notes"), crash.process.as_deref());
+        assert_eq!(Some("RenderThread"), crash.thread.as_deref());
+        assert_eq!(Some("SIGSEGV"), crash.signal_name());
+        // Only the backtrace of the crashed thread.
+        assert_eq!(5, crash.frames.len());
+
+        let frame = &crash.frames[0];
+        assert_eq!(0x4f1a0, frame.pc);
+        assert_eq!("libnotes.so", frame.library_name());
+        assert_eq!(Some("render_note(Note const*)+48"), frame.symbol.as_deref());
+        assert_eq!(Some("render_note(Note const*)"), frame.function());
+        assert_eq!(
+            Some("6f1e2d3c4b5a69788796a5b4c3d2e1f0a9b8c7d6"),
+            frame.build_id.as_deref()
+        );
+
+        let frame = &crash.frames[3];
+        assert_eq!("/system/framework/arm64/boot-framework.oat", frame.library);
+        assert_eq!(
+            Some("android.view.ThreadedRenderer.draw+92"),
+            frame.symbol.as_deref()
+        );
+        assert_eq!(None, frame.build_id);
+    }
+
+    #[test]
+    fn parse_tombstone_and_anr() {
+        let entries = fixture();
+
+        let Content::NativeCrash(crash) = entry(&entries, "SYSTEM_TOMBSTONE") else {
+            panic!("not a native crash");
+        };
+        assert_eq!(Some("/system/bin/surfaceflinger"), crash.process.as_deref());
+        assert_eq!(Some("SIGABRT"), crash.signal_name());
+        assert_eq!(
+            Some("Check failed: layer != nullptr"),
+            crash.abort_message.as_deref()
+        );
+        assert_eq!(None, crash.frames[1].symbol);
+
+        let Content::Anr(anr) = entry(&entries, "data_app_anr") else {
+            panic!("not an ANR");
+        };
+        assert_eq!(Some("com.example.notes"), anr.process.as_deref());
+        assert!(
+            anr.subject
+                .as_deref()
+                .unwrap()
+                .starts_with("Input dispatching timed out")
+        );
+        assert_eq!(2, anr.threads.len());
+
+        let main = anr.main_thread().unwrap();
+        assert_eq!("Blocked", main.state);
+        assert_eq!(4, main.lines.len());
+        assert!(main.lines[1].starts_with("- waiting to lock"));
+
+        // Entries without text are left out.
+        assert_eq!(3, entries.len());
+        assert!(
+            entries
+                .iter()
+                .filter(|e| e.is_process("com.example.notes"))
+                .count()
+                == 2
+        );
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert!(parse(&json!("text")).is_empty());
+        assert!(parse(&json!({ "data_app_crash": 1 })).is_empty());
+
+        let entries = parse(&json!({ "data_app_crash": "@2024-03-02 14:21:07\nText: boom\n" }));
+        assert_eq!(Content::Text("boom\n".to_owned()), entries[0].content);
+        assert_eq!("2024-03-02 14:21:07", entries[0].time);
+    }
+}
@@ -0,0 +1,185 @@
+//! Environments that reports are sent from, like `production` or `development`, so crashes of
+//! developers' debug builds don't mix with the ones of users.
+//!
+//! Each app can configure rules, that map a value of `CUSTOM_DATA` or `BUILD_CONFIG` to an
+//! environment, written like `BUILD_CONFIG.FLAVOR:staging = staging`. The first matching rule
+//! wins. Without any matching rule, debug builds with `BUILD_CONFIG.DEBUG` set are in the
+//! `development` environment and everything else is in `production`. Flavors are deliberately
+//! left out, as many of them aren't environments, like `free` and `paid`, and guessing wrong
+//! would hide crashes of released builds from the production views.
+
+use serde_json::Value;
+
+use crate::{db::models::EnvironmentRule, report::Report, tags};
+
+pub const PRODUCTION: &str = "production";
+pub const DEVELOPMENT: &str = "development";
+/// Selection in views that shows the reports of all environments.
+pub const ALL: &str = "all";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("invalid environment rule `{0}`, expected <key>:<value> = <environment>")]
+    Rule(String),
+    #[error(transparent)]
+    Key(#[from] tags::ParseError),
+    #[error("invalid environment `{0}`, expected lowercase letters, digits, `-` or `_`")]
+    Name(String),
+}
+
+/// Environment of a report, as picked by the rules or else by whether it's a debug build.
+pub fn derive(rules: &[EnvironmentRule], report: &Report) -> String {
+    let rule = rules
+        .iter()
+        .find(|rule| tags::value(report, &rule.key).is_some_and(|value| value == rule.value));
+    if let Some(rule) = rule {
+        return rule.environment.clone();
+    }
+
+    if report.build_config.get("DEBUG") == Some(&Value::Bool(true)) {
+        DEVELOPMENT
+    } else {
+        PRODUCTION
+    }
+    .to_owned()
+}
+
+/// Whether the name is valid for an environment, which must be usable in a link as is.
+pub fn is_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// Environment that views filter by, from the selection in the view, or `None` to show all of
+/// them. Without a selection, only production reports are shown.
+pub fn filter(selection: Option<&str>) -> Result<Option<String>, ParseError> {
+    match selection.map_or(PRODUCTION, str::trim) {
+        ALL => Ok(None),
+        name if is_valid(name) => Ok(Some(name.to_owned())),
+        name => Err(ParseError::Name(name.to_owned())),
+    }
+}
+
+/// Environments to pick from in views, out of the ones that an app received reports from.
+/// Production is always there, as it's selected by default.
+pub fn choices(mut received: Vec<String>) -> Vec<String> {
+    if !received.iter().any(|name| name == PRODUCTION) {
+        received.push(PRODUCTION.to_owned());
+        received.sort();
+    }
+    received
+}
+
+/// Format a rule like `BUILD_CONFIG.FLAVOR:staging = staging`, the way it's configured.
+pub fn format_rule(rule: &EnvironmentRule) -> String {
+    format!(
+        "{}:{} = {}",
+        tags::format_key(&rule.key),
+        rule.value,
+        rule.environment
+    )
+}
+
+/// Parse rules as formatted by [`format_rule`], one per line. Empty lines are skipped.
+pub fn parse_rules(text: &str) -> Result<Vec<EnvironmentRule>, ParseError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let invalid = || ParseError::Rule(line.to_owned());
+            let (key, environment) = line.rsplit_once('=').ok_or_else(invalid)?;
+            let (key, value) = key.split_once(':').ok_or_else(invalid)?;
+            let environment = environment.trim();
+            let value = value.trim();
+            // `all` is taken by the selection of all environments in views.
+            if value.is_empty() || !is_valid(environment) || environment == ALL {
+                return Err(invalid());
+            }
+
+            Ok(EnvironmentRule {
+                key: tags::parse_keys(key)?.pop().ok_or_else(invalid)?,
+                value: value.to_owned(),
+                environment: environment.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::{DEVELOPMENT, PRODUCTION, derive, filter, format_rule, is_valid, parse_rules};
+    use crate::{fixtures, report::Report};
+
+    fn report(build_config: &Value) -> Report {
+        fixtures::report(|report| report["BUILD_CONFIG"] = build_config.clone())
+    }
+
+    #[test]
+    fn rules() {
+        let rules = parse_rules(
+            "BUILD_CONFIG.FLAVOR:staging = staging\n\n custom_data.channel : internal=dogfood \n",
+        )
+        .unwrap();
+
+        assert_eq!(2, rules.len());
+        assert_eq!(
+            "BUILD_CONFIG.FLAVOR:staging = staging",
+            format_rule(&rules[0])
+        );
+        assert_eq!(
+            "CUSTOM_DATA.channel:internal = dogfood",
+            format_rule(&rules[1])
+        );
+
+        assert!(parse_rules("FLAVOR:staging = staging").is_err());
+        assert!(parse_rules("BUILD_CONFIG.FLAVOR = staging").is_err());
+        assert!(parse_rules("BUILD_CONFIG.FLAVOR: = staging").is_err());
+        assert!(parse_rules("BUILD_CONFIG.FLAVOR:staging = Staging Env").is_err());
+        assert!(parse_rules("BUILD_CONFIG.FLAVOR:staging = all").is_err());
+        assert!(is_valid("qa-1") && !is_valid("") && !is_valid("QA"));
+
+        assert_eq!(Some(PRODUCTION.to_owned()), filter(None).unwrap());
+        assert_eq!(
+            Some(DEVELOPMENT.to_owned()),
+            filter(Some("development")).unwrap()
+        );
+        assert_eq!(None, filter(Some("all")).unwrap());
+        assert!(filter(Some("Debug Builds")).is_err());
+    }
+
+    #[test]
+    fn derive_environment() {
+        let rules = parse_rules(
+            "BUILD_CONFIG.FLAVOR:staging = staging\nBUILD_CONFIG.BUILD_TYPE:beta = beta",
+        )
+        .unwrap();
+
+        let release = json!({ "BUILD_TYPE": "release", "DEBUG": false, "FLAVOR": "" });
+        assert_eq!(PRODUCTION, derive(&rules, &report(&release)));
+        assert_eq!(PRODUCTION, derive(&[], &report(&json!({}))));
+
+        let debug = json!({ "BUILD_TYPE": "debug", "DEBUG": true, "FLAVOR": "" });
+        assert_eq!(DEVELOPMENT, derive(&rules, &report(&debug)));
+        assert_eq!(DEVELOPMENT, derive(&[], &report(&json!({ "DEBUG": true }))));
+        // Only the flag counts, as build types can be named anything.
+        let build_type = json!({ "BUILD_TYPE": "debug", "DEBUG": false });
+        assert_eq!(PRODUCTION, derive(&[], &report(&build_type)));
+
+        // Rules come first, in the order they are configured.
+        let staging = json!({ "BUILD_TYPE": "beta", "DEBUG": true, "FLAVOR": "staging" });
+        assert_eq!("staging", derive(&rules, &report(&staging)));
+        let beta = json!({ "BUILD_TYPE": "beta", "FLAVOR": "free" });
+        assert_eq!("beta", derive(&rules, &report(&beta)));
+
+        // Flavors only pick the environment through a rule, and release builds of any other
+        // flavor stay in production.
+        let free = json!({ "BUILD_TYPE": "release", "DEBUG": false, "FLAVOR": "free" });
+        assert_eq!(PRODUCTION, derive(&rules, &report(&free)));
+        let qa = json!({ "BUILD_TYPE": "debug", "DEBUG": true, "FLAVOR": "qaServer" });
+        assert_eq!(DEVELOPMENT, derive(&rules, &report(&qa)));
+    }
+}
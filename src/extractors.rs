@@ -1,31 +1,58 @@
-use std::sync::Arc;
+use std::{
+    convert::Infallible,
+    error::Error,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use axum::{
-    body::Body,
-    extract::{FromRef, FromRequestParts},
+    Json, RequestExt,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, FromRef, FromRequest, FromRequestParts, Request},
     http::{
-        StatusCode,
-        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
         request::Parts,
     },
     response::{IntoResponse, Response},
 };
 use axum_extra::{TypedHeader, typed_header::TypedHeaderRejection};
 use headers::{Authorization, authorization::Basic};
+use http_body_util::{BodyExt, LengthLimitError};
+use multer::{Constraints, Multipart, SizeLimit};
+use serde_json::Value;
+use subtle::ConstantTimeEq;
+use tokio::{fs::File, io::AsyncWriteExt};
+use tracing::{error, warn};
 
-use crate::settings::Auth;
+use crate::{
+    db::{
+        DbConnPool,
+        models::App,
+        repositories::{self, NotFound},
+    },
+    server::PeerAddr,
+    settings::{IpNet, Server},
+};
 
-pub struct User(Basic);
+/// The app that a client authenticated as, with the credentials from `app add` or
+/// `app rotate-credentials`.
+pub struct User(pub App);
 
 impl User {
     pub fn username(&self) -> &str {
-        self.0.username()
+        &self.0.username
     }
 }
 
 impl<S> FromRequestParts<S> for User
 where
-    Arc<Auth>: FromRef<S>,
+    DbConnPool: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AuthRejection;
@@ -35,15 +62,27 @@ where
             return Err(Self::Rejection::InvalidCredentials);
         }
 
-        let settings = Arc::<Auth>::from_ref(state);
         let TypedHeader(Authorization(header)) =
             TypedHeader::<Authorization<Basic>>::from_request_parts(parts, state).await?;
 
-        if header.username() != settings.username || header.password() != settings.password {
+        let pool = DbConnPool::from_ref(state);
+        let app = repositories::app_repo(pool)
+            .get_by_username(header.username().to_owned())
+            .await
+            .map_err(|e| {
+                if e.is::<NotFound>() {
+                    Self::Rejection::InvalidCredentials
+                } else {
+                    Self::Rejection::Database(e)
+                }
+            })?;
+
+        // Compare in constant time, to not leak how much of the password was guessed right.
+        if !bool::from(header.password().as_bytes().ct_eq(app.password.as_bytes())) {
             return Err(Self::Rejection::InvalidCredentials);
         }
 
-        Ok(Self(header))
+        Ok(Self(app))
     }
 }
 
@@ -51,6 +90,8 @@ where
 pub enum AuthRejection {
     TypedHeaderRejection(TypedHeaderRejection),
     InvalidCredentials,
+    /// The credentials couldn't be checked, as loading the app failed.
+    Database(anyhow::Error),
 }
 
 impl IntoResponse for AuthRejection {
@@ -62,6 +103,10 @@ impl IntoResponse for AuthRejection {
                 .header(WWW_AUTHENTICATE, "Basic")
                 .body(Body::empty())
                 .unwrap(),
+            Self::Database(e) => {
+                error!("failed loading app credentials: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
         }
     }
 }
@@ -71,3 +116,486 @@ impl From<TypedHeaderRejection> for AuthRejection {
         Self::TypedHeaderRejection(value)
     }
 }
+
+/// Maximum size of a report request, including all of its attachments.
+pub const REPORT_LIMIT: usize = 1024 * 512;
+
+/// A report as sent by ACRA, together with all attached files.
+///
+/// Reports are usually sent as plain JSON. Once attachments are configured, ACRA sends a
+/// `multipart/mixed` request instead, with the report in the `ACRA_REPORT` part and each file in
+/// an `ACRA_ATTACHMENT` part.
+pub struct ReportUpload {
+    pub raw: Value,
+    pub attachments: Vec<Attachment>,
+}
+
+pub struct Attachment {
+    pub name: String,
+    pub data: Bytes,
+}
+
+impl<S> FromRequest<S> for ReportUpload
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let boundary = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(multipart_boundary);
+
+        let Some(boundary) = boundary else {
+            let Json(raw) = Json::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            return Ok(Self {
+                raw,
+                attachments: Vec::new(),
+            });
+        };
+
+        let limit = SizeLimit::new()
+            .whole_stream(REPORT_LIMIT as u64)
+            .per_field(REPORT_LIMIT as u64);
+        let mut multipart = multipart(req, boundary, Constraints::new().size_limit(limit));
+        let mut raw = None;
+        let mut attachments = Vec::new();
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| multipart_rejection(&e))?
+        {
+            match field.name() {
+                Some("ACRA_REPORT") => {
+                    let data = field.bytes().await.map_err(|e| multipart_rejection(&e))?;
+                    raw = Some(serde_json::from_slice(&data).map_err(|e| {
+                        (StatusCode::BAD_REQUEST, format!("invalid report: {e}")).into_response()
+                    })?);
+                }
+                Some("ACRA_ATTACHMENT") => {
+                    let name = field.file_name().unwrap_or_default().to_owned();
+                    let data = field.bytes().await.map_err(|e| multipart_rejection(&e))?;
+                    attachments.push(Attachment { name, data });
+                }
+                _ => {}
+            }
+        }
+
+        let raw =
+            raw.ok_or_else(|| (StatusCode::BAD_REQUEST, "report is missing").into_response())?;
+
+        Ok(Self { raw, attachments })
+    }
+}
+
+/// Maximum size of an uploaded mapping file.
+pub const MAPPING_LIMIT: usize = 1024 * 1024 * 64;
+/// Maximum size of a stack trace to retrace.
+const STACK_TRACE_LIMIT: usize = 1024 * 1024;
+/// Maximum size of a retrace form, with room for both the mapping file and the stack trace.
+pub const RETRACE_LIMIT: usize = MAPPING_LIMIT + STACK_TRACE_LIMIT + 1024;
+
+/// Input of the retrace tool, sent as `multipart/form-data` so it can include a mapping file.
+pub struct RetraceForm {
+    pub stack_trace: String,
+    pub version_id: Option<i64>,
+    /// Content of an uploaded mapping file, used instead of the stored one of the version.
+    pub mapping: Option<String>,
+}
+
+impl<S> FromRequest<S> for RetraceForm
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let boundary = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(multipart_boundary)
+            .ok_or_else(|| {
+                (
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "expected a multipart form",
+                )
+                    .into_response()
+            })?;
+
+        let limit = SizeLimit::new()
+            .whole_stream(RETRACE_LIMIT as u64)
+            .for_field("mapping", MAPPING_LIMIT as u64)
+            .for_field("stack_trace", STACK_TRACE_LIMIT as u64)
+            .per_field(1024);
+        let mut multipart = multipart(req, boundary, Constraints::new().size_limit(limit));
+        let mut form = Self {
+            stack_trace: String::new(),
+            version_id: None,
+            mapping: None,
+        };
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|e| multipart_rejection(&e))?
+        {
+            let name = field.name().unwrap_or_default().to_owned();
+            let value = field.text().await.map_err(|e| multipart_rejection(&e))?;
+
+            match name.as_str() {
+                "stack_trace" => form.stack_trace = value,
+                "version_id" if !value.is_empty() => {
+                    let id = value.parse().map_err(|_| {
+                        (StatusCode::BAD_REQUEST, "invalid version").into_response()
+                    })?;
+                    form.version_id = Some(id);
+                }
+                // Browsers send an empty file if none was picked.
+                "mapping" if !value.is_empty() => form.mapping = Some(value),
+                _ => {}
+            }
+        }
+
+        Ok(form)
+    }
+}
+
+/// Maximum size of an uploaded debug file, which holds the full debug information of a native
+/// library.
+pub const SYMBOLS_LIMIT: usize = 1024 * 1024 * 512;
+
+/// The body of an upload, written to a temporary file as it arrives, so that large files never
+/// have to be held in memory. The file is removed once this is dropped.
+pub struct Upload {
+    path: PathBuf,
+    utf8: bool,
+}
+
+impl Upload {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the content is valid UTF-8 text.
+    pub fn is_utf8(&self) -> bool {
+        self.utf8
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!(path = %self.path.display(), "failed removing upload: {e}");
+        }
+    }
+}
+
+impl<S> FromRequest<S> for Upload
+where
+    S: Send + Sync,
+{
+    type Rejection = UploadRejection;
+
+    async fn from_request(req: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        let mut upload = Self {
+            path: std::env::temp_dir().join(format!(
+                "acralite-upload-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            )),
+            utf8: true,
+        };
+        let mut file = File::create(&upload.path).await?;
+        let mut body = req.into_limited_body();
+        // Bytes at the end of the last chunk that may be the start of a character split across
+        // chunks.
+        let mut partial = Vec::new();
+
+        while let Some(frame) = body.frame().await {
+            let Ok(chunk) = frame?.into_data() else {
+                continue;
+            };
+
+            upload.utf8 = upload.utf8 && continues_utf8(&mut partial, &chunk);
+
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+        upload.utf8 &= partial.is_empty();
+
+        Ok(upload)
+    }
+}
+
+/// Check whether the next chunk of a text continues it as valid UTF-8. Trailing bytes that may be
+/// the start of a character are kept in `partial`, to be completed by the next chunk.
+fn continues_utf8(partial: &mut Vec<u8>, chunk: &[u8]) -> bool {
+    partial.extend_from_slice(chunk);
+
+    match std::str::from_utf8(partial) {
+        Ok(_) => {
+            partial.clear();
+            true
+        }
+        Err(e) if e.error_len().is_none() => {
+            partial.drain(..e.valid_up_to());
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug)]
+pub enum UploadRejection {
+    /// The body exceeds the body limit of the route.
+    TooLarge,
+    Read(axum::Error),
+    /// The temporary file couldn't be written.
+    Write(io::Error),
+}
+
+impl IntoResponse for UploadRejection {
+    fn into_response(self) -> Response {
+        match self {
+            Self::TooLarge => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+            Self::Read(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+            Self::Write(e) => {
+                error!("failed writing upload: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+impl From<axum::Error> for UploadRejection {
+    fn from(value: axum::Error) -> Self {
+        if is_length_limit(&value) {
+            Self::TooLarge
+        } else {
+            Self::Read(value)
+        }
+    }
+}
+
+impl From<io::Error> for UploadRejection {
+    fn from(value: io::Error) -> Self {
+        Self::Write(value)
+    }
+}
+
+/// Read the body of a request as multipart content. Its size is bounded by the body limit of the
+/// route, in addition to the given constraints.
+fn multipart(req: Request, boundary: String, constraints: Constraints) -> Multipart<'static> {
+    Multipart::with_constraints(
+        req.into_limited_body().into_data_stream(),
+        boundary,
+        constraints,
+    )
+}
+
+/// Turn a failure of reading multipart content into a response, telling apart content that is too
+/// large from content that is malformed.
+fn multipart_rejection(e: &multer::Error) -> Response {
+    let too_large = match e {
+        multer::Error::FieldSizeExceeded { .. } | multer::Error::StreamSizeExceeded { .. } => true,
+        // The body limit of the route surfaces as failure to read the body.
+        multer::Error::StreamReadFailed(e) => is_length_limit(e.as_ref()),
+        _ => false,
+    };
+    let status = if too_large {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+
+    (status, e.to_string()).into_response()
+}
+
+/// Whether reading a body failed because it exceeds the body limit of the route.
+fn is_length_limit(e: &(dyn Error + 'static)) -> bool {
+    std::iter::successors(Some(e), |&e| e.source()).any(<dyn Error>::is::<LengthLimitError>)
+}
+
+/// Extract the boundary of a multipart content type, or `None` if it's any other content type.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_owned())
+    })
+}
+
+/// Address of the client that sent the request. If the request was forwarded by a trusted reverse
+/// proxy, this is the address the proxy reported in the `X-Forwarded-For` header.
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    Arc<Server>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let settings = Arc::<Server>::from_ref(state);
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<PeerAddr>>()
+            .map(|info| info.0);
+
+        Ok(Self(resolve_client_ip(
+            peer,
+            &settings.trusted_proxies,
+            &parts.headers,
+        )))
+    }
+}
+
+fn resolve_client_ip(
+    peer: Option<PeerAddr>,
+    trusted_proxies: &[IpNet],
+    headers: &HeaderMap,
+) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    let peer_ip = match peer? {
+        PeerAddr::Tcp(addr) => Some(addr.ip().to_canonical()),
+        PeerAddr::Unix => None,
+    };
+
+    if let Some(ip) = peer_ip.filter(|&ip| !is_trusted(ip)) {
+        return Some(ip);
+    }
+
+    // Each proxy appends the address it received the request from, so walk the list backwards and
+    // stop at the first address that isn't one of our own proxies.
+    let mut forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|ip| ip.trim().parse::<IpAddr>().map(|ip| ip.to_canonical()))
+        .collect::<Vec<_>>();
+    let mut client_ip = peer_ip;
+
+    while let Some(ip) = forwarded.pop() {
+        // A malformed entry can't be trusted, so neither can anything before it.
+        let Ok(ip) = ip else {
+            break;
+        };
+
+        client_ip = Some(ip);
+
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+
+    client_ip
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{continues_utf8, multipart_boundary, resolve_client_ip};
+    use crate::server::PeerAddr;
+
+    fn headers(forwarded_for: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_str(forwarded_for).unwrap(),
+        );
+        headers
+    }
+
+    fn peer(addr: &str) -> PeerAddr {
+        PeerAddr::Tcp(addr.parse::<SocketAddr>().unwrap())
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_header() {
+        let ip = resolve_client_ip(
+            Some(peer("203.0.113.7:1234")),
+            &[],
+            &headers("198.51.100.1"),
+        );
+        assert_eq!(Some("203.0.113.7".parse().unwrap()), ip);
+    }
+
+    #[test]
+    fn trusted_peer_uses_header() {
+        let trusted = ["10.0.0.0/8".parse().unwrap()];
+        let ip = resolve_client_ip(
+            Some(peer("10.1.2.3:1234")),
+            &trusted,
+            &headers("198.51.100.1, 203.0.113.7, 10.0.0.5"),
+        );
+        assert_eq!(Some("203.0.113.7".parse().unwrap()), ip);
+    }
+
+    #[test]
+    fn unix_peer_is_trusted() {
+        let ip = resolve_client_ip(Some(PeerAddr::Unix), &[], &headers("2001:db8::1"));
+        assert_eq!(Some("2001:db8::1".parse().unwrap()), ip);
+    }
+
+    #[test]
+    fn malformed_entry_stops_search() {
+        let trusted = ["::1".parse().unwrap()];
+        let ip = resolve_client_ip(
+            Some(peer("[::1]:1234")),
+            &trusted,
+            &headers("198.51.100.1, garbage"),
+        );
+        assert_eq!(Some("::1".parse().unwrap()), ip);
+    }
+
+    #[test]
+    fn boundary_of_acra_request() {
+        assert_eq!(
+            Some("%&ACRA_REPORT_DIVIDER&%".to_owned()),
+            multipart_boundary("multipart/mixed; boundary=%&ACRA_REPORT_DIVIDER&%")
+        );
+        assert_eq!(
+            Some("abc".to_owned()),
+            multipart_boundary("multipart/form-data; charset=utf-8; boundary=\"abc\"")
+        );
+        assert_eq!(None, multipart_boundary("application/json; charset=utf-8"));
+        assert_eq!(None, multipart_boundary("application/json"));
+    }
+
+    #[test]
+    fn utf8_across_chunks() {
+        let mut partial = Vec::new();
+        let text = "größe".as_bytes();
+
+        assert!(continues_utf8(&mut partial, &text[..3]));
+        assert_eq!(vec![0xc3], partial);
+        assert!(continues_utf8(&mut partial, &text[3..]));
+        assert!(partial.is_empty());
+
+        assert!(!continues_utf8(&mut partial, &[b'a', 0xff, b'b']));
+    }
+}
@@ -0,0 +1,403 @@
+//! Inbound filters that drop unwanted reports before anything of them is stored, like reports from
+//! emulators, very old app versions or crashes in third-party code that can't be fixed.
+//!
+//! Each app configures its filters one per line, like `version_code:..120` or `emulator`. A report
+//! is dropped if any of them matches.
+
+use std::{cell::OnceCell, collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use regex::Regex;
+use tracing::warn;
+
+use crate::{
+    db::{DbConnPool, models::InboundFilter, repositories},
+    report::{Build, Report},
+    stacktrace::{InApp, StackTrace},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error(
+        "unknown filter `{0}`, expected one of version_code, emulator, build_tags, \
+         package_mismatch, android_version, exception or stack_trace"
+    )]
+    Unknown(String),
+    #[error("filter `{0}` needs a value, like `{0}:<value>`")]
+    Value(String),
+    #[error(
+        "invalid range `{0}`, expected a number or a range like `100..200`, `..120` or `130..`"
+    )]
+    Range(String),
+    #[error("invalid regex for the stack trace")]
+    Regex(#[from] regex::Error),
+}
+
+/// Range of numbers, including the start but not the end. Either side can be left open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+}
+
+impl Range {
+    pub fn contains(self, value: u32) -> bool {
+        self.start.is_none_or(|start| value >= start) && self.end.is_none_or(|end| value < end)
+    }
+}
+
+impl FromStr for Range {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::Range(s.to_owned());
+        let bound = |value: &str| {
+            let value = value.trim();
+            (!value.is_empty())
+                .then(|| value.parse().map_err(|_| invalid()))
+                .transpose()
+        };
+
+        let range = if let Some((start, end)) = s.split_once("..") {
+            Self {
+                start: bound(start)?,
+                end: bound(end)?,
+            }
+        } else {
+            let value = bound(s)?.ok_or_else(invalid)?;
+            Self {
+                start: Some(value),
+                end: Some(value.checked_add(1).ok_or_else(invalid)?),
+            }
+        };
+
+        if range.start.is_none() && range.end.is_none() {
+            return Err(invalid());
+        }
+
+        Ok(range)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if end == start + 1 => write!(f, "{start}"),
+            (start, end) => {
+                if let Some(start) = start {
+                    write!(f, "{start}")?;
+                }
+                f.write_str("..")?;
+                if let Some(end) = end {
+                    write!(f, "{end}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A single filter, which drops every report that it matches.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    /// Reports of app versions with a version code in the range.
+    VersionCode(Range),
+    /// Reports from emulators, as told by the build fingerprint and hardware.
+    Emulator,
+    /// Reports from builds with the given tag, like `test-keys` for custom or rooted systems.
+    BuildTags(String),
+    /// Reports of any other package than the given one, like from repackaged copies of the app.
+    PackageMismatch(String),
+    /// Reports from Android versions with a major version in the range.
+    AndroidVersion(Range),
+    /// Reports where the thrown exception or any of its causes is of the given class.
+    Exception(String),
+    /// Reports with a stack trace that the regex matches anywhere.
+    StackTrace(Regex),
+}
+
+/// A report that is checked against filters. Its stack trace is only parsed once, and only if a
+/// filter needs it.
+pub struct Candidate<'a> {
+    report: &'a Report,
+    stack_trace: OnceCell<StackTrace>,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new(report: &'a Report) -> Self {
+        Self {
+            report,
+            stack_trace: OnceCell::new(),
+        }
+    }
+
+    fn stack_trace(&self) -> &StackTrace {
+        // Filters only look at the exception classes, so the frames don't need to be told apart.
+        self.stack_trace
+            .get_or_init(|| StackTrace::parse(&self.report.stack_trace, &InApp::default()))
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, candidate: &Candidate<'_>) -> bool {
+        let report = candidate.report;
+        match self {
+            Self::VersionCode(range) => range.contains(report.app_version_code),
+            Self::Emulator => is_emulator(&report.build),
+            Self::BuildTags(tag) => report.build.tags.split(',').any(|t| t.trim() == tag),
+            Self::PackageMismatch(package_name) => report.package_name != *package_name,
+            Self::AndroidVersion(range) => report
+                .android_version
+                .split('.')
+                .next()
+                .and_then(|major| major.trim().parse().ok())
+                .is_some_and(|major| range.contains(major)),
+            Self::Exception(class) => candidate
+                .stack_trace()
+                .exceptions
+                .iter()
+                .any(|exception| exception.class == *class),
+            Self::StackTrace(regex) => regex.is_match(&report.stack_trace),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s.split_once(':').map_or((s.trim(), None), |(name, value)| {
+            (name.trim(), Some(value.trim()))
+        });
+        let value = || {
+            value
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| ParseError::Value(name.to_owned()))
+        };
+
+        Ok(match name {
+            "version_code" => Self::VersionCode(value()?.parse()?),
+            "emulator" => Self::Emulator,
+            "build_tags" => Self::BuildTags(value()?.to_owned()),
+            "package_mismatch" => Self::PackageMismatch(value()?.to_owned()),
+            "android_version" => Self::AndroidVersion(value()?.parse()?),
+            "exception" => Self::Exception(value()?.to_owned()),
+            "stack_trace" => Self::StackTrace(Regex::new(value()?)?),
+            _ => return Err(ParseError::Unknown(s.to_owned())),
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VersionCode(range) => write!(f, "version_code:{range}"),
+            Self::Emulator => f.write_str("emulator"),
+            Self::BuildTags(tag) => write!(f, "build_tags:{tag}"),
+            Self::PackageMismatch(package_name) => write!(f, "package_mismatch:{package_name}"),
+            Self::AndroidVersion(range) => write!(f, "android_version:{range}"),
+            Self::Exception(class) => write!(f, "exception:{class}"),
+            Self::StackTrace(regex) => write!(f, "stack_trace:{}", regex.as_str()),
+        }
+    }
+}
+
+/// Parse filters, one per line. Empty lines are skipped.
+pub fn parse(text: &str) -> Result<Vec<Filter>, ParseError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// The configured filters of an app, parsed once to check reports against them.
+pub struct Filters(Vec<(InboundFilter, Filter)>);
+
+impl Filters {
+    /// Parse the configured filters. Filters that can't be parsed are skipped, as they are
+    /// validated before saving them.
+    pub fn new(configured: Vec<InboundFilter>) -> Self {
+        Self(
+            configured
+                .into_iter()
+                .filter_map(|filter| match filter.rule.parse::<Filter>() {
+                    Ok(parsed) => Some((filter, parsed)),
+                    Err(e) => {
+                        warn!(rule = %filter.rule, "invalid inbound filter: {e}");
+                        None
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// The first of the filters that matches the report, if any.
+    pub fn first_match(&self, report: &Report) -> Option<&InboundFilter> {
+        let candidate = Candidate::new(report);
+        self.0
+            .iter()
+            .find(|(_, filter)| filter.matches(&candidate))
+            .map(|(configured, _)| configured)
+    }
+}
+
+/// Parsed inbound filters of each app, so they are loaded and parsed once instead of for every
+/// report. The entry of an app must be invalidated whenever its filters are saved.
+#[derive(Clone, Default)]
+pub struct FilterCache(Arc<RwLock<CachedFilters>>);
+
+#[derive(Default)]
+struct CachedFilters {
+    apps: HashMap<i64, Arc<Filters>>,
+    /// Increased by every invalidation, so that filters loaded before it aren't cached after it.
+    generation: u64,
+}
+
+impl FilterCache {
+    /// The filters of an app, loaded from the database if they aren't cached yet.
+    pub async fn get(&self, pool: &DbConnPool, app_id: i64) -> Result<Arc<Filters>> {
+        let generation = {
+            let cached = self.0.read();
+            if let Some(filters) = cached.apps.get(&app_id) {
+                return Ok(Arc::clone(filters));
+            }
+            cached.generation
+        };
+
+        let configured = repositories::filter_repo(pool.clone()).list(app_id).await?;
+        let filters = Arc::new(Filters::new(configured));
+
+        let mut cached = self.0.write();
+        if cached.generation == generation {
+            cached.apps.insert(app_id, Arc::clone(&filters));
+        }
+
+        Ok(filters)
+    }
+
+    pub fn invalidate(&self, app_id: i64) {
+        let mut cached = self.0.write();
+        cached.apps.remove(&app_id);
+        cached.generation += 1;
+    }
+}
+
+/// Whether a build is an emulator image rather than a real device.
+fn is_emulator(build: &Build) -> bool {
+    let fingerprint = build.fingerprint.to_ascii_lowercase();
+
+    fingerprint.starts_with("generic")
+        || fingerprint.starts_with("unknown")
+        || fingerprint.contains("emulator")
+        || fingerprint.contains("sdk_gphone")
+        || matches!(build.hardware.as_str(), "goldfish" | "ranchu" | "vbox86")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{Candidate, Filter, Filters, Range, parse};
+    use crate::{db::models::InboundFilter, fixtures::report, report::Report};
+
+    fn is_match(text: &str, report: &Report) -> bool {
+        text.parse::<Filter>()
+            .unwrap()
+            .matches(&Candidate::new(report))
+    }
+
+    #[test]
+    fn ranges() {
+        let range = "100..200".parse::<Range>().unwrap();
+        assert!(range.contains(100) && range.contains(199) && !range.contains(200));
+        assert!("..120".parse::<Range>().unwrap().contains(0));
+        assert!(!"130..".parse::<Range>().unwrap().contains(129));
+        assert!("14".parse::<Range>().unwrap().contains(14));
+
+        for text in ["..120", "130..", "100..200", "14"] {
+            assert_eq!(text, text.parse::<Range>().unwrap().to_string());
+        }
+        for text in ["", "..", "a..b", "-1", "4294967295"] {
+            assert!(text.parse::<Range>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn parse_filters() {
+        let filters =
+            parse("version_code: ..120\n\n emulator \nstack_trace:at com\\.ads\\.").unwrap();
+
+        assert_eq!(
+            vec![
+                "version_code:..120",
+                "emulator",
+                "stack_trace:at com\\.ads\\."
+            ],
+            filters.iter().map(ToString::to_string).collect::<Vec<_>>()
+        );
+        assert!(parse("rooted").is_err());
+        assert!(parse("exception").is_err());
+        assert!(parse("stack_trace:(").is_err());
+        assert!(parse("android_version:eight").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        let pixel = report(|_| {});
+
+        assert!(is_match("version_code:..15", &pixel));
+        assert!(!is_match("version_code:15..", &pixel));
+        assert!(is_match("android_version:13", &pixel));
+        assert!(!is_match("android_version:..8", &pixel));
+        assert!(!is_match("emulator", &pixel));
+        assert!(!is_match("build_tags:test-keys", &pixel));
+        assert!(!is_match("package_mismatch:com.example.notes", &pixel));
+        assert!(is_match("package_mismatch:com.example.notes.pro", &pixel));
+        assert!(is_match("exception:java.lang.NullPointerException", &pixel));
+        assert!(!is_match(
+            "exception:java.lang.IllegalStateException",
+            &pixel
+        ));
+        assert!(is_match("stack_trace:NullPointer", &pixel));
+        assert!(!is_match("stack_trace:^at ", &pixel));
+
+        let emulator = report(|report| {
+            report["BUILD"]["FINGERPRINT"] = Value::String(
+                "google/sdk_gphone64_x86_64/emu64xa:14/UE1A.230829.036/10836412:userdebug/dev-keys"
+                    .to_owned(),
+            );
+            report["BUILD"]["TAGS"] = Value::String("dev-keys,test-keys".to_owned());
+        });
+        assert!(is_match("emulator", &emulator));
+        assert!(is_match("build_tags:test-keys", &emulator));
+    }
+
+    #[test]
+    fn first_match() {
+        let filters = Filters::new(
+            [
+                "rooted",
+                "version_code:15..",
+                "exception:java.lang.NullPointerException",
+                "emulator",
+            ]
+            .into_iter()
+            .map(|rule| InboundFilter {
+                rule: rule.to_owned(),
+                dropped: 0,
+            })
+            .collect(),
+        );
+
+        let matched = filters
+            .first_match(&report(|_| {}))
+            .map(|filter| filter.rule.as_str());
+        assert_eq!(Some("exception:java.lang.NullPointerException"), matched);
+        let matched = filters.first_match(&report(|report| {
+            report["STACK_TRACE"] = Value::String("java.lang.IllegalStateException".to_owned());
+        }));
+        assert_eq!(None, matched);
+    }
+}
@@ -0,0 +1,67 @@
+//! Reports and database content shared by the tests of several modules. The reports are from the
+//! files in `fixtures/reports`.
+
+use serde_json::Value;
+
+use crate::{
+    db::{
+        DbConnPool,
+        models::{NewApp, NewUser},
+        repositories,
+    },
+    report::Report,
+    settings,
+};
+
+/// A crash on a Pixel 6 with Android 13, with night mode turned on before the crash.
+pub const PIXEL: &str = include_str!("../fixtures/reports/api33-acra5.11.1-pixel6-npe.json");
+/// ID of the [`PIXEL`] report.
+pub const PIXEL_ID: &str = "6b2f0a8e-3c1d-4f7a-9e52-0d8c4b1a7f31";
+/// A crash with a cause on a Galaxy A50 with Android 10.
+pub const GALAXY: &str = include_str!("../fixtures/reports/api29-acra5.8.4-galaxy-a50-cause.json");
+/// A crash on a Galaxy Tab A with Android 7, without any configuration changes.
+pub const GALAXY_TAB: &str =
+    include_str!("../fixtures/reports/api24-acra5.4.0-galaxy-tab-a-rtl.json");
+
+/// The raw [`PIXEL`] report, with some of its fields changed.
+pub fn pixel(change: impl FnOnce(&mut Value)) -> Value {
+    let mut raw = serde_json::from_str::<Value>(PIXEL).unwrap();
+    change(&mut raw);
+    raw
+}
+
+/// The parsed [`PIXEL`] report, with some of its fields changed before parsing.
+pub fn report(change: impl FnOnce(&mut Value)) -> Report {
+    serde_json::from_value(pixel(change)).unwrap()
+}
+
+/// An empty in-memory database, without any migrations run yet.
+pub fn memory_pool() -> DbConnPool {
+    // In-memory databases are bound to a single connection, so the pool must not open more.
+    crate::db::create_pool(&settings::Database {
+        pool_size: 1,
+        ..settings::Database::default()
+    })
+    .unwrap()
+}
+
+/// Create the user `admin` with the app `Test`, that reports are sent with the `test:test`
+/// credentials to. Returns the ID of the app, which is 1 in fresh SQLite databases.
+pub async fn test_app(pool: &DbConnPool) -> i64 {
+    let user_id = repositories::user_repo(pool.clone())
+        .save(NewUser {
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        })
+        .await
+        .unwrap();
+    repositories::app_repo(pool.clone())
+        .save(NewApp {
+            user_id,
+            name: "Test".to_owned(),
+            username: "test".to_owned(),
+            password: "test".to_owned(),
+        })
+        .await
+        .unwrap()
+}
@@ -0,0 +1,211 @@
+//! Grouping of reports into issues, based on a fingerprint of their stack trace.
+
+use crate::{
+    dropbox::{Anr, NativeCrash},
+    stacktrace::StackTrace,
+};
+
+/// Maximum length of an issue title, in characters.
+const TITLE_MAX_LEN: usize = 200;
+
+/// Calculate a stable fingerprint of the given stack trace, that is used to group reports with the
+/// same cause into a single issue.
+///
+/// Only the exception types and the called methods are considered. Exception messages and line
+/// numbers are left out, as they often differ slightly for the same underlying problem. Frames
+/// outside of the app are left out as well, as the same bug can surface through different paths
+/// in the framework, like a click handler called from either a button or a menu. They're only
+/// used if no frame is in-app at all.
+pub fn fingerprint(stack_trace: &StackTrace) -> String {
+    hash(normalize_trace(stack_trace).into_iter())
+}
+
+/// Calculate the fingerprint of a handled exception. It differs from the one of a crash with the
+/// same stack trace, so both end up in separate issues.
+pub fn non_fatal_fingerprint(stack_trace: &StackTrace) -> String {
+    hash(std::iter::once("non-fatal".to_owned()).chain(normalize_trace(stack_trace)))
+}
+
+/// Calculate the fingerprint of a native crash, from the signal and the functions of its
+/// backtrace. Addresses are only used for frames without a resolved function, as they change with
+/// every build.
+pub fn native_fingerprint(crash: &NativeCrash) -> String {
+    let frames = crash.frames.iter().map(|frame| match frame.function() {
+        Some(function) => format!("{} {function}", frame.library_name()),
+        None => format!("{} {:x}", frame.library_name(), frame.pc),
+    });
+
+    hash(
+        ["native", crash.signal_name().unwrap_or_default()]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .chain(frames),
+    )
+}
+
+/// Calculate the fingerprint of an ANR, from the called methods of the main thread. The reason is
+/// left out, as it usually contains the ID of the window that didn't respond.
+pub fn anr_fingerprint(anr: &Anr) -> String {
+    let frames = anr
+        .main_thread()
+        .into_iter()
+        .flat_map(|thread| &thread.lines)
+        .filter_map(|line| normalize_line(line).filter(|_| line.starts_with("at ")));
+
+    hash(std::iter::once("anr").chain(frames))
+}
+
+fn hash(lines: impl Iterator<Item = impl AsRef<str>>) -> String {
+    let hash = lines.fold(FNV_OFFSET_BASIS, |hash, line| {
+        line.as_ref()
+            .bytes()
+            .chain(std::iter::once(b'\n'))
+            .fold(hash, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+            })
+    });
+
+    format!("{hash:016x}")
+}
+
+/// Create a human readable title for an issue, which is the first line of the stack trace.
+pub fn title(stack_trace: &str) -> String {
+    truncate(stack_trace.lines().next().unwrap_or_default().trim())
+}
+
+/// Create a title for a native crash, like `SIGSEGV in libnotes.so (render_note+48)`.
+pub fn native_title(crash: &NativeCrash) -> String {
+    let signal = crash.signal_name().unwrap_or("Native crash");
+
+    // The first frame in the app's own libraries is more telling than the system library that
+    // eventually failed, like `abort` in `libc.so`.
+    let frame = crash
+        .frames
+        .iter()
+        .find(|frame| frame.library.starts_with("/data/"))
+        .or_else(|| crash.frames.first());
+
+    match frame {
+        Some(frame) => match &frame.symbol {
+            Some(symbol) => truncate(&format!("{signal} in {} ({symbol})", frame.library_name())),
+            None => truncate(&format!("{signal} in {}", frame.library_name())),
+        },
+        None => signal.to_owned(),
+    }
+}
+
+/// Create a title for an ANR, which is its reason.
+pub fn anr_title(anr: &Anr) -> String {
+    match &anr.subject {
+        Some(subject) => truncate(&format!("ANR: {subject}")),
+        None => "ANR".to_owned(),
+    }
+}
+
+fn truncate(line: &str) -> String {
+    if line.chars().count() > TITLE_MAX_LEN {
+        let mut title = line.chars().take(TITLE_MAX_LEN - 1).collect::<String>();
+        title.push('…');
+        title
+    } else {
+        line.to_owned()
+    }
+}
+
+fn normalize_trace(stack_trace: &StackTrace) -> Vec<String> {
+    fn walk(trace: &StackTrace, all_frames: bool, lines: &mut Vec<String>) {
+        for exception in &trace.exceptions {
+            lines.push(exception.class.clone());
+            lines.extend(
+                exception
+                    .frames
+                    .iter()
+                    .filter(|frame| all_frames || frame.in_app)
+                    .map(|frame| format!("{}.{}", frame.class, frame.method)),
+            );
+            for suppressed in &exception.suppressed {
+                walk(suppressed, all_frames, lines);
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    walk(stack_trace, !has_in_app_frames(stack_trace), &mut lines);
+    lines
+}
+
+fn has_in_app_frames(trace: &StackTrace) -> bool {
+    trace.exceptions.iter().any(|exception| {
+        exception.frames.iter().any(|frame| frame.in_app)
+            || exception.suppressed.iter().any(has_in_app_frames)
+    })
+}
+
+fn normalize_line(line: &str) -> Option<&str> {
+    if let Some(frame) = line.strip_prefix("at ") {
+        Some(frame.split_once('(').map_or(frame, |(method, _)| method))
+    } else if line.starts_with("...") || line.is_empty() {
+        None
+    } else {
+        let line = line.strip_prefix("Caused by: ").unwrap_or(line);
+        Some(line.split_once(':').map_or(line, |(class, _)| class))
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[cfg(test)]
+mod tests {
+    use super::{fingerprint, non_fatal_fingerprint};
+    use crate::stacktrace::{InApp, StackTrace};
+
+    fn parse(text: &str) -> StackTrace {
+        StackTrace::parse(text, &InApp::new("com.example.notes", &[]))
+    }
+
+    #[test]
+    fn group_by_in_app_frames() {
+        let button = parse(
+            "\
+java.lang.IllegalStateException: note 12 is locked
+\tat com.example.notes.Editor.save(Editor.kt:31)
+\tat android.view.View.performClick(View.java:7448)
+\tat android.os.Handler.handleCallback(Handler.java:938)
+\tat java.lang.Thread.run(Thread.java:1012)",
+        );
+        let menu = parse(
+            "\
+java.lang.IllegalStateException: note 3 is locked
+\tat com.example.notes.Editor.save(Editor.kt:35)
+\tat android.widget.PopupMenu$1.onMenuItemSelected(PopupMenu.java:91)
+\tat java.lang.Thread.run(Thread.java:1012)",
+        );
+        let other = parse(
+            "\
+java.lang.IllegalStateException: note 3 is locked
+\tat com.example.notes.Editor.delete(Editor.kt:52)
+\tat android.view.View.performClick(View.java:7448)",
+        );
+
+        assert_eq!(fingerprint(&button), fingerprint(&menu));
+        assert_ne!(fingerprint(&button), fingerprint(&other));
+        assert_ne!(fingerprint(&button), non_fatal_fingerprint(&button));
+    }
+
+    #[test]
+    fn group_without_in_app_frames() {
+        let view = parse(
+            "\
+java.lang.NullPointerException
+\tat android.view.View.performClick(View.java:7448)",
+        );
+        let menu = parse(
+            "\
+java.lang.NullPointerException
+\tat android.widget.PopupMenu$1.onMenuItemSelected(PopupMenu.java:91)",
+        );
+
+        assert_ne!(fingerprint(&view), fingerprint(&menu));
+    }
+}
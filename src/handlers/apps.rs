@@ -1,21 +1,30 @@
+use std::sync::Arc;
+
 use anyhow::anyhow;
-use axum::{extract::State, response::IntoResponse};
-use tracing::instrument;
+use axum::{
+    extract::{Form, Path, State},
+    response::{IntoResponse, Redirect},
+};
+use serde::Deserialize;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tracing::{error, instrument};
 
+use super::error::AppError;
 use crate::{
-    db::{
-        DbConnPool,
-        repositories::{self, AppRepository},
-    },
-    templates,
+    db::{DbConnPool, models::AppSettings, repositories},
+    environments,
+    filters::{self, FilterCache},
+    ingest, settings,
+    storage::Storage,
+    tags, templates,
 };
 
 #[instrument(skip_all)]
-pub async fn list(State(db): State<DbConnPool>) -> impl IntoResponse {
+pub async fn list(State(db): State<DbConnPool>) -> Result<impl IntoResponse, AppError> {
     let app_repo = repositories::app_repo(db);
-    let apps = app_repo.list().await.unwrap();
+    let apps = app_repo.list().await?;
 
-    templates::apps::Index { apps }
+    Ok(templates::apps::Index { apps })
 }
 
 #[instrument(skip_all)]
@@ -29,3 +38,113 @@ pub fn create_post() -> impl IntoResponse {
         result: Err(anyhow!("not implemented yet!")),
     }
 }
+
+#[instrument(skip_all)]
+pub async fn settings(
+    Path((id,)): Path<(i64,)>,
+    State(db): State<DbConnPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let app = repositories::app_repo(db.clone()).get(id).await?;
+    let tag_keys = repositories::tag_repo(db.clone()).list_keys(id).await?;
+    let environment_rules = repositories::environment_repo(db.clone())
+        .list_rules(id)
+        .await?;
+    let inbound_filters = repositories::filter_repo(db).list(id).await?;
+
+    Ok(templates::apps::Settings {
+        app,
+        tag_keys: tag_keys
+            .iter()
+            .map(tags::format_key)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        environment_rules: environment_rules
+            .iter()
+            .map(environments::format_rule)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        filter_rules: inbound_filters
+            .iter()
+            .map(|filter| filter.rule.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        inbound_filters,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SettingsForm {
+    /// Keys to extract as tags, one per line.
+    tag_keys: String,
+    /// Rules that put reports into environments, one per line.
+    #[serde(default)]
+    environment_rules: String,
+    /// Inbound filters that drop unwanted reports, one per line.
+    #[serde(default)]
+    filter_rules: String,
+}
+
+#[instrument(skip_all)]
+pub async fn settings_post(
+    Path((id,)): Path<(i64,)>,
+    State(db): State<DbConnPool>,
+    State(filter_cache): State<FilterCache>,
+    Form(data): Form<SettingsForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let tag_keys =
+        tags::parse_keys(&data.tag_keys).map_err(|e| AppError::Validation(e.to_string()))?;
+    let environment_rules = environments::parse_rules(&data.environment_rules)
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+    let filter_rules =
+        filters::parse(&data.filter_rules).map_err(|e| AppError::Validation(e.to_string()))?;
+
+    repositories::app_repo(db)
+        .save_settings(
+            id,
+            AppSettings {
+                tag_keys,
+                environment_rules,
+                filter_rules: filter_rules.iter().map(ToString::to_string).collect(),
+            },
+        )
+        .await?;
+    filter_cache.invalidate(id);
+
+    Ok(Redirect::to(&format!("/apps/{id}")))
+}
+
+/// Held while the stored reports are reprocessed, so that only a single run happens at a time.
+#[derive(Clone, Default)]
+pub struct ReprocessLock(Arc<Mutex<()>>);
+
+impl ReprocessLock {
+    /// Take the lock for a new run, or `None` if another one is still going.
+    pub fn try_start(&self) -> Option<OwnedMutexGuard<()>> {
+        Arc::clone(&self.0).try_lock_owned().ok()
+    }
+}
+
+/// Import all stored raw reports again, in the background as it can take a while for many reports.
+#[instrument(skip_all)]
+pub async fn reprocess(
+    State(db): State<DbConnPool>,
+    State(storage): State<Storage>,
+    State(stack_trace): State<Arc<settings::StackTrace>>,
+    State(filter_cache): State<FilterCache>,
+    State(lock): State<ReprocessLock>,
+) -> Result<impl IntoResponse, AppError> {
+    let running = lock.try_start().ok_or_else(|| {
+        AppError::Conflict("The reports are already being reprocessed".to_owned())
+    })?;
+
+    tokio::spawn(async move {
+        if let Err(e) =
+            ingest::import_stored(&db, &storage, &stack_trace.in_app, &filter_cache).await
+        {
+            error!("failed reprocessing reports: {e:?}");
+        }
+        drop(running);
+    });
+
+    Ok(templates::apps::Reprocess {})
+}
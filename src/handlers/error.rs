@@ -1,8 +1,21 @@
 #![allow(clippy::needless_pass_by_value)]
 
-use axum::BoxError;
-use hyper::StatusCode;
+use std::any::Any;
+
+use axum::{
+    BoxError, Json,
+    http::{HeaderName, StatusCode, header::WWW_AUTHENTICATE},
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
 use tower::timeout::error::Elapsed;
+use tracing::error;
+
+use crate::{
+    db::repositories::{NotFound, UserSaveError},
+    extractors::{AuthRejection, UploadRejection},
+    templates::ErrorPage,
+};
 
 pub async fn timeout(err: BoxError) -> StatusCode {
     if err.is::<Elapsed>() {
@@ -11,3 +24,196 @@ pub async fn timeout(err: BoxError) -> StatusCode {
         StatusCode::INTERNAL_SERVER_ERROR
     }
 }
+
+/// Turn a panic inside a handler into a regular error page, instead of dropping the connection.
+pub fn panic(err: Box<dyn Any + Send + 'static>) -> Response {
+    let cause = err
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| err.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown cause");
+    error!("handler panicked: {cause}");
+
+    let status = StatusCode::INTERNAL_SERVER_ERROR;
+    let message = "An internal error happened".to_owned();
+    (status, ErrorPage { status, message }).into_response()
+}
+
+/// Any error that can happen while handling a request. Rendered as error page, or as JSON when
+/// wrapped in an [`ApiError`].
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// The requested entry doesn't exist.
+    #[error("{0}")]
+    NotFound(String),
+    /// The request content is malformed or incomplete.
+    #[error("{0}")]
+    Validation(String),
+    /// The entry can't be created, as it collides with an existing one.
+    #[error("{0}")]
+    Conflict(String),
+    /// Credentials are missing or wrong.
+    #[error("invalid credentials")]
+    Unauthorized,
+    /// The uploaded content exceeds the size limit.
+    #[error("The content is too large")]
+    TooLarge,
+    #[error("database error")]
+    Database(#[source] anyhow::Error),
+    #[error("storage error")]
+    Storage(#[source] anyhow::Error),
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Database(_) | Self::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Message that is safe to show to the client. Details of internal errors are only logged.
+    fn message(&self) -> String {
+        match self {
+            Self::Database(err) | Self::Storage(err) => {
+                error!("{self}: {err:?}");
+                "An internal error happened".to_owned()
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// Status, extra headers and message of the response.
+    fn into_parts(self) -> (StatusCode, Option<[(HeaderName, &'static str); 1]>, String) {
+        // Ask for credentials the same way as the `User` extractor.
+        let headers = matches!(self, Self::Unauthorized).then_some([(WWW_AUTHENTICATE, "Basic")]);
+        (self.status(), headers, self.message())
+    }
+}
+
+/// Repository errors are database errors, unless the requested entry doesn't exist.
+impl From<anyhow::Error> for AppError {
+    fn from(value: anyhow::Error) -> Self {
+        match value.downcast_ref::<NotFound>() {
+            Some(not_found) => Self::NotFound(not_found.to_string()),
+            None => Self::Database(value),
+        }
+    }
+}
+
+impl From<UserSaveError> for AppError {
+    fn from(value: UserSaveError) -> Self {
+        match value {
+            UserSaveError::AlreadyExists(name) => {
+                Self::Conflict(format!("The user `{name}` already exists"))
+            }
+            err => Self::Database(err.into()),
+        }
+    }
+}
+
+impl From<AuthRejection> for AppError {
+    fn from(value: AuthRejection) -> Self {
+        match value {
+            AuthRejection::TypedHeaderRejection(rejection) => {
+                Self::Validation(rejection.to_string())
+            }
+            AuthRejection::InvalidCredentials => Self::Unauthorized,
+            AuthRejection::Database(e) => Self::Database(e),
+        }
+    }
+}
+
+impl From<UploadRejection> for AppError {
+    fn from(value: UploadRejection) -> Self {
+        match value {
+            UploadRejection::TooLarge => Self::TooLarge,
+            UploadRejection::Read(e) => Self::Validation(e.to_string()),
+            UploadRejection::Write(e) => Self::Storage(e.into()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, headers, message) = self.into_parts();
+        (status, headers, ErrorPage { status, message }).into_response()
+    }
+}
+
+/// An [`AppError`] for machine clients, rendered as JSON object with a single `error` field.
+#[derive(Debug)]
+pub struct ApiError(pub AppError);
+
+impl<E: Into<AppError>> From<E> for ApiError {
+    fn from(value: E) -> Self {
+        Self(value.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, headers, message) = self.0.into_parts();
+        (status, headers, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::{self, Body},
+        http::{Request, StatusCode, header::WWW_AUTHENTICATE},
+        response::{IntoResponse, Response},
+        routing::get,
+    };
+    use tower::Service;
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    use super::{ApiError, AppError};
+    use crate::db::repositories::NotFound;
+
+    async fn body(response: Response) -> String {
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn api_error_as_json() {
+        let response = ApiError::from(anyhow::Error::new(NotFound("app"))).into_response();
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(r#"{"error":"app not found"}"#, body(response).await);
+
+        // Internals are only logged, never sent to the client.
+        let response = ApiError::from(anyhow::anyhow!("disk full")).into_response();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert_eq!(
+            r#"{"error":"An internal error happened"}"#,
+            body(response).await
+        );
+
+        let response = ApiError(AppError::Unauthorized).into_response();
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!("Basic", response.headers()[WWW_AUTHENTICATE]);
+    }
+
+    #[tokio::test]
+    async fn catch_panic() {
+        let mut router = Router::new()
+            .route("/", get(async || -> &'static str { panic!("boom") }))
+            .layer(CatchPanicLayer::custom(super::panic));
+
+        let response = router
+            .call(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(body(response).await.contains("An internal error happened"));
+    }
+}
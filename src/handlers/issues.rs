@@ -0,0 +1,61 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::instrument;
+
+use super::error::AppError;
+use crate::{
+    db::{DbConnPool, repositories},
+    environments, tags, templates,
+};
+
+/// Maximum amount of reports listed on the issue page.
+const REPORTS_LIMIT: u32 = 50;
+
+#[derive(Deserialize)]
+pub struct DetailsQuery {
+    /// Tags of the listed reports, like `tier:premium FLAVOR:free`.
+    #[serde(default)]
+    tags: String,
+    /// Environment of the listed reports, or `all`. Defaults to production.
+    environment: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn details(
+    Path((id,)): Path<(i64,)>,
+    Query(query): Query<DetailsQuery>,
+    State(db): State<DbConnPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter =
+        tags::parse_filter(&query.tags).map_err(|e| AppError::Validation(e.to_string()))?;
+    let environment = environments::filter(query.environment.as_deref())
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let issue = repositories::issue_repo(db.clone()).get(id).await?;
+    let app = repositories::app_repo(db.clone()).get(issue.app_id).await?;
+    let environments = repositories::environment_repo(db.clone())
+        .list(app.id)
+        .await?;
+    let tag_counts = repositories::tag_repo(db.clone())
+        .distribution(id, environment.clone())
+        .await?;
+    let report_repo = repositories::report_repo(db);
+    let reports = report_repo
+        .list_by_issue(id, environment.clone(), filter, REPORTS_LIMIT)
+        .await?;
+    let conditions = report_repo.conditions(id, environment.clone()).await?;
+
+    Ok(templates::issues::Details {
+        app,
+        issue,
+        reports,
+        conditions,
+        tags: query.tags.trim().to_owned(),
+        tag_counts,
+        environment: environment.unwrap_or_else(|| environments::ALL.to_owned()),
+        environments: environments::choices(environments),
+    })
+}
@@ -1,146 +1,254 @@
 #![allow(clippy::unused_async)]
 
-use anyhow::{Context, Result};
 use axum::{
-    extract::{Json, Path, State},
+    extract::{FromRequest, Path, Query, Request, State},
     http::StatusCode,
-    response::{IntoResponse, Redirect, Response},
+    response::{IntoResponse, Redirect},
 };
+use serde::Deserialize;
 use serde_json::Value;
-use tokio::fs;
 use tracing::{error, info, instrument, warn};
 
 pub mod apps;
 pub mod error;
+pub mod issues;
+pub mod reports;
+pub mod tools;
 pub mod users;
 
-use self::users::UserError;
+use self::error::{ApiError, AppError};
 use crate::{
     AppState,
-    db::{
-        DbConnPool,
-        models::{NewReport, NewVersion},
-        repositories::{self, AppRepository, ReportRepository, UserSaveError, VersionRepository},
-    },
-    dirs::DIRS,
-    extractors::User,
+    db::{DbConnPool, models::CrashType, repositories},
+    environments,
+    extractors::{AuthRejection, ClientIp, ReportUpload, Upload, User},
+    ingest::{self, Stored},
+    metrics::METRICS,
+    notifications,
     report::Report,
-    retrace,
-    templates::{self, ErrorPage},
+    retrace, symbols, templates,
 };
 
-#[derive(derive_more::From)]
-pub enum AppError {
-    User(UserError),
-}
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
-            Self::User(err) => match err {
-                UserError::Save(err) => match err {
-                    UserSaveError::AlreadyExists(name) => (
-                        StatusCode::CONFLICT,
-                        format!("The user `{name}` already exists"),
-                    ),
-                    _ => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("An internal error happened: {err:?}"),
-                    ),
-                },
-            },
-        };
-
-        ErrorPage { status, message }.into_response()
-    }
-}
-
 #[instrument(skip_all)]
 pub fn index() -> impl IntoResponse {
     Redirect::temporary("/apps")
 }
 
+#[derive(Deserialize)]
+pub struct VersionsQuery {
+    /// Crash type of the issues to show, like `anr`. Defaults to fatal crashes.
+    #[serde(rename = "type")]
+    crash_type: Option<String>,
+    /// Environment of the reports to show, or `all`. Defaults to production.
+    environment: Option<String>,
+}
+
 #[instrument(skip_all)]
 pub async fn versions_list(
     Path((id,)): Path<(i64,)>,
+    Query(query): Query<VersionsQuery>,
     State(db): State<DbConnPool>,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, AppError> {
     let version_repo = repositories::version_repo(db.clone());
-    let app_repo = repositories::app_repo(db);
-
-    let app = app_repo.get(id).await.unwrap();
-    let versions = version_repo.list_by_app(id).await.unwrap();
-
-    templates::apps::Details { app, versions }
+    let issue_repo = repositories::issue_repo(db.clone());
+    let app_repo = repositories::app_repo(db.clone());
+
+    let crash_type = query
+        .crash_type
+        .as_deref()
+        .map(str::parse::<CrashType>)
+        .transpose()
+        .map_err(|e| AppError::Validation(e.to_string()))?
+        .unwrap_or_default();
+    let environment = environments::filter(query.environment.as_deref())
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let app = app_repo.get(id).await?;
+    let versions = version_repo.list_by_app(id).await?;
+    let issues = issue_repo
+        .list_by_app(id, crash_type, environment.clone())
+        .await?;
+    let environments = repositories::environment_repo(db).list(id).await?;
+
+    Ok(templates::apps::Details {
+        app,
+        versions,
+        crash_type,
+        issues,
+        environment: environment.unwrap_or_else(|| environments::ALL.to_owned()),
+        environments: environments::choices(environments),
+    })
 }
 
 #[instrument(skip_all)]
 pub async fn report_save(
-    user: User,
+    user: Result<User, AuthRejection>,
+    ClientIp(client_ip): ClientIp,
     State(state): State<AppState>,
-    Json(raw): Json<Value>,
-) -> impl IntoResponse {
-    if let Err(e) = save_raw(&raw).await {
-        error!("failed saving report to file: {}", e);
+    ReportUpload {
+        mut raw,
+        attachments,
+    }: ReportUpload,
+) -> Result<StatusCode, ApiError> {
+    let _timer = METRICS.ingestion_duration.start_timer();
+    let User(app) = user.inspect_err(|_| METRICS.reject("unknown", "unauthorized"))?;
+
+    METRICS
+        .reports_received
+        .with_label_values(&[app.username.as_str()])
+        .inc();
+
+    // Record the address the report was sent from. Whatever the client put there is replaced, as
+    // it's usually an address of the local network, or could be made up entirely.
+    if let (Some(ip), Some(raw)) = (client_ip, raw.as_object_mut()) {
+        raw.insert("USER_IP".to_owned(), Value::String(ip.to_string()));
     }
 
-    let report = match serde_json::from_value::<Report>(raw) {
-        Ok(r) => r,
+    let report = match Report::deserialize(&raw) {
+        Ok(report) => report,
         Err(e) => {
             warn!("invalid report: {}", e);
-            return StatusCode::BAD_REQUEST;
+            METRICS.reject(&app.username, "invalid");
+            // Keep it anyway, so it can be imported once the parser understands it.
+            if let Err(e) = ingest::save_raw(&state.storage, app.id, &raw).await {
+                error!("failed saving raw report: {}", e);
+            }
+            return Err(AppError::Validation(format!("invalid report: {e}")).into());
         }
     };
 
-    let app_repo = repositories::app_repo(state.pool.clone());
-    let version_repo = repositories::version_repo(state.pool.clone());
-    let report_repo = repositories::report_repo(state.pool);
+    // Unwanted reports are dropped before anything of them is stored. They're still accepted, so
+    // the client doesn't send them again.
+    let inbound_filters = state.filters.get(&state.pool, app.id).await?;
+    if let Some(filter) = inbound_filters.first_match(&report) {
+        info!(rule = %filter.rule, "dropped report");
+        METRICS.reject(&app.username, "filtered");
+        repositories::filter_repo(state.pool.clone())
+            .record_drop(app.id, filter.rule.clone())
+            .await?;
+        return Ok(StatusCode::OK);
+    }
 
-    let app = app_repo
-        .get_by_username(user.username().to_owned())
-        .await
-        .unwrap();
-    let version_id = version_repo
-        .get_or_create(NewVersion {
-            app_id: app.id,
-            name: report.app_version_name.clone(),
-            code: i64::from(report.app_version_code),
-        })
-        .await
-        .unwrap();
-    report_repo
-        .save(NewReport {
-            version_id,
-            report_id: report.id.clone(),
-            crash_date: report.user_crash_date.clone(),
-        })
-        .await
-        .unwrap();
+    if let Err(e) = ingest::save_raw(&state.storage, app.id, &raw).await {
+        error!("failed saving raw report: {}", e);
+    }
+
+    for attachment in attachments {
+        if let Err(e) = state
+            .storage
+            .save_attachment(app.id, &report.id, &attachment.name, attachment.data.into())
+            .await
+        {
+            error!(name = %attachment.name, "failed saving attachment: {e:?}");
+        }
+    }
+
+    let Stored {
+        issue_id,
+        new_in_production,
+    } = ingest::store(
+        &state.pool,
+        app.id,
+        &report,
+        &state.stack_trace.in_app,
+        false,
+    )
+    .await?;
+
+    METRICS
+        .reports_accepted
+        .with_label_values(&[app.username.as_str()])
+        .inc();
+
+    let storage = state.storage.clone();
+
+    if let (true, Some(mailer)) = (new_in_production, state.mailer) {
+        let task = METRICS.background_task();
+        tokio::spawn(async move {
+            let _task = task;
+            if let Err(e) = notifications::notify_new_issue(state.pool, mailer, issue_id).await {
+                warn!("failed notifying about new issue: {e:?}");
+            }
+        });
+    }
 
+    let task = METRICS.background_task();
     tokio::spawn(async move {
-        match retrace::retrace(&report.stack_trace).await {
+        let _task = task;
+        let version_code = report.app_version_code.into();
+        match retrace::retrace(&storage, app.id, version_code, &report.stack_trace).await {
             Ok(st) => info!("Stacktrace: {}", st),
             Err(e) => warn!("failed retracing: {}", e),
         }
     });
 
-    StatusCode::OK
+    Ok(StatusCode::OK)
 }
 
+/// Upload the ProGuard mapping file of a single version of the app that the credentials belong to.
 #[instrument(skip_all)]
-async fn save_raw(raw: &Value) -> Result<()> {
-    let report_id = raw
-        .as_object()
-        .and_then(|r| r.get("REPORT_ID"))
-        .and_then(Value::as_str)
-        .context("report id is missing")?;
-
-    fs::create_dir_all(DIRS.reports_dir()).await?;
-
-    fs::write(
-        format!("{}/{}.json", DIRS.reports_dir(), report_id),
-        serde_json::to_vec(raw)?,
-    )
+pub async fn mapping_save(
+    user: Result<User, AuthRejection>,
+    Path((version_code,)): Path<(i64,)>,
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<StatusCode, ApiError> {
+    let User(app) = user?;
+    // Only read the content once the credentials are known to be valid.
+    let upload = Upload::from_request(request, &()).await?;
+    if !upload.is_utf8() {
+        return Err(AppError::Validation("The mapping file isn't valid UTF-8".to_owned()).into());
+    }
+
+    state
+        .storage
+        .save_version_mapping(app.id, version_code, upload.path())
+        .await
+        .map_err(AppError::Storage)?;
+
+    info!(app = %app.name, version_code, "saved mapping file");
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Upload the debug file of a native library of the app that the credentials belong to, which is
+/// the library before stripping. It's identified by its GNU build ID, which must match the file.
+#[instrument(skip_all)]
+pub async fn symbols_save(
+    user: Result<User, AuthRejection>,
+    Path((build_id,)): Path<(String,)>,
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<StatusCode, ApiError> {
+    let User(app) = user?;
+    let build_id = build_id.to_ascii_lowercase();
+    let upload = Upload::from_request(request, &()).await?;
+
+    let path = upload.path().to_owned();
+    // Parsing only reads the parts of the file that it needs, instead of loading all of it.
+    let id = tokio::task::spawn_blocking(move || {
+        std::fs::File::open(path).map(|file| symbols::build_id(&object::ReadCache::new(file)))
+    })
     .await
-    .map_err(Into::into)
+    .map_err(|e| AppError::Storage(e.into()))?
+    .map_err(|e| AppError::Storage(e.into()))?;
+
+    match id {
+        Ok(Some(id)) if id == build_id => {}
+        Ok(Some(id)) => {
+            return Err(AppError::Validation(format!("The file has the build ID {id}")).into());
+        }
+        Ok(None) => return Err(AppError::Validation("The file has no build ID".to_owned()).into()),
+        Err(e) => return Err(AppError::Validation(format!("Invalid debug file: {e:#}")).into()),
+    }
+
+    state
+        .storage
+        .save_debug_file(app.id, &build_id, upload.path())
+        .await
+        .map_err(AppError::Storage)?;
+
+    info!(app = %app.name, build_id, "saved debug file");
+
+    Ok(StatusCode::NO_CONTENT)
 }
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::{instrument, warn};
+
+use super::error::AppError;
+use crate::{
+    conditions::{self, MemInfo, Memory},
+    db::{DbConnPool, models::App, repositories},
+    dropbox,
+    logcat::{self, Level, Log, Source},
+    report::Report,
+    retrace::{self, ReportText},
+    settings,
+    storage::Storage,
+    symbols, tags, templates,
+};
+
+#[derive(Deserialize)]
+pub struct DetailsQuery {
+    /// Show the deobfuscated text and symbolicated native frames instead of the original ones.
+    #[serde(default)]
+    retraced: bool,
+}
+
+#[instrument(skip_all)]
+pub async fn details(
+    Path((id,)): Path<(String,)>,
+    Query(query): Query<DetailsQuery>,
+    State(db): State<DbConnPool>,
+    State(storage): State<Storage>,
+    State(stack_trace): State<Arc<settings::StackTrace>>,
+) -> Result<impl IntoResponse, AppError> {
+    let (app, report) = load(db.clone(), &storage, id).await?;
+    let tag_keys = repositories::tag_repo(db).list_keys(app.id).await?;
+
+    let retraced = if query.retraced {
+        retrace::retrace_report(&storage, app.id, &report)
+            .await
+            .inspect_err(|e| warn!("failed retracing report: {e:?}"))
+            .ok()
+    } else {
+        None
+    };
+
+    let mut dropbox = report
+        .dropbox
+        .as_ref()
+        .map(dropbox::parse)
+        .unwrap_or_default();
+    if query.retraced {
+        symbols::symbolicate(&storage, app.id, &mut dropbox).await;
+    }
+
+    Ok(templates::reports::Details {
+        config_changes: conditions::config_changes(&report),
+        memory: Memory::of(&report),
+        meminfo: report.dumpsys_meminfo.as_deref().and_then(MemInfo::parse),
+        entries: tags::entries(&tag_keys, &report),
+        dropbox,
+        retrace_failed: query.retraced && retraced.is_none(),
+        text: retraced.unwrap_or_else(|| ReportText::original(&report)),
+        retraced: query.retraced,
+        in_app: stack_trace.in_app.clone(),
+        app,
+        report,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct LogsQuery {
+    /// Log to show, like `events`. Defaults to logcat.
+    log: Option<String>,
+    /// Minimum level of shown lines, as its letter like `W`.
+    level: Option<String>,
+    /// Comma separated tags of the shown lines.
+    tag: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn logs(
+    Path((id,)): Path<(String,)>,
+    Query(query): Query<LogsQuery>,
+    State(db): State<DbConnPool>,
+    State(storage): State<Storage>,
+) -> Result<impl IntoResponse, AppError> {
+    let (app, report) = load(db, &storage, id).await?;
+
+    // Form fields that were left empty count as not set.
+    let non_empty = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+
+    let source = match non_empty(query.log) {
+        Some(name) => Source::from_name(&name)
+            .ok_or_else(|| AppError::Validation(format!("Unknown log {name}")))?,
+        None => Source::Logcat,
+    };
+    let level = non_empty(query.level)
+        .map(|letter| {
+            Level::from_letter(&letter)
+                .ok_or_else(|| AppError::Validation(format!("Unknown log level {letter}")))
+        })
+        .transpose()?;
+    let tag = non_empty(query.tag).unwrap_or_default();
+    let tags = tag
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut log = Log::parse(source.text(&report).unwrap_or_default());
+    log.filter(level, &tags);
+    let crash_line =
+        logcat::crash_time(&report.user_crash_date).and_then(|time| log.last_before(&time));
+
+    Ok(templates::reports::Logs {
+        sources: Source::ALL
+            .into_iter()
+            .filter(|source| source.text(&report).is_some())
+            .collect(),
+        source,
+        level,
+        tag: tag.clone(),
+        crash_line,
+        log,
+        app,
+        report,
+    })
+}
+
+/// Load a stored report together with the app it belongs to.
+async fn load(db: DbConnPool, storage: &Storage, id: String) -> Result<(App, Report), AppError> {
+    let app_id = repositories::report_repo(db.clone())
+        .get_app_id(id.clone())
+        .await?
+        .ok_or_else(|| AppError::NotFound("report not found".to_owned()))?;
+    let app = repositories::app_repo(db).get(app_id).await?;
+
+    let raw = storage
+        .report(app_id, &id)
+        .await
+        .map_err(AppError::Storage)?
+        .ok_or_else(|| AppError::NotFound("report not found".to_owned()))?;
+    let report = serde_json::from_value::<Report>(raw)
+        .map_err(|e| AppError::Storage(anyhow::Error::new(e).context("invalid stored report")))?;
+
+    Ok((app, report))
+}
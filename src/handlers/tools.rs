@@ -0,0 +1,221 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{State, rejection::JsonRejection},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::instrument;
+
+use super::error::{ApiError, AppError};
+use crate::{
+    db::{
+        DbConnPool,
+        models::{App, Issue, Version},
+        repositories,
+    },
+    extractors::RetraceForm,
+    grouping,
+    retrace::{self, Retraced},
+    settings,
+    stacktrace::{InApp, StackTrace},
+    storage::Storage,
+    templates,
+};
+
+#[instrument(skip_all)]
+pub async fn retrace(State(db): State<DbConnPool>) -> Result<impl IntoResponse, AppError> {
+    Ok(templates::tools::Retrace {
+        apps: list_versions(db).await?,
+        stack_trace: String::new(),
+        version_id: None,
+        retraced: None,
+        issue: None,
+        error: None,
+    })
+}
+
+#[instrument(skip_all)]
+pub async fn retrace_post(
+    State(db): State<DbConnPool>,
+    State(storage): State<Storage>,
+    State(stack_trace): State<Arc<settings::StackTrace>>,
+    form: RetraceForm,
+) -> Result<impl IntoResponse, AppError> {
+    let result = run(
+        db.clone(),
+        &storage,
+        &stack_trace.in_app,
+        &form.stack_trace,
+        form.version_id,
+        form.mapping,
+    )
+    .await;
+
+    // Mistakes in the input are shown next to the form, so they can be corrected right away.
+    let (retraced, issue, error) = match result {
+        Ok((retraced, issue)) => (Some(retraced), issue, None),
+        Err(AppError::Validation(message) | AppError::NotFound(message)) => {
+            (None, None, Some(message))
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(templates::tools::Retrace {
+        apps: list_versions(db).await?,
+        stack_trace: form.stack_trace,
+        version_id: form.version_id,
+        retraced,
+        issue,
+        error,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct RetraceRequest {
+    stack_trace: String,
+    version_id: Option<i64>,
+    /// Content of a mapping file, used instead of the stored one of the version.
+    mapping: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn retrace_api(
+    State(db): State<DbConnPool>,
+    State(storage): State<Storage>,
+    State(stack_trace): State<Arc<settings::StackTrace>>,
+    request: Result<Json<RetraceRequest>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(request) = request.map_err(|e| AppError::Validation(e.body_text()))?;
+    let (retraced, issue) = run(
+        db,
+        &storage,
+        &stack_trace.in_app,
+        &request.stack_trace,
+        request.version_id,
+        request.mapping,
+    )
+    .await?;
+
+    Ok(Json(json!({
+        "stack_trace": retraced.stack_trace,
+        "frames": retraced.frames,
+        "issue": issue.map(|issue| json!({
+            "id": issue.id,
+            "title": issue.title,
+            "url": format!("/issues/{}", issue.id),
+        })),
+    })))
+}
+
+/// Retrace the given stack trace with either an ad hoc mapping, or the stored one of the version.
+/// If the version is known, the issue with the same fingerprint is looked up as well.
+async fn run(
+    db: DbConnPool,
+    storage: &Storage,
+    in_app: &[String],
+    stack_trace: &str,
+    version_id: Option<i64>,
+    mapping: Option<String>,
+) -> Result<(Retraced, Option<Issue>), AppError> {
+    if stack_trace.trim().is_empty() {
+        return Err(AppError::Validation("The stack trace is empty".to_owned()));
+    }
+
+    let version = match version_id {
+        Some(id) => Some(repositories::version_repo(db.clone()).get(id).await?),
+        None => None,
+    };
+
+    let mapping = match (mapping, &version) {
+        (Some(mapping), _) => mapping,
+        (None, Some(version)) => storage
+            .version_mapping(version.app_id, version.code)
+            .await
+            .map_err(AppError::Storage)?
+            .ok_or_else(|| {
+                AppError::Validation(format!(
+                    "No mapping file available for version {}",
+                    version.name
+                ))
+            })?,
+        (None, None) => {
+            return Err(AppError::Validation(
+                "Pick a version or upload a mapping file".to_owned(),
+            ));
+        }
+    };
+
+    let retraced = retrace::retrace_frames(&mapping, stack_trace)
+        .await
+        .map_err(|e| AppError::Validation(format!("Failed retracing: {e}")))?;
+
+    let Some(version) = version else {
+        return Ok((retraced, None));
+    };
+
+    // Issues are grouped by the trace as it was sent, which is usually obfuscated. But reports of
+    // unobfuscated builds match the retraced one. Either can be a crash or a handled exception.
+    // The in-app frames that issues are grouped by depend on the package name, which only the
+    // reports tell. Without any report, there's no issue to find either.
+    let Some(report_id) = repositories::report_repo(db.clone())
+        .latest_id(version.id)
+        .await?
+    else {
+        return Ok((retraced, None));
+    };
+    let raw = storage
+        .report(version.app_id, &report_id)
+        .await
+        .map_err(AppError::Storage)?;
+    let package_name = raw
+        .as_ref()
+        .and_then(|raw| raw.get("PACKAGE_NAME"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let in_app = InApp::new(package_name, in_app);
+
+    let issues = repositories::issue_repo(db);
+    let mut issue = None;
+
+    let fingerprints = [stack_trace, &retraced.stack_trace]
+        .into_iter()
+        .flat_map(|trace| {
+            let trace = StackTrace::parse(trace, &in_app);
+            [
+                grouping::fingerprint(&trace),
+                grouping::non_fatal_fingerprint(&trace),
+            ]
+        });
+
+    for fingerprint in fingerprints {
+        issue = issues.find(version.app_id, fingerprint).await?;
+        if issue.is_some() {
+            break;
+        }
+    }
+
+    Ok((retraced, issue))
+}
+
+/// All apps together with their versions, newest first.
+async fn list_versions(db: DbConnPool) -> Result<Vec<(App, Vec<Version>)>, AppError> {
+    let apps = repositories::app_repo(db.clone()).list().await?;
+    let mut versions = repositories::version_repo(db).list().await?;
+    versions.sort_by_key(|version| std::cmp::Reverse(version.code));
+
+    let mut by_app = HashMap::<_, Vec<_>>::new();
+    for version in versions {
+        by_app.entry(version.app_id).or_default().push(version);
+    }
+
+    Ok(apps
+        .into_iter()
+        .map(|app| {
+            let versions = by_app.remove(&app.id).unwrap_or_default();
+            (app, versions)
+        })
+        .collect())
+}
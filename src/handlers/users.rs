@@ -1,32 +1,28 @@
 use axum::{
-    extract::{Form, State},
+    extract::{Path, State},
     response::{IntoResponse, Redirect},
 };
+// Unlike the one of axum, this form collects repeated fields into lists.
+use axum_extra::extract::Form;
 use serde::Deserialize;
 use tracing::instrument;
 
-use super::AppError;
+use super::error::AppError;
 use crate::{
     db::{
         DbConnPool,
-        models::NewUser,
-        repositories::{self, UserRepository, UserSaveError},
+        models::{NewUser, NotificationSettings},
+        repositories,
     },
     templates,
 };
 
-#[derive(Debug, thiserror::Error)]
-pub enum UserError {
-    #[error("failed saving user")]
-    Save(#[from] UserSaveError),
-}
-
 #[instrument(skip_all)]
-pub async fn list(State(db): State<DbConnPool>) -> impl IntoResponse {
+pub async fn list(State(db): State<DbConnPool>) -> Result<impl IntoResponse, AppError> {
     let user_repo = repositories::user_repo(db);
-    let users = user_repo.list().await.unwrap();
+    let users = user_repo.list().await?;
 
-    templates::users::List { users }
+    Ok(templates::users::List { users })
 }
 
 #[instrument(skip_all)]
@@ -54,9 +50,56 @@ pub async fn create_post(
     State(db): State<DbConnPool>,
     Form(data): Form<NewUserForm>,
 ) -> Result<impl IntoResponse, AppError> {
-    repositories::user_repo(db)
-        .save(data.into())
-        .await
-        .map_err(UserError::Save)?;
+    repositories::user_repo(db).save(data.into()).await?;
+    Ok(Redirect::to("/users"))
+}
+
+#[instrument(skip_all)]
+pub async fn notifications(
+    Path((id,)): Path<(i64,)>,
+    State(db): State<DbConnPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = repositories::user_repo(db.clone()).get(id).await?;
+    let apps = repositories::app_repo(db.clone()).list().await?;
+    let notification_repo = repositories::notification_repo(db);
+    let settings = notification_repo.get(id).await?;
+    let alert_apps = notification_repo.list_apps(id).await?;
+
+    Ok(templates::users::Notifications {
+        user,
+        settings,
+        apps,
+        alert_apps,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct NotificationsForm {
+    email: String,
+    // Checkboxes are only sent when they are checked.
+    immediate: Option<String>,
+    digest: Option<String>,
+    /// Apps to send alerts about new issues for, one field per checked app.
+    #[serde(default, rename = "app")]
+    apps: Vec<i64>,
+}
+
+#[instrument(skip_all)]
+pub async fn notifications_post(
+    Path((id,)): Path<(i64,)>,
+    State(db): State<DbConnPool>,
+    Form(data): Form<NotificationsForm>,
+) -> Result<impl IntoResponse, AppError> {
+    repositories::notification_repo(db)
+        .save(
+            NotificationSettings {
+                user_id: id,
+                email: data.email,
+                immediate: data.immediate.is_some(),
+                digest: data.digest.is_some(),
+            },
+            data.apps,
+        )
+        .await?;
     Ok(Redirect::to("/users"))
 }
@@ -0,0 +1,401 @@
+//! The ingestion pipeline that turns a raw report into database entries, shared by the report
+//! endpoint and the import of previously stored or exported reports.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio::fs;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    conditions::{self, Memory},
+    db::{
+        DbConnPool,
+        models::{CrashType, NewIssue, NewReport, NewVersion},
+        repositories,
+    },
+    dropbox::{self, Content},
+    environments,
+    filters::FilterCache,
+    grouping,
+    report::Report,
+    stacktrace::{InApp, StackTrace},
+    storage::Storage,
+    tags,
+};
+
+/// Outcome of storing a single report.
+pub struct Stored {
+    pub issue_id: i64,
+    /// Whether the report is the first production report of its issue, which is when users are
+    /// alerted about it. Issues that showed up in other environments before count as new then.
+    pub new_in_production: bool,
+}
+
+/// Store a parsed report for the given app, creating its version and issue as needed.
+///
+/// Frames in the app's package or any of the `in_app` package prefixes are the ones the report is
+/// grouped by. If `replace` is set, any previously stored entries of the same report are removed
+/// first, so the report is grouped again from scratch.
+#[instrument(skip_all, fields(report_id = %report.id))]
+pub async fn store(
+    pool: &DbConnPool,
+    app_id: i64,
+    report: &Report,
+    in_app: &[String],
+    replace: bool,
+) -> Result<Stored> {
+    let version_id = repositories::version_repo(pool.clone())
+        .get_or_create(NewVersion {
+            app_id,
+            name: report.app_version_name.clone(),
+            code: i64::from(report.app_version_code),
+        })
+        .await?;
+    let stack_trace = StackTrace::parse(
+        &report.stack_trace,
+        &InApp::new(&report.package_name, in_app),
+    );
+    let (crash_type, fingerprint) = if report.is_silent {
+        (
+            CrashType::NonFatal,
+            grouping::non_fatal_fingerprint(&stack_trace),
+        )
+    } else {
+        (CrashType::Fatal, grouping::fingerprint(&stack_trace))
+    };
+    let (issue_id, new_issue) = repositories::issue_repo(pool.clone())
+        .get_or_create(NewIssue {
+            app_id,
+            fingerprint,
+            title: grouping::title(&report.stack_trace),
+            crash_type,
+        })
+        .await?;
+
+    let report_repo = repositories::report_repo(pool.clone());
+    let config_changes = conditions::config_changes(report)
+        .into_iter()
+        .map(|change| change.setting)
+        .collect::<Vec<_>>();
+    let low_memory = Memory::of(report).is_low();
+    let rules = repositories::environment_repo(pool.clone())
+        .list_rules(app_id)
+        .await?;
+    let environment = environments::derive(&rules, report);
+    let new_report = NewReport {
+        version_id,
+        issue_id,
+        report_id: report.id.clone(),
+        crash_date: report.user_crash_date.clone(),
+        crash_type,
+        config_changes,
+        low_memory,
+        environment: environment.clone(),
+    };
+
+    let id = if replace {
+        report_repo.replace(new_report).await?
+    } else {
+        report_repo.save(new_report).await?
+    };
+
+    let new_in_production = environment == environments::PRODUCTION
+        && (new_issue
+            || report_repo
+                .list_by_issue(issue_id, Some(environment.clone()), Vec::new(), 2)
+                .await?
+                .len()
+                == 1);
+
+    let tag_repo = repositories::tag_repo(pool.clone());
+    let keys = tag_repo.list_keys(app_id).await?;
+    tag_repo
+        .save_report_tags(app_id, report.id.clone(), tags::extract(&keys, report))
+        .await?;
+
+    // Native crashes and ANRs that the system recorded for the app shortly before are issues of
+    // their own, as they never reach the Java crash handler.
+    for issue in dropbox_issues(app_id, report) {
+        let (issue_id, _) = repositories::issue_repo(pool.clone())
+            .get_or_create(issue)
+            .await?;
+        report_repo.link_issue(id, issue_id).await?;
+    }
+
+    Ok(Stored {
+        issue_id,
+        new_in_production,
+    })
+}
+
+/// Issues for the native crashes and ANRs of the app in the `DROPBOX` field of a report.
+fn dropbox_issues(app_id: i64, report: &Report) -> Vec<NewIssue> {
+    let Some(dropbox) = &report.dropbox else {
+        return Vec::new();
+    };
+
+    dropbox::parse(dropbox)
+        .into_iter()
+        .filter(|entry| entry.is_process(&report.package_name))
+        .filter_map(|entry| match entry.content {
+            Content::NativeCrash(crash) => Some(NewIssue {
+                app_id,
+                fingerprint: grouping::native_fingerprint(&crash),
+                title: grouping::native_title(&crash),
+                crash_type: CrashType::Native,
+            }),
+            Content::Anr(anr) => Some(NewIssue {
+                app_id,
+                fingerprint: grouping::anr_fingerprint(&anr),
+                title: grouping::anr_title(&anr),
+                crash_type: CrashType::Anr,
+            }),
+            Content::Text(_) => None,
+        })
+        .collect()
+}
+
+/// Keep the original payload of a report together with its app, so it can be imported again later.
+#[instrument(skip_all)]
+pub async fn save_raw(storage: &Storage, app_id: i64, raw: &Value) -> Result<()> {
+    let report_id = raw
+        .as_object()
+        .and_then(|r| r.get("REPORT_ID"))
+        .and_then(Value::as_str)
+        .context("report id is missing")?;
+
+    storage.save_report(app_id, report_id, raw).await
+}
+
+/// Counts of processed reports during an import.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: u64,
+    /// Reports that couldn't be assigned to any app.
+    pub skipped: u64,
+    /// Reports that are invalid or failed to be stored.
+    pub failed: u64,
+    /// Reports that an inbound filter of their app drops. Ones that were stored before the filter
+    /// was added are removed.
+    pub filtered: u64,
+    /// Issues that were left without any reports after grouping the reports again.
+    pub removed_issues: u64,
+}
+
+/// Feed all JSON files of a directory through the ingestion pipeline.
+///
+/// Reports that were already received before stay with their app and are grouped again. All other
+/// reports are assigned to `fallback_app`, or skipped if it isn't given.
+#[instrument(skip_all, fields(dir = %dir.display()))]
+pub async fn import_dir(
+    pool: &DbConnPool,
+    storage: &Storage,
+    dir: &Path,
+    fallback_app: Option<i64>,
+    in_app: &[String],
+    filters: &FilterCache,
+) -> Result<ImportSummary> {
+    let mut entries = fs::read_dir(dir)
+        .await
+        .with_context(|| format!("failed reading directory {}", dir.display()))?;
+    let mut summary = ImportSummary::default();
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+
+        let value = match read_json(&path).await {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(path = %path.display(), "failed reading report file: {e:?}");
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        for raw in unwrap_reports(value) {
+            import(
+                pool,
+                Some(storage),
+                raw,
+                Assign::Received {
+                    fallback: fallback_app,
+                },
+                in_app,
+                filters,
+                &mut summary,
+            )
+            .await;
+        }
+    }
+
+    finish(pool, &mut summary).await?;
+    Ok(summary)
+}
+
+/// Feed all raw reports in the storage through the ingestion pipeline again, for example to group
+/// them anew after the grouping logic changed. Reports stay with the app that they were sent for.
+/// Ones stored before reports were kept per app stay with the app that they were received for, if
+/// any.
+#[instrument(skip_all)]
+pub async fn import_stored(
+    pool: &DbConnPool,
+    storage: &Storage,
+    in_app: &[String],
+    filters: &FilterCache,
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    for stored in storage.list_reports().await? {
+        let raw = match storage.load_report(&stored.object.key).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn!(key = %stored.object.key, "failed loading stored report: {e:?}");
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        let assign = stored
+            .app_id
+            .map_or(Assign::Received { fallback: None }, Assign::Stored);
+        import(pool, None, raw, assign, in_app, filters, &mut summary).await;
+    }
+
+    finish(pool, &mut summary).await?;
+    Ok(summary)
+}
+
+/// Clean up after an import, as reports may have moved to other issues, and log the outcome.
+async fn finish(pool: &DbConnPool, summary: &mut ImportSummary) -> Result<()> {
+    summary.removed_issues = repositories::issue_repo(pool.clone())
+        .delete_empty()
+        .await?;
+
+    info!(
+        imported = summary.imported,
+        skipped = summary.skipped,
+        failed = summary.failed,
+        filtered = summary.filtered,
+        removed_issues = summary.removed_issues,
+        "finished importing reports"
+    );
+    Ok(())
+}
+
+async fn read_json(path: &Path) -> Result<Value> {
+    let content = fs::read(path).await?;
+    serde_json::from_slice(&content).map_err(Into::into)
+}
+
+/// How an imported report is assigned to an app.
+#[derive(Clone, Copy)]
+enum Assign {
+    /// To the app that the report was stored for.
+    Stored(i64),
+    /// To the app that the report was received for before, or else to `fallback`, if given.
+    Received { fallback: Option<i64> },
+}
+
+/// Import a single report. It's saved to the storage as well, if given, which is only needed if it
+/// doesn't come from the storage already.
+async fn import(
+    pool: &DbConnPool,
+    storage: Option<&Storage>,
+    raw: Value,
+    assign: Assign,
+    in_app: &[String],
+    filters: &FilterCache,
+    summary: &mut ImportSummary,
+) {
+    let report = match serde_json::from_value::<Report>(raw.clone()) {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("invalid report: {e}");
+            summary.failed += 1;
+            return;
+        }
+    };
+
+    let app_id = match assign {
+        Assign::Stored(app_id) => Some(app_id),
+        Assign::Received { fallback } => match repositories::report_repo(pool.clone())
+            .get_app_id(report.id.clone())
+            .await
+        {
+            Ok(app_id) => app_id.or(fallback),
+            Err(e) => {
+                warn!(report_id = %report.id, "failed looking up app: {e:?}");
+                summary.failed += 1;
+                return;
+            }
+        },
+    };
+
+    let Some(app_id) = app_id else {
+        warn!(report_id = %report.id, "report doesn't belong to any known app, skipping it");
+        summary.skipped += 1;
+        return;
+    };
+
+    let filters = match filters.get(pool, app_id).await {
+        Ok(filters) => filters,
+        Err(e) => {
+            warn!(report_id = %report.id, "failed loading inbound filters: {e:?}");
+            summary.failed += 1;
+            return;
+        }
+    };
+
+    if let Some(filter) = filters.first_match(&report) {
+        info!(report_id = %report.id, rule = %filter.rule, "dropped report");
+        match repositories::report_repo(pool.clone())
+            .delete(app_id, report.id.clone())
+            .await
+        {
+            Ok(()) => summary.filtered += 1,
+            Err(e) => {
+                warn!(report_id = %report.id, "failed removing filtered report: {e:?}");
+                summary.failed += 1;
+            }
+        }
+        return;
+    }
+
+    if let Some(storage) = storage
+        && let Err(e) = save_raw(storage, app_id, &raw).await
+    {
+        warn!(report_id = %report.id, "failed saving raw report: {e:?}");
+    }
+
+    match store(pool, app_id, &report, in_app, true).await {
+        Ok(_) => summary.imported += 1,
+        Err(e) => {
+            warn!(report_id = %report.id, "failed storing report: {e:?}");
+            summary.failed += 1;
+        }
+    }
+}
+
+/// Extract the actual reports from a JSON value, which is either a single raw report or an export
+/// of another backend. Exports can contain several reports in an array and wrap the report in an
+/// object with a `content` field, either as nested object or as JSON string, like Acrarium does.
+fn unwrap_reports(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values.into_iter().flat_map(unwrap_reports).collect(),
+        Value::Object(mut object)
+            if !object.contains_key("REPORT_ID") && object.contains_key("content") =>
+        {
+            match object.remove("content").unwrap_or_default() {
+                Value::String(content) => serde_json::from_str::<Value>(&content)
+                    .map_or_else(|_| vec![Value::String(content)], unwrap_reports),
+                content => unwrap_reports(content),
+            }
+        }
+        value => vec![value],
+    }
+}
@@ -0,0 +1,328 @@
+//! Parsing of the logs that ACRA collects, which are usually in the `threadtime` format of logcat:
+//!
+//! ```text
+//! 06-12 09:21:47.091 12345 12345 E AndroidRuntime: FATAL EXCEPTION: main
+//! ```
+//!
+//! The older `time` format, like `06-12 09:21:47.091 E/AndroidRuntime(12345): ...`, is understood
+//! as well. Anything else is kept as plain text lines.
+
+use crate::report::Report;
+
+/// Length of a timestamp like `06-12 09:21:47.091`.
+const TIME_LEN: usize = 18;
+
+/// Priority of a log line, from least to most important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Verbose,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    pub const ALL: [Self; 6] = [
+        Self::Verbose,
+        Self::Debug,
+        Self::Info,
+        Self::Warn,
+        Self::Error,
+        Self::Fatal,
+    ];
+
+    /// Parse the level from its single letter, like `W`.
+    pub fn from_letter(letter: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|level| letter.eq_ignore_ascii_case(level.letter()))
+    }
+
+    pub fn letter(self) -> &'static str {
+        match self {
+            Self::Verbose => "V",
+            Self::Debug => "D",
+            Self::Info => "I",
+            Self::Warn => "W",
+            Self::Error => "E",
+            Self::Fatal => "F",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Verbose => "Verbose",
+            Self::Debug => "Debug",
+            Self::Info => "Info",
+            Self::Warn => "Warning",
+            Self::Error => "Error",
+            Self::Fatal => "Fatal",
+        }
+    }
+}
+
+/// A single log line. All fields except the message are `None` for lines in an unknown format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// Local time of the device, like `06-12 09:21:47.091`.
+    pub time: Option<String>,
+    pub pid: Option<u32>,
+    /// Thread ID, which isn't part of the `time` format.
+    pub tid: Option<u32>,
+    pub level: Option<Level>,
+    pub tag: Option<String>,
+    pub message: String,
+}
+
+impl Line {
+    fn text(message: &str) -> Self {
+        Self {
+            time: None,
+            pid: None,
+            tid: None,
+            level: None,
+            tag: None,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// A parsed log, together with the process and thread that crashed, if they could be found.
+#[derive(Debug, Default)]
+pub struct Log {
+    pub lines: Vec<Line>,
+    pub pid: Option<u32>,
+    pub tid: Option<u32>,
+}
+
+impl Log {
+    pub fn parse(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_line)
+            .collect::<Vec<_>>();
+
+        // The crash handler logs from the crashed thread, followed by the process ID.
+        let mut log = Self::default();
+        for line in &lines {
+            if line.tag.as_deref() != Some("AndroidRuntime") {
+                continue;
+            }
+
+            if line.message.starts_with("FATAL EXCEPTION") {
+                log.pid = log.pid.or(line.pid);
+                log.tid = line.tid;
+            } else if let Some((_, pid)) = line.message.split_once(", PID: ") {
+                log.pid = log.pid.or_else(|| pid.trim().parse().ok());
+            }
+        }
+
+        log.lines = lines;
+        log
+    }
+
+    /// Keep only lines of at least the given level and with one of the given tags. Either filter
+    /// is skipped if not set.
+    pub fn filter(&mut self, level: Option<Level>, tags: &[&str]) {
+        self.lines.retain(|line| {
+            level.is_none_or(|level| line.level.is_some_and(|l| l >= level))
+                && (tags.is_empty() || line.tag.as_deref().is_some_and(|t| tags.contains(&t)))
+        });
+    }
+
+    /// Index of the last line that was logged at or before the given time, in the same format as
+    /// the lines.
+    pub fn last_before(&self, time: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| line.time.as_deref().is_some_and(|t| t <= time))
+    }
+}
+
+/// The logs of a report that can be shown in the viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Logcat,
+    Events,
+    Radio,
+    Application,
+}
+
+impl Source {
+    pub const ALL: [Self; 4] = [Self::Logcat, Self::Events, Self::Radio, Self::Application];
+
+    /// Name as used in URLs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Logcat => "logcat",
+            Self::Events => "events",
+            Self::Radio => "radio",
+            Self::Application => "application",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.as_str() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Logcat => "Logcat",
+            Self::Events => "Events log",
+            Self::Radio => "Radio log",
+            Self::Application => "Application log",
+        }
+    }
+
+    /// The log of the report, or `None` if it wasn't collected.
+    pub fn text(self, report: &Report) -> Option<&str> {
+        let text = match self {
+            Self::Logcat => Some(report.logcat.as_str()),
+            Self::Events => report.eventslog.as_deref(),
+            Self::Radio => report.radiolog.as_deref(),
+            Self::Application => report.application_log.as_deref(),
+        };
+
+        text.filter(|text| !text.trim().is_empty())
+    }
+}
+
+/// Convert a crash date like `2023-06-12T09:21:47.093+02:00` to the local time format of the log
+/// lines. The year is dropped, as logcat doesn't print it.
+pub fn crash_time(crash_date: &str) -> Option<String> {
+    let date = crash_date.get(5..10)?;
+    let time = crash_date.get(11..23)?;
+    let time = format!("{date} {time}");
+
+    is_time(&time).then_some(time)
+}
+
+fn is_time(time: &str) -> bool {
+    time.len() == TIME_LEN
+        && time.bytes().enumerate().all(|(i, b)| match i {
+            2 => b == b'-',
+            5 => b == b' ',
+            8 | 11 => b == b':',
+            14 => b == b'.',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+fn parse_line(line: &str) -> Line {
+    let line = line.trim_end();
+
+    line.get(..TIME_LEN)
+        .filter(|time| is_time(time))
+        .and_then(|time| {
+            let rest = line[TIME_LEN..].trim_start();
+            parse_threadtime(rest)
+                .or_else(|| parse_time(rest))
+                .map(|line| Line {
+                    time: Some(time.to_owned()),
+                    ..line
+                })
+        })
+        .unwrap_or_else(|| Line::text(line))
+}
+
+/// Parse the rest of a line like `12345 12345 E AndroidRuntime: FATAL EXCEPTION: main`.
+fn parse_threadtime(rest: &str) -> Option<Line> {
+    let (pid, rest) = rest.split_once(' ')?;
+    let (tid, rest) = rest.trim_start().split_once(' ')?;
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    // Tags are padded to a minimum width, so the separator can be preceded by spaces.
+    let (tag, message) = rest
+        .split_once(": ")
+        .or_else(|| rest.strip_suffix(':').map(|tag| (tag, "")))?;
+
+    Some(Line {
+        time: None,
+        pid: Some(pid.parse().ok()?),
+        tid: Some(tid.parse().ok()?),
+        level: Some(Level::from_letter(level)?),
+        tag: Some(tag.trim().to_owned()),
+        message: message.to_owned(),
+    })
+}
+
+/// Parse the rest of a line like `E/AndroidRuntime(12345): FATAL EXCEPTION: main`.
+fn parse_time(rest: &str) -> Option<Line> {
+    let (level, rest) = rest.split_once('/')?;
+    let (tag, rest) = rest.split_once('(')?;
+    let (pid, message) = rest.split_once("):")?;
+
+    Some(Line {
+        time: None,
+        pid: Some(pid.trim().parse().ok()?),
+        tid: None,
+        level: Some(Level::from_letter(level)?),
+        tag: Some(tag.trim().to_owned()),
+        message: message.strip_prefix(' ').unwrap_or(message).to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Level, Line, Log, crash_time};
+
+    const THREADTIME: &str = include_str!("../fixtures/logcat/threadtime.txt");
+
+    #[test]
+    fn parse_threadtime() {
+        let log = Log::parse(THREADTIME);
+
+        assert_eq!(12, log.lines.len());
+        assert_eq!((Some(12345), Some(12345)), (log.pid, log.tid));
+        assert_eq!(
+            Line {
+                time: Some("06-12 09:21:45.120".to_owned()),
+                pid: Some(12345),
+                tid: Some(12377),
+                level: Some(Level::Warn),
+                tag: Some("SyncWorker".to_owned()),
+                message: "retrying in 30s: java.net.SocketTimeoutException".to_owned(),
+            },
+            log.lines[3]
+        );
+        // Padded tags and unknown lines.
+        assert_eq!(Some("NoteAdapter"), log.lines[5].tag.as_deref());
+        assert_eq!("--------- beginning of crash", log.lines[6].message);
+        assert_eq!(None, log.lines[6].level);
+        // Leading spaces of the message stay.
+        assert!(log.lines[10].message.starts_with("  Force finishing"));
+    }
+
+    #[test]
+    fn parse_time_format() {
+        let log = Log::parse(
+            "06-12 09:21:47.091 E/AndroidRuntime( 8123): FATAL EXCEPTION: RenderThread\n06-12 \
+             09:21:47.091 E/AndroidRuntime( 8123): Process: com.example.notes, PID: 8123\n",
+        );
+
+        assert_eq!((Some(8123), None), (log.pid, log.tid));
+        assert_eq!(Some(Level::Error), log.lines[0].level);
+        assert_eq!("FATAL EXCEPTION: RenderThread", log.lines[0].message);
+    }
+
+    #[test]
+    fn filter_and_jump() {
+        let mut log = Log::parse(THREADTIME);
+
+        let time = crash_time("2023-06-12T09:21:47.093+02:00").unwrap();
+        assert_eq!("06-12 09:21:47.093", time);
+        assert_eq!(Some(9), log.last_before(&time));
+        assert_eq!(None, log.last_before("06-12 09:00:00.000"));
+        assert_eq!(None, crash_time("yesterday"));
+
+        log.filter(Some(Level::Warn), &[]);
+        assert_eq!(6, log.lines.len());
+        assert!(log.lines.iter().all(|line| line.level >= Some(Level::Warn)));
+
+        log.filter(None, &["AndroidRuntime", "storaged"]);
+        assert_eq!(4, log.lines.len());
+        assert_eq!(Some(3), log.last_before(&time));
+    }
+}
@@ -2,64 +2,178 @@
 #![deny(rust_2018_idioms, clippy::all, clippy::pedantic)]
 #![allow(dead_code)]
 
-use std::{
-    env,
-    net::{Ipv4Addr, SocketAddr},
-    sync::Arc,
-    time::Duration,
-};
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Result;
 use axum::{
     Router,
     error_handling::HandleErrorLayer,
     extract::{DefaultBodyLimit, FromRef},
-    routing::{get, post},
+    routing::{get, post, put},
 };
-use tokio::net::TcpListener;
+use clap::Parser;
 use tokio_shutdown::Shutdown;
 use tower::ServiceBuilder;
-use tower_http::{compression::CompressionLayer, trace::TraceLayer};
-use tracing::{Level, info};
-use tracing_subscriber::{filter::Targets, prelude::*};
+use tower_http::{catch_panic::CatchPanicLayer, compression::CompressionLayer, trace::TraceLayer};
 
-use self::{db::DbConnPool, settings::Auth};
+use self::{
+    cli::{Cli, Command, ConfigCommand},
+    db::DbConnPool,
+    filters::FilterCache,
+    handlers::apps::ReprocessLock,
+    notifications::Mailer,
+    settings::{Metrics, Server, Settings},
+    storage::Storage,
+};
 
+mod admin;
+mod backup;
+mod cli;
+mod conditions;
 mod db;
 mod dirs;
+mod dropbox;
+mod environments;
 mod extractors;
+mod filters;
+#[cfg(test)]
+mod fixtures;
+mod grouping;
 mod handlers;
+mod ingest;
+mod logcat;
+mod metrics;
+mod notifications;
 mod report;
 mod retrace;
+mod server;
 mod settings;
+mod stacktrace;
+mod storage;
+mod symbols;
+mod tags;
+mod telemetry;
 mod templates;
 
-const ADDRESS: Ipv4Addr = if cfg!(debug_assertions) {
-    Ipv4Addr::LOCALHOST
-} else {
-    Ipv4Addr::UNSPECIFIED
-};
-
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<()> {
-    let settings = settings::load()?;
-
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer())
-        .with(
-            Targets::new()
-                .with_target(env!("CARGO_CRATE_NAME"), Level::TRACE)
-                .with_target("tower_http", Level::TRACE)
-                .with_default(Level::INFO),
-        )
-        .init();
+    let cli = Cli::parse();
+    let settings = settings::load(cli.config.as_deref(), &cli.overrides)?;
 
-    let settings = Arc::new(settings.auth);
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(settings).await,
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => config_check(&settings),
+        Command::Migrate => {
+            crate::db::run_migrations(&crate::db::create_pool(&settings.database)?)?;
+            println!("database is up to date");
+            Ok(())
+        }
+        Command::User { command } => admin::user(open_db(&settings)?, command).await,
+        Command::App { command } => admin::app(open_db(&settings)?, command).await,
+        Command::Reports { command } => {
+            let storage = Storage::new(&settings.storage)?;
+            admin::reports(
+                open_db(&settings)?,
+                storage,
+                &settings.stack_trace.in_app,
+                command,
+            )
+            .await
+        }
+        Command::Backup { command } => {
+            let storage = Storage::new(&settings.storage)?;
+            admin::backup(settings.backup.as_ref(), &storage, command, || {
+                open_db(&settings)
+            })
+            .await
+        }
+    }
+}
 
-    let pool = crate::db::create_pool()?;
+/// Open the database for administrative commands, making sure it has the latest schema.
+fn open_db(settings: &Settings) -> Result<DbConnPool> {
+    let pool = crate::db::create_pool(&settings.database)?;
     crate::db::run_migrations(&pool)?;
+    Ok(pool)
+}
+
+fn config_check(settings: &Settings) -> Result<()> {
+    settings.validate()?;
+    print!("{}", toml::to_string_pretty(&settings.masked())?);
+    Ok(())
+}
+
+async fn serve(settings: Settings) -> Result<()> {
+    settings.validate()?;
+
+    // Held until the server stops, to flush out all spans on the way out.
+    let _telemetry = telemetry::init(settings.tracing.as_ref())?;
+
+    let mailer = settings
+        .smtp
+        .as_ref()
+        .map(Mailer::new)
+        .transpose()?
+        .map(Arc::new);
+    let storage = Storage::new(&settings.storage)?;
+    let backup = settings.backup;
+    let database = settings.database;
+    let server = Arc::new(settings.server);
+    let metrics = Arc::new(settings.metrics);
+    let stack_trace = Arc::new(settings.stack_trace);
+
+    let pool = crate::db::create_pool(&database)?;
+    crate::db::run_migrations(&pool)?;
+    crate::db::spawn_maintenance(&pool);
+
+    if let Some(mailer) = &mailer {
+        notifications::spawn_digest(pool.clone(), Arc::clone(mailer));
+    }
+
+    if let Some(backup) = backup {
+        backup::spawn_scheduled(
+            pool.clone(),
+            backup,
+            storage.local_dir().map(ToOwned::to_owned),
+        );
+    }
+
+    let app = router(AppState {
+        server: Arc::clone(&server),
+        metrics,
+        stack_trace,
+        pool,
+        storage,
+        mailer,
+        filters: FilterCache::default(),
+        reprocess: ReprocessLock::default(),
+    });
+
+    let shutdown = Shutdown::new()?;
 
-    let app = Router::new()
+    server::serve(&server, app, &shutdown).await
+}
+
+fn router(state: AppState) -> Router {
+    // Mapping and debug files can be large, so their uploads get more time than other requests.
+    let uploads = Router::new()
+        .route(
+            "/mappings/{version_code}",
+            put(handlers::mapping_save).layer(DefaultBodyLimit::max(extractors::MAPPING_LIMIT)),
+        )
+        .route(
+            "/symbols/{build_id}",
+            put(handlers::symbols_save).layer(DefaultBodyLimit::max(extractors::SYMBOLS_LIMIT)),
+        )
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handlers::error::timeout))
+                .timeout(Duration::from_mins(10)),
+        );
+
+    Router::new()
         .route("/", get(async || handlers::index()))
         .nest(
             "/users",
@@ -68,54 +182,85 @@ async fn main() -> Result<()> {
                     "/create",
                     get(handlers::users::create).post(handlers::users::create_post),
                 )
+                .route(
+                    "/{id}/notifications",
+                    get(handlers::users::notifications).post(handlers::users::notifications_post),
+                )
                 .route("/", get(handlers::users::list)),
         )
         .nest(
             "/apps",
             Router::new()
-                .route("/:id", get(handlers::versions_list))
+                .route("/{id}", get(handlers::versions_list))
                 .route(
                     "/create",
                     get(handlers::apps::create).post(async || handlers::apps::create_post()),
                 )
+                .route(
+                    "/{id}/settings",
+                    get(handlers::apps::settings).post(handlers::apps::settings_post),
+                )
+                .route("/reprocess", post(handlers::apps::reprocess))
                 .route("/", get(handlers::apps::list)),
         )
         .route(
             "/report",
-            post(handlers::report_save).layer(DefaultBodyLimit::max(1024 * 512)),
+            post(handlers::report_save).layer(DefaultBodyLimit::max(extractors::REPORT_LIMIT)),
+        )
+        .route("/issues/{id}", get(handlers::issues::details))
+        .route("/reports/{id}", get(handlers::reports::details))
+        .route("/reports/{id}/logs", get(handlers::reports::logs))
+        .route(
+            "/tools/retrace",
+            get(handlers::tools::retrace)
+                .post(handlers::tools::retrace_post)
+                .layer(DefaultBodyLimit::max(extractors::RETRACE_LIMIT)),
         )
-        .with_state(AppState { settings, pool })
+        .route("/api/retrace", post(handlers::tools::retrace_api))
+        .route("/metrics", get(metrics::handler))
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(handlers::error::timeout))
-                .timeout(Duration::from_secs(10))
+                .timeout(Duration::from_secs(10)),
+        )
+        .merge(uploads)
+        .with_state(state)
+        .layer(
+            ServiceBuilder::new()
                 .layer(TraceLayer::new_for_http())
                 .layer(CompressionLayer::new())
+                .layer(CatchPanicLayer::custom(handlers::error::panic))
                 .into_inner(),
-        );
-
-    let addr = SocketAddr::from((ADDRESS, 8080));
-    let shutdown = Shutdown::new()?;
-
-    let listener = TcpListener::bind(addr).await?;
-    let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.handle());
-
-    info!("listening on http://{}", addr);
-
-    server.await?;
-
-    Ok(())
+        )
 }
 
 #[derive(Clone)]
 struct AppState {
-    settings: Arc<Auth>,
+    server: Arc<Server>,
+    metrics: Arc<Metrics>,
+    stack_trace: Arc<settings::StackTrace>,
     pool: DbConnPool,
+    storage: Storage,
+    mailer: Option<Arc<Mailer>>,
+    filters: FilterCache,
+    reprocess: ReprocessLock,
+}
+
+impl FromRef<AppState> for Arc<Server> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.server)
+    }
 }
 
-impl FromRef<AppState> for Arc<Auth> {
+impl FromRef<AppState> for Arc<Metrics> {
     fn from_ref(input: &AppState) -> Self {
-        Arc::clone(&input.settings)
+        Arc::clone(&input.metrics)
+    }
+}
+
+impl FromRef<AppState> for Arc<settings::StackTrace> {
+    fn from_ref(input: &AppState) -> Self {
+        Arc::clone(&input.stack_trace)
     }
 }
 
@@ -124,3 +269,1243 @@ impl FromRef<AppState> for DbConnPool {
         input.pool.clone()
     }
 }
+
+impl FromRef<AppState> for Storage {
+    fn from_ref(input: &AppState) -> Self {
+        input.storage.clone()
+    }
+}
+
+impl FromRef<AppState> for FilterCache {
+    fn from_ref(input: &AppState) -> Self {
+        input.filters.clone()
+    }
+}
+
+impl FromRef<AppState> for ReprocessLock {
+    fn from_ref(input: &AppState) -> Self {
+        input.reprocess.clone()
+    }
+}
+
+/// Drive the full router like a real client would, and check the outcome in the database.
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc, time::Duration};
+
+    use axum::{
+        Router,
+        body::{self, Body},
+        extract::ConnectInfo,
+        http::{
+            Request, StatusCode,
+            header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+        },
+    };
+    use scraper::{ElementRef, Html, Selector};
+    use serde_json::{Value, json};
+    use tower::Service;
+
+    use super::{AppState, ReprocessLock, router};
+    use crate::{
+        db::{
+            DbConnPool,
+            models::{CrashType, NewIssue},
+            repositories,
+        },
+        extractors,
+        filters::FilterCache,
+        fixtures::{self, GALAXY, PIXEL, PIXEL_ID, pixel},
+        ingest,
+        server::PeerAddr,
+        settings,
+        storage::Storage,
+        symbols,
+    };
+
+    /// Basic auth header for the `test:test` credentials.
+    const AUTH: &str = "Basic dGVzdDp0ZXN0";
+    /// Basic auth header with a wrong password.
+    const WRONG_AUTH: &str = "Basic dGVzdDp3cm9uZw==";
+    const DAY: Duration = Duration::from_hours(24);
+    const MAPPING: &str = "com.example.Foo -> a.b.c:\n    1:1:void run():20:20 -> a\n";
+    const OBFUSCATED: &str = "java.lang.ClassCastException: a.b.c cannot be cast to \
+                              java.lang.String\n\tat a.b.c.a(SourceFile:1)";
+
+    struct TestApp {
+        router: Router,
+        pool: DbConnPool,
+        storage: Storage,
+        reprocess: ReprocessLock,
+        dir: PathBuf,
+    }
+
+    impl TestApp {
+        async fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("acralite-router-{name}-{}", std::process::id()));
+            let pool = fixtures::memory_pool();
+            crate::db::run_migrations(&pool).unwrap();
+            fixtures::test_app(&pool).await;
+
+            let storage = Storage::new(&settings::Storage {
+                dir: Some(dir.clone()),
+                ..settings::Storage::default()
+            })
+            .unwrap();
+
+            let reprocess = ReprocessLock::default();
+
+            let router = router(AppState {
+                server: Arc::default(),
+                metrics: Arc::default(),
+                stack_trace: Arc::default(),
+                pool: pool.clone(),
+                storage: storage.clone(),
+                mailer: None,
+                filters: FilterCache::default(),
+                reprocess: reprocess.clone(),
+            });
+
+            Self {
+                router,
+                pool,
+                storage,
+                reprocess,
+                dir,
+            }
+        }
+
+        async fn send(&self, request: Request<Body>) -> (StatusCode, String, Option<String>) {
+            let response = self.router.clone().call(request).await.unwrap();
+            let status = response.status();
+            let authenticate = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .map(|value| value.to_str().unwrap().to_owned());
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+
+            (
+                status,
+                String::from_utf8(body.to_vec()).unwrap(),
+                authenticate,
+            )
+        }
+
+        async fn report(&self, auth: Option<&str>, body: impl Into<Body>) -> StatusCode {
+            let mut request = Request::post("/report").header(CONTENT_TYPE, "application/json");
+            if let Some(auth) = auth {
+                request = request.header(AUTHORIZATION, auth);
+            }
+
+            self.send(request.body(body.into()).unwrap()).await.0
+        }
+
+        /// Send the [`PIXEL`] report with some of its fields changed, which must be accepted.
+        async fn ingest(&self, change: impl FnOnce(&mut Value)) {
+            let status = self.report(Some(AUTH), pixel(change).to_string()).await;
+            assert_eq!(StatusCode::OK, status);
+        }
+
+        async fn upload_mapping(&self, version_code: i64, content: impl Into<Body>) -> StatusCode {
+            let request = Request::put(format!("/mappings/{version_code}"))
+                .header(AUTHORIZATION, AUTH)
+                .body(content.into())
+                .unwrap();
+            self.send(request).await.0
+        }
+
+        async fn page(&self, uri: impl AsRef<str>) -> (StatusCode, Page) {
+            let (status, body, _) = self
+                .send(Request::get(uri.as_ref()).body(Body::empty()).unwrap())
+                .await;
+            (status, Page::parse(&body))
+        }
+
+        async fn save_settings(&self, form: &str) -> StatusCode {
+            let request = Request::post("/apps/1/settings")
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form.to_owned()))
+                .unwrap();
+            self.send(request).await.0
+        }
+    }
+
+    impl Drop for TestApp {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    /// A rendered page, to look up its content by CSS selectors.
+    struct Page(Html);
+
+    impl Page {
+        fn parse(body: &str) -> Self {
+            Self(Html::parse_document(body))
+        }
+
+        /// Text of all elements that match the selector, with whitespace collapsed.
+        fn texts(&self, selector: &str) -> Vec<String> {
+            self.0.select(&parse_selector(selector)).map(text).collect()
+        }
+
+        /// Value of an attribute of all elements that match the selector.
+        fn attrs(&self, selector: &str, name: &str) -> Vec<String> {
+            self.0
+                .select(&parse_selector(selector))
+                .filter_map(|element| element.value().attr(name))
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+
+        /// Text of the cells of all table rows that match the selector, header cells included.
+        fn rows(&self, selector: &str) -> Vec<Vec<String>> {
+            let cells = parse_selector("th, td");
+            self.0
+                .select(&parse_selector(selector))
+                .map(|row| row.select(&cells).map(text).collect())
+                .collect()
+        }
+
+        /// Text of the other cells in the first table row that starts with the given header.
+        fn row(&self, header: &str) -> Option<Vec<String>> {
+            self.rows("tr")
+                .into_iter()
+                .find(|cells| cells.first().is_some_and(|first| first == header))
+                .map(|mut cells| cells.split_off(1))
+        }
+
+        /// IDs of the reports that the page links to, in order.
+        fn report_links(&self) -> Vec<String> {
+            self.texts("td a[href^='/reports/']")
+        }
+
+        /// Content of the text area of a form field, as it would be submitted.
+        fn field(&self, name: &str) -> Option<String> {
+            self.0
+                .select(&parse_selector(&format!("textarea[name={name}]")))
+                .next()
+                .map(|field| field.text().collect())
+        }
+
+        /// Labels and links of the environment selection, with the selected one marked.
+        fn environment_tabs(&self) -> Vec<(String, String, bool)> {
+            self.0
+                .select(&parse_selector(".buttons.has-addons a"))
+                .map(|tab| {
+                    let selected = tab.value().classes().any(|class| class == "is-selected");
+                    let href = tab.value().attr("href").unwrap_or_default().to_owned();
+                    (text(tab), href, selected)
+                })
+                .collect()
+        }
+    }
+
+    fn parse_selector(selector: &str) -> Selector {
+        Selector::parse(selector).unwrap()
+    }
+
+    fn text(element: ElementRef<'_>) -> String {
+        element
+            .text()
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn with_report_id(id: &str) -> impl FnOnce(&mut Value) {
+        move |report| report["REPORT_ID"] = json!(id)
+    }
+
+    #[tokio::test]
+    async fn ingest_reports() {
+        let app = TestApp::new("ingest").await;
+
+        assert_eq!(StatusCode::OK, app.report(Some(AUTH), PIXEL).await);
+        app.ingest(with_report_id("pixel-again")).await;
+        assert_eq!(StatusCode::OK, app.report(Some(AUTH), GALAXY).await);
+
+        let test_app = repositories::app_repo(app.pool.clone())
+            .get_by_username("test".to_owned())
+            .await
+            .unwrap();
+
+        let mut versions = repositories::version_repo(app.pool.clone())
+            .list_by_app(test_app.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.name, v.code))
+            .collect::<Vec<_>>();
+        versions.sort();
+        assert_eq!(
+            vec![("1.3.2".to_owned(), 13), ("1.4.0".to_owned(), 14),],
+            versions
+        );
+
+        // The same crash is grouped into a single issue.
+        let mut issues = repositories::issue_repo(app.pool.clone())
+            .list_top(DAY, None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.count, issue.title))
+            .collect::<Vec<_>>();
+        issues.sort();
+        assert_eq!(2, issues.len());
+        assert_eq!(1, issues[0].0);
+        assert!(issues[0].1.contains("IllegalStateException"));
+        assert_eq!(2, issues[1].0);
+        assert!(issues[1].1.starts_with("java.lang.NullPointerException"));
+
+        let reports = repositories::report_repo(app.pool.clone());
+        for id in [
+            PIXEL_ID,
+            "pixel-again",
+            "c4e19b72-8d05-4a6f-b3a1-5f0e2d9c6b48",
+        ] {
+            assert_eq!(
+                Some(test_app.id),
+                reports.get_app_id(id.to_owned()).await.unwrap()
+            );
+        }
+
+        assert_eq!(3, app.storage.list_reports().await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn reprocess() {
+        let app = TestApp::new("reprocess").await;
+        assert_eq!(StatusCode::OK, app.report(Some(AUTH), PIXEL).await);
+
+        // A report that was kept, but failed to be stored in the database.
+        let mut lost = serde_json::from_str::<Value>(GALAXY).unwrap();
+        with_report_id("lost")(&mut lost);
+        app.storage.save_report(1, "lost", &lost).await.unwrap();
+        // An issue that all of its reports moved away from.
+        let issues = repositories::issue_repo(app.pool.clone());
+        let (empty_id, _) = issues
+            .get_or_create(NewIssue {
+                app_id: 1,
+                fingerprint: "0123456789abcdef".to_owned(),
+                title: "java.lang.IllegalStateException: regrouped".to_owned(),
+                crash_type: CrashType::Fatal,
+            })
+            .await
+            .unwrap();
+
+        let summary = ingest::import_stored(&app.pool, &app.storage, &[], &FilterCache::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            (2, 0, 0, 0, 1),
+            (
+                summary.imported,
+                summary.skipped,
+                summary.failed,
+                summary.filtered,
+                summary.removed_issues
+            )
+        );
+
+        assert_eq!(
+            Some(1),
+            repositories::report_repo(app.pool.clone())
+                .get_app_id("lost".to_owned())
+                .await
+                .unwrap()
+        );
+        assert!(issues.get(empty_id).await.is_err());
+        assert_eq!(2, issues.list_top(DAY, None, 10).await.unwrap().len());
+
+        // Reports that a filter added later on drops are removed by the next run.
+        repositories::filter_repo(app.pool.clone())
+            .save(1, vec!["version_code:..1000".to_owned()])
+            .await
+            .unwrap();
+        let summary = ingest::import_stored(&app.pool, &app.storage, &[], &FilterCache::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            (0, 2, 2),
+            (summary.imported, summary.filtered, summary.removed_issues)
+        );
+        assert!(issues.list_top(DAY, None, 10).await.unwrap().is_empty());
+
+        // Only a single run may happen at a time.
+        let reprocess = || {
+            app.send(
+                Request::post("/apps/reprocess")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let running = app.reprocess.try_start().unwrap();
+        assert_eq!(StatusCode::CONFLICT, reprocess().await.0);
+        drop(running);
+        assert_eq!(StatusCode::OK, reprocess().await.0);
+    }
+
+    #[tokio::test]
+    async fn reject_unauthorized() {
+        let app = TestApp::new("unauthorized").await;
+
+        for auth in [None, Some(WRONG_AUTH)] {
+            let mut request = Request::post("/report").header(CONTENT_TYPE, "application/json");
+            if let Some(auth) = auth {
+                request = request.header(AUTHORIZATION, auth);
+            }
+
+            let (status, _, authenticate) =
+                app.send(request.body(Body::from(PIXEL)).unwrap()).await;
+            assert_eq!(StatusCode::UNAUTHORIZED, status, "auth {auth:?}");
+            assert_eq!(Some("Basic"), authenticate.as_deref());
+        }
+
+        assert_eq!(
+            None,
+            repositories::report_repo(app.pool.clone())
+                .get_app_id(PIXEL_ID.to_owned())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn rotated_credentials() {
+        let app = TestApp::new("rotated").await;
+        repositories::app_repo(app.pool.clone())
+            .update_credentials(1, "rotated".to_owned(), "s3cret".to_owned())
+            .await
+            .unwrap();
+
+        // The previous credentials stop working right away.
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            app.report(Some(AUTH), PIXEL).await
+        );
+        assert_eq!(
+            StatusCode::OK,
+            app.report(Some("Basic cm90YXRlZDpzM2NyZXQ="), PIXEL).await
+        );
+
+        assert_eq!(
+            Some(1),
+            repositories::report_repo(app.pool.clone())
+                .get_app_id(PIXEL_ID.to_owned())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_invalid() {
+        let app = TestApp::new("invalid").await;
+
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.report(Some(AUTH), "{\"REPORT_ID\":").await
+        );
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.report(Some(AUTH), "{\"REPORT_ID\":\"incomplete\"}")
+                .await
+        );
+
+        let (status, ..) = app
+            .send(
+                Request::post("/report")
+                    .header(AUTHORIZATION, AUTH)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(Body::from(PIXEL))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, status);
+
+        assert!(
+            repositories::version_repo(app.pool.clone())
+                .list()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn report_attachments() {
+        let app = TestApp::new("attachments").await;
+        let upload = |attachment: &str| {
+            let body = format!(
+                "--X\r\nContent-Disposition: form-data; \
+                 name=\"ACRA_REPORT\"\r\n\r\n{}\r\n--X\r\nContent-Disposition: form-data; \
+                 name=\"ACRA_ATTACHMENT\"; filename=\"notes.db\"\r\n\r\n{attachment}\r\n--X--\r\n",
+                pixel(|_| {})
+            );
+            app.send(
+                Request::post("/report")
+                    .header(AUTHORIZATION, AUTH)
+                    .header(CONTENT_TYPE, "multipart/mixed; boundary=X")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(StatusCode::OK, upload("notes").await.0);
+        let attachments = app.storage.list_attachments(1, PIXEL_ID).await.unwrap();
+        assert_eq!(1, attachments.len());
+
+        let (status, ..) = upload(&"x".repeat(extractors::REPORT_LIMIT)).await;
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+    }
+
+    #[tokio::test]
+    async fn client_ip() {
+        let app = TestApp::new("client-ip").await;
+
+        // The address the device collected itself is of no use, so it's replaced.
+        let report = pixel(|report| report["USER_IP"] = json!("192.168.178.20"));
+        let mut request = Request::post("/report")
+            .header(AUTHORIZATION, AUTH)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(report.to_string()))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(PeerAddr::Tcp(
+            "203.0.113.7:1234".parse().unwrap(),
+        )));
+
+        let (status, ..) = app.send(request).await;
+        assert_eq!(StatusCode::OK, status);
+
+        let raw = app.storage.report(1, PIXEL_ID).await.unwrap().unwrap();
+        assert_eq!(json!("203.0.113.7"), raw["USER_IP"]);
+    }
+
+    #[tokio::test]
+    async fn error_page() {
+        let app = TestApp::new("error-page").await;
+
+        let (status, body, _) = app
+            .send(
+                Request::post("/users/create")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("username=admin&password=secret"))
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(StatusCode::CONFLICT, status);
+        let page = Page::parse(&body);
+        assert_eq!(vec!["☠️ Conflict"], page.texts(".box .title"));
+        assert_eq!(
+            vec!["The user `admin` already exists"],
+            page.texts(".box .subtitle")
+        );
+
+        let (status, page) = app.page("/apps/999").await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!(vec!["app not found"], page.texts(".box .subtitle"));
+    }
+
+    #[tokio::test]
+    async fn report_page() {
+        let app = TestApp::new("report-page").await;
+        app.ingest(|report| {
+            report["LOGCAT"] = json!("E/Notes: failed opening a.b.c");
+            report["STACK_TRACE"] = json!(
+                "java.lang.IllegalStateException: boom\n\tat \
+                 com.example.notes.Editor.save(Editor.kt:3)\n\tat \
+                 android.view.View.performClick(View.java:7448)\n\tat \
+                 android.os.Handler.handleCallback(Handler.java:938)\n\tat \
+                 com.example.notes.Main.run(Main.kt:1)"
+            );
+        })
+        .await;
+
+        let page = |query: &str| app.page(format!("/reports/{PIXEL_ID}{query}"));
+        let logcat = |page: &Page| page.texts("pre").pop();
+
+        let (status, report) = page("").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            Some(vec!["com.example.notes".to_owned()]),
+            report.row("Package")
+        );
+        assert_eq!(
+            Some("E/Notes: failed opening a.b.c".to_owned()),
+            logcat(&report)
+        );
+        // Only the frames outside of the app are collapsed.
+        assert_eq!(
+            vec!["2 framework frames"],
+            report.texts("pre details summary")
+        );
+        assert!(
+            report.texts("pre").contains(
+                &"java.lang.IllegalStateException: boom at \
+                  com.example.notes.Editor.save(Editor.kt:3) 2 framework frames at \
+                  android.view.View.performClick(View.java:7448) at \
+                  android.os.Handler.handleCallback(Handler.java:938) at \
+                  com.example.notes.Main.run(Main.kt:1)"
+                    .to_owned()
+            )
+        );
+
+        // Without a mapping file, the original text is shown with a warning.
+        let (_, report) = page("?retraced=true").await;
+        assert_eq!(1, report.texts(".message.is-warning").len());
+        assert_eq!(vec!["Deobfuscated"], report.texts(".tabs li.is-active"));
+
+        let upload = |auth: &'static str| {
+            app.send(
+                Request::put("/mappings/14")
+                    .header(AUTHORIZATION, auth)
+                    .body(Body::from("com.example.notes.ui.Editor -> a.b.c:\n"))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(StatusCode::UNAUTHORIZED, upload(WRONG_AUTH).await.0);
+        // A character cut off at the end isn't valid text.
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.upload_mapping(14, vec![b'a', 0xc3]).await
+        );
+        assert_eq!(StatusCode::NO_CONTENT, upload(AUTH).await.0);
+
+        let (status, report) = page("?retraced=true").await;
+        assert_eq!(StatusCode::OK, status);
+        assert!(report.texts(".message.is-warning").is_empty());
+        assert_eq!(
+            Some("E/Notes: failed opening com.example.notes.ui.Editor".to_owned()),
+            logcat(&report)
+        );
+
+        assert_eq!(StatusCode::NOT_FOUND, app.page("/reports/unknown").await.0);
+    }
+
+    #[tokio::test]
+    async fn dropbox_issues() {
+        let app = TestApp::new("dropbox-issues").await;
+        app.ingest(|report| {
+            report["DROPBOX"] =
+                serde_json::from_str(include_str!("../fixtures/dropbox/dropbox.json")).unwrap();
+        })
+        .await;
+
+        // Crashes of other processes, like system services, don't become issues of the app.
+        let mut titles = repositories::issue_repo(app.pool.clone())
+            .list_top(DAY, None, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.title, issue.count))
+            .collect::<Vec<_>>();
+        titles.sort();
+        assert_eq!(3, titles.len());
+        assert!(titles[0].0.starts_with("ANR: Input dispatching timed out"));
+        assert_eq!(
+            (
+                "SIGSEGV in libnotes.so (render_note(Note const*)+48)".to_owned(),
+                1
+            ),
+            titles[1]
+        );
+        assert!(titles[2].0.starts_with("java.lang.NullPointerException"));
+
+        // The report is linked to the issues, rather than stored once for each of them, and
+        // importing it again keeps it that way.
+        ingest::import_stored(&app.pool, &app.storage, &[], &FilterCache::default())
+            .await
+            .unwrap();
+        let issues = repositories::issue_repo(app.pool.clone())
+            .list_top(DAY, None, 10)
+            .await
+            .unwrap();
+        assert_eq!(3, issues.len());
+        for issue in issues {
+            assert_eq!(1, issue.count, "{}", issue.title);
+            let reports = repositories::report_repo(app.pool.clone())
+                .list_by_issue(issue.id, None, Vec::new(), 10)
+                .await
+                .unwrap();
+            assert_eq!(1, reports.len(), "{}", issue.title);
+        }
+
+        let (status, report) = app.page(format!("/reports/{PIXEL_ID}")).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            vec!["SYSTEM_TOMBSTONE", "data_app_anr", "data_app_native_crash"],
+            report.texts("h3 .tag")
+        );
+        assert!(
+            report
+                .texts("td code")
+                .contains(&"6f1e2d3c4b5a69788796a5b4c3d2e1f0a9b8c7d6".to_owned())
+        );
+        assert!(
+            report
+                .texts("p code")
+                .iter()
+                .any(|message| message.starts_with("Check failed: layer"))
+        );
+        assert!(
+            report
+                .texts("pre")
+                .iter()
+                .any(|thread| thread.contains("- waiting to lock"))
+        );
+    }
+
+    #[tokio::test]
+    async fn log_viewer() {
+        let app = TestApp::new("log-viewer").await;
+        app.ingest(|report| {
+            report["LOGCAT"] = json!(include_str!("../fixtures/logcat/threadtime.txt"));
+        })
+        .await;
+
+        let page = |query: &str| app.page(format!("/reports/{PIXEL_ID}/logs{query}"));
+
+        let (status, logs) = page("").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec!["#crash"], logs.attrs("a.button[href^='#']", "href"));
+        assert!(!logs.rows("tr.has-background-danger-light").is_empty());
+        let crash = logs.rows("tr#crash");
+        assert_eq!(1, crash.len());
+        assert_eq!(
+            ["E", "AndroidRuntime"],
+            [crash[0][3].as_str(), crash[0][4].as_str()]
+        );
+        assert!(crash[0][5].starts_with("java.lang.NullPointerException"));
+
+        let (status, logs) = page("?log=logcat&level=E&tag=storaged").await;
+        assert_eq!(StatusCode::OK, status);
+        let lines = logs.rows("tbody tr");
+        assert!(!lines.is_empty());
+        assert!(
+            lines
+                .iter()
+                .all(|line| line[3] == "E" && line[4] == "storaged")
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line[5].contains("getDiskStats failed"))
+        );
+
+        assert_eq!(StatusCode::BAD_REQUEST, page("?level=X").await.0);
+        assert_eq!(StatusCode::BAD_REQUEST, page("?log=kernel").await.0);
+    }
+
+    #[tokio::test]
+    async fn device_conditions() {
+        let app = TestApp::new("device-conditions").await;
+
+        // A second report of the same crash, without a configuration change but low on memory.
+        app.ingest(|_| {}).await;
+        app.ingest(|report| {
+            report["REPORT_ID"] = json!("low-memory");
+            report["CRASH_CONFIGURATION"] = report["INITIAL_CONFIGURATION"].clone();
+            report["AVAILABLE_MEM_SIZE"] = json!(1_000_000_000);
+        })
+        .await;
+
+        let (status, report) = app.page(format!("/reports/{PIXEL_ID}")).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            Some(vec!["Day".to_owned(), "Night".to_owned()]),
+            report.row("Night mode")
+        );
+        assert_eq!(
+            Some(vec!["61.8 GB of 115.3 GB (53%)".to_owned()]),
+            report.row("Available memory")
+        );
+        assert!(report.texts("p.mb-3")[0].ends_with("Java heap 50% used."));
+        assert!(report.row("Dalvik Heap").is_some());
+
+        let (_, report) = app.page("/reports/low-memory").await;
+        assert_eq!(
+            Some(vec!["1.0 GB of 115.3 GB (0%) Low memory".to_owned()]),
+            report.row("Available memory")
+        );
+        assert_eq!(None, report.row("Night mode"));
+
+        let (status, issue) = app.page("/issues/1").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            Some(vec!["1 of 2 reports (50%)".to_owned()]),
+            issue.row("Low memory")
+        );
+        assert_eq!(
+            Some(vec!["1 of 2 reports (50%)".to_owned()]),
+            issue.row("Night mode changed")
+        );
+    }
+
+    #[tokio::test]
+    async fn report_tags() {
+        let app = TestApp::new("report-tags").await;
+
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.save_settings("tag_keys=tier").await
+        );
+        assert_eq!(
+            StatusCode::SEE_OTHER,
+            app.save_settings("tag_keys=CUSTOM_DATA.tier%0D%0ABUILD_CONFIG.BUILD_TYPE")
+                .await
+        );
+
+        for (id, tier) in [("premium-user", "premium"), ("free-user", "free")] {
+            app.ingest(|report| {
+                report["REPORT_ID"] = json!(id);
+                report["CUSTOM_DATA"] = json!({ "tier": tier, "session": "4f2a" });
+            })
+            .await;
+        }
+
+        let (_, settings) = app.page("/apps/1/settings").await;
+        assert_eq!(
+            Some("BUILD_CONFIG.BUILD_TYPE\nCUSTOM_DATA.tier".to_owned()),
+            settings.field("tag_keys")
+        );
+
+        let (status, issue) = app.page("/issues/1").await;
+        assert_eq!(StatusCode::OK, status);
+        let mut tags = issue.rows("table:has(th:first-child + th) tbody tr");
+        tags.retain(|row| row.len() == 3);
+        tags.sort();
+        assert_eq!(
+            vec![
+                vec!["BUILD_TYPE", "release", "2 (100%)"],
+                vec!["tier", "free", "1 (50%)"],
+                vec!["tier", "premium", "1 (50%)"],
+            ],
+            tags
+        );
+        assert!(
+            issue
+                .attrs("td a[href*='tags=']", "href")
+                .iter()
+                .any(|href| href.ends_with("tags=tier%3Apremium"))
+        );
+        let mut reports = issue.report_links();
+        reports.sort();
+        assert_eq!(vec!["free-user", "premium-user"], reports);
+
+        let (status, issue) = app
+            .page("/issues/1?tags=tier:premium+BUILD_TYPE:release")
+            .await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec!["premium-user"], issue.report_links());
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.page("/issues/1?tags=tier").await.0
+        );
+
+        // Keys that aren't extracted as tags are still shown on the report page.
+        let (_, report) = app.page("/reports/premium-user").await;
+        assert_eq!(
+            Some(vec!["4f2a".to_owned()]),
+            report.row("CUSTOM_DATA.session")
+        );
+        assert!(report.row("BUILD_CONFIG.VERSION_NAME").is_some());
+        assert_eq!(
+            Some(vec!["premium".to_owned()]),
+            report.row("CUSTOM_DATA.tier tag")
+        );
+        assert_eq!(2, report.texts("th .tag").len());
+    }
+
+    #[tokio::test]
+    async fn environments() {
+        let app = TestApp::new("environments").await;
+
+        let rules = "BUILD_CONFIG.FLAVOR%3Astaging+%3D+staging";
+        assert_eq!(
+            StatusCode::SEE_OTHER,
+            app.save_settings(&format!("tag_keys=&environment_rules={rules}"))
+                .await
+        );
+
+        let builds = [
+            (
+                "release-build",
+                json!({ "BUILD_TYPE": "release", "DEBUG": false }),
+            ),
+            (
+                "debug-build",
+                json!({ "BUILD_TYPE": "debug", "DEBUG": true }),
+            ),
+            (
+                "staging-build",
+                json!({ "BUILD_TYPE": "debug", "FLAVOR": "staging" }),
+            ),
+        ];
+        for (id, build_config) in builds {
+            app.ingest(|report| {
+                report["REPORT_ID"] = json!(id);
+                report["BUILD_CONFIG"] = build_config;
+            })
+            .await;
+        }
+
+        // Only production reports are shown by default.
+        let (status, issue) = app.page("/issues/1").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec!["release-build"], issue.report_links());
+        let tab = |label: &str, selected| {
+            (
+                label.to_owned(),
+                format!("/issues/1?environment={label}"),
+                selected,
+            )
+        };
+        assert_eq!(
+            vec![
+                tab("development", false),
+                tab("production", true),
+                tab("staging", false),
+                tab("all", false),
+            ],
+            issue.environment_tabs()
+        );
+
+        let (_, issue) = app.page("/issues/1?environment=development").await;
+        assert_eq!(vec!["debug-build"], issue.report_links());
+
+        let (_, issue) = app.page("/issues/1?environment=all").await;
+        let mut reports = issue.report_links();
+        reports.sort();
+        assert_eq!(
+            vec!["debug-build", "release-build", "staging-build"],
+            reports
+        );
+        assert_eq!(
+            Some(vec!["3 of 3 reports (100%)".to_owned()]),
+            issue.row("Night mode changed")
+        );
+
+        let (status, details) = app.page("/apps/1?environment=staging").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            vec!["/issues/1?environment=staging"],
+            details.attrs("td a[href^='/issues/']", "href")
+        );
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.page("/issues/1?environment=Not%20Valid").await.0
+        );
+
+        let (_, settings) = app.page("/apps/1/settings").await;
+        assert_eq!(
+            Some("BUILD_CONFIG.FLAVOR:staging = staging".to_owned()),
+            settings.field("environment_rules")
+        );
+    }
+
+    #[tokio::test]
+    async fn inbound_filters() {
+        let app = TestApp::new("inbound-filters").await;
+
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            app.save_settings("tag_keys=&filter_rules=rooted").await
+        );
+        assert_eq!(
+            StatusCode::SEE_OTHER,
+            app.save_settings("tag_keys=&filter_rules=emulator%0D%0Aversion_code%3A..14")
+                .await
+        );
+
+        // Dropped reports are still accepted, so they aren't sent again.
+        app.ingest(|report| {
+            report["REPORT_ID"] = json!("old-version");
+            report["APP_VERSION_CODE"] = json!(13);
+        })
+        .await;
+
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            app.page("/reports/old-version").await.0
+        );
+        assert_eq!(StatusCode::NOT_FOUND, app.page("/issues/1").await.0);
+
+        app.ingest(|_| {}).await;
+        assert_eq!(StatusCode::OK, app.page("/issues/1").await.0);
+
+        let (_, settings) = app.page("/apps/1/settings").await;
+        assert_eq!(
+            Some("emulator\nversion_code:..14".to_owned()),
+            settings.field("filter_rules")
+        );
+        assert_eq!(
+            vec![vec!["emulator", "0"], vec!["version_code:..14", "1"]],
+            settings.rows("tr:has(td code)")
+        );
+
+        // Saved filters apply to the next report right away.
+        assert_eq!(
+            StatusCode::SEE_OTHER,
+            app.save_settings("tag_keys=&filter_rules=emulator").await
+        );
+        app.ingest(|report| {
+            report["REPORT_ID"] = json!("now-accepted");
+            report["APP_VERSION_CODE"] = json!(13);
+        })
+        .await;
+        assert_eq!(StatusCode::OK, app.page("/reports/now-accepted").await.0);
+    }
+
+    #[tokio::test]
+    async fn crash_type_tabs() {
+        let app = TestApp::new("crash-type-tabs").await;
+        app.ingest(|_| {}).await;
+        app.ingest(|report| {
+            report["REPORT_ID"] = json!("silent");
+            report["IS_SILENT"] = json!(true);
+        })
+        .await;
+
+        let page = |query: &str| app.page(format!("/apps/1{query}"));
+        let issues = |page: &Page| page.texts("td a[href^='/issues/']");
+
+        // The same stack trace as a crash and a handled exception makes two separate issues.
+        for query in ["", "?type=non_fatal"] {
+            let (status, details) = page(query).await;
+            assert_eq!(StatusCode::OK, status);
+            let issues = issues(&details);
+            assert_eq!(1, issues.len(), "{issues:?}");
+            assert!(issues[0].starts_with("java.lang.NullPointerException"));
+        }
+
+        let (_, details) = page("?type=anr").await;
+        assert!(issues(&details).is_empty());
+        assert_eq!(vec!["ANR"], details.texts(".tabs li.is-active"));
+        assert_eq!(StatusCode::BAD_REQUEST, page("?type=other").await.0);
+
+        let (_, report) = app.page("/reports/silent").await;
+        assert_eq!(Some(vec!["Non-fatal".to_owned()]), report.row("Type"));
+    }
+
+    #[tokio::test]
+    async fn native_symbols() {
+        const LIBNOTES: &[u8] = include_bytes!("../fixtures/symbols/libnotes.so");
+
+        let app = TestApp::new("native-symbols").await;
+        let id = symbols::build_id(LIBNOTES).unwrap().unwrap();
+
+        let upload = |build_id: &str, auth: &'static str| {
+            app.send(
+                Request::put(format!("/symbols/{build_id}"))
+                    .header(AUTHORIZATION, auth)
+                    .body(Body::from(LIBNOTES))
+                    .unwrap(),
+            )
+        };
+        assert_eq!(StatusCode::UNAUTHORIZED, upload(&id, WRONG_AUTH).await.0);
+        let (status, body, _) = upload("6f1e2d", AUTH).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!(
+            json!({ "error": format!("The file has the build ID {id}") }),
+            serde_json::from_str::<Value>(&body).unwrap()
+        );
+        assert_eq!(StatusCode::NO_CONTENT, upload(&id, AUTH).await.0);
+
+        // Let the crash happen in the uploaded library, within the inlined function.
+        let dropbox = include_str!("../fixtures/dropbox/dropbox.json")
+            .replace("6f1e2d3c4b5a69788796a5b4c3d2e1f0a9b8c7d6", &id)
+            .replace("000000000004f1a0", "0000000000001127");
+        app.ingest(|report| report["DROPBOX"] = serde_json::from_str(&dropbox).unwrap())
+            .await;
+
+        let page = |query: &str| app.page(format!("/reports/{PIXEL_ID}{query}"));
+        let functions = |page: &Page| page.texts("td div code");
+
+        let (_, report) = page("").await;
+        assert!(functions(&report).is_empty());
+
+        let (status, report) = page("?retraced=true").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            [
+                "title_length (/src/libnotes.c:11)",
+                "render_note (/src/libnotes.c:19)"
+            ],
+            functions(&report)[..2]
+        );
+        assert_eq!(vec!["inlined"], report.texts("td div .tag"));
+    }
+
+    #[tokio::test]
+    async fn retrace_tool() {
+        let app = TestApp::new("retrace-tool").await;
+        assert_eq!(StatusCode::OK, app.report(Some(AUTH), PIXEL).await);
+
+        let test_app = repositories::app_repo(app.pool.clone())
+            .get_by_username("test".to_owned())
+            .await
+            .unwrap();
+        let version = repositories::version_repo(app.pool.clone())
+            .list_by_app(test_app.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|version| version.code == 14)
+            .unwrap();
+
+        let api = |request: Value| {
+            app.send(
+                Request::post("/api/retrace")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(request.to_string()))
+                    .unwrap(),
+            )
+        };
+
+        let (status, body, _) = api(json!({ "stack_trace": OBFUSCATED })).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!(
+            r#"{"error":"Pick a version or upload a mapping file"}"#,
+            body
+        );
+
+        let (status, body, _) =
+            api(json!({ "stack_trace": OBFUSCATED, "version_id": version.id })).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        let error = serde_json::from_str::<Value>(&body).unwrap();
+        assert!(
+            error["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("No mapping file available"),
+            "{body}"
+        );
+
+        let (status, body, _) = api(json!({ "stack_trace": OBFUSCATED, "mapping": MAPPING })).await;
+        assert_eq!(StatusCode::OK, status);
+        let response = serde_json::from_str::<Value>(&body).unwrap();
+        assert!(
+            response["stack_trace"]
+                .as_str()
+                .unwrap()
+                .contains("com.example.Foo cannot be cast"),
+            "{body}"
+        );
+        assert_eq!("a.b.c.a(SourceFile:1)", response["frames"][0]["original"]);
+        assert!(
+            response["frames"][0]["remapped"][0]
+                .as_str()
+                .unwrap()
+                .starts_with("com.example.Foo."),
+            "{body}"
+        );
+        assert_eq!(Value::Null, response["issue"]);
+
+        // Traces of known issues link to them.
+        assert_eq!(
+            StatusCode::NO_CONTENT,
+            app.upload_mapping(14, MAPPING).await
+        );
+        let trace = pixel(|_| {})["STACK_TRACE"].clone();
+        let (status, body, _) =
+            api(json!({ "stack_trace": trace, "version_id": version.id })).await;
+        assert_eq!(StatusCode::OK, status);
+        let issue = serde_json::from_str::<Value>(&body).unwrap()["issue"].clone();
+        assert!(
+            issue["title"]
+                .as_str()
+                .unwrap()
+                .starts_with("java.lang.NullPointerException"),
+            "{body}"
+        );
+
+        let (status, page) = app.page(issue["url"].as_str().unwrap()).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec![PIXEL_ID], page.report_links());
+    }
+
+    #[tokio::test]
+    async fn retrace_form() {
+        let app = TestApp::new("retrace-form").await;
+        app.ingest(|_| {}).await;
+        let version = repositories::version_repo(app.pool.clone())
+            .list()
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(
+            StatusCode::NO_CONTENT,
+            app.upload_mapping(version.code, MAPPING).await
+        );
+
+        let form = |stack_trace: &str| {
+            let body = format!(
+                "--X\r\nContent-Disposition: form-data; \
+                 name=\"stack_trace\"\r\n\r\n{stack_trace}\r\n--X\r\nContent-Disposition: \
+                 form-data; name=\"version_id\"\r\n\r\n{}\r\n--X\r\nContent-Disposition: \
+                 form-data; name=\"mapping\"; filename=\"\"\r\n\r\n\r\n--X--\r\n",
+                version.id
+            );
+            app.send(
+                Request::post("/tools/retrace")
+                    .header(CONTENT_TYPE, "multipart/form-data; boundary=X")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        };
+
+        let (status, body, _) = form(OBFUSCATED).await;
+        assert_eq!(StatusCode::OK, status);
+        let page = Page::parse(&body);
+        assert!(page.texts("pre")[0].contains("com.example.Foo cannot be cast"));
+        assert_eq!(
+            vec![version.id.to_string()],
+            page.attrs("option[selected]", "value")
+        );
+
+        let (status, body, _) = form("").await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            vec!["The stack trace is empty"],
+            Page::parse(&body).texts(".message.is-danger .message-body")
+        );
+
+        let (status, ..) = form(&"x".repeat(2 * 1024 * 1024)).await;
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+    }
+
+    #[tokio::test]
+    async fn notification_settings() {
+        let app = TestApp::new("notification-settings").await;
+
+        let save = |form: &'static str| {
+            app.send(
+                Request::post("/users/1/notifications")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from(form))
+                    .unwrap(),
+            )
+        };
+        let alert_apps = async || {
+            let (status, page) = app.page("/users/1/notifications").await;
+            assert_eq!(StatusCode::OK, status);
+            page.attrs("input[name=app][checked]", "value")
+        };
+
+        assert!(alert_apps().await.is_empty());
+
+        let (status, ..) = save("email=admin%40example.com&immediate=on&app=1").await;
+        assert_eq!(StatusCode::SEE_OTHER, status);
+        assert_eq!(vec!["1"], alert_apps().await);
+
+        assert_eq!(
+            StatusCode::SEE_OTHER,
+            save("email=admin%40example.com").await.0
+        );
+        assert!(alert_apps().await.is_empty());
+    }
+}
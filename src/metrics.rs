@@ -0,0 +1,297 @@
+//! Prometheus metrics about the report ingestion and general health of the server.
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use axum_extra::TypedHeader;
+use headers::{Authorization, authorization::Bearer};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TEXT_FORMAT, TextEncoder, core::Collector,
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+use tracing::{instrument, warn};
+
+use crate::{db::DbConnPool, settings, storage::Storage};
+
+// Unwrap: Metrics are only created from static definitions, so any failure is a programming error.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(|| Metrics::new().unwrap());
+
+/// How long the calculated size of the raw storage is reused, as calculating it lists all stored
+/// reports.
+const RAW_STORAGE_TTL: Duration = Duration::from_mins(5);
+
+pub struct Metrics {
+    registry: Registry,
+    /// Reports received, labeled by app.
+    pub reports_received: IntCounterVec,
+    /// Reports successfully stored, labeled by app.
+    pub reports_accepted: IntCounterVec,
+    /// Reports that were refused, labeled by app and reason.
+    pub reports_rejected: IntCounterVec,
+    pub ingestion_duration: Histogram,
+    pub retrace_duration: Histogram,
+    pub retrace_failures: IntCounter,
+    db_pool_connections: IntGauge,
+    db_pool_idle_connections: IntGauge,
+    db_pool_max_connections: IntGauge,
+    raw_storage_bytes: IntGauge,
+    /// Work that is left after a report was accepted, like retracing and alerts. Reports aren't
+    /// spooled before they're stored, so this is the only queue that can pile up.
+    background_tasks: IntGauge,
+    /// Last calculated size of the raw storage, and when it was calculated.
+    raw_storage_size: Mutex<Option<(Instant, u64)>>,
+}
+
+impl Metrics {
+    fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some(env!("CARGO_CRATE_NAME").to_owned()), None)?;
+
+        let metrics = Self {
+            reports_received: IntCounterVec::new(
+                Opts::new("reports_received_total", "Amount of received reports"),
+                &["app"],
+            )?,
+            reports_accepted: IntCounterVec::new(
+                Opts::new(
+                    "reports_accepted_total",
+                    "Amount of successfully stored reports",
+                ),
+                &["app"],
+            )?,
+            reports_rejected: IntCounterVec::new(
+                Opts::new("reports_rejected_total", "Amount of refused reports"),
+                &["app", "reason"],
+            )?,
+            ingestion_duration: Histogram::with_opts(HistogramOpts::new(
+                "ingestion_duration_seconds",
+                "Time it took to process a single incoming report",
+            ))?,
+            retrace_duration: Histogram::with_opts(HistogramOpts::new(
+                "retrace_duration_seconds",
+                "Time it took to retrace a single stack trace",
+            ))?,
+            retrace_failures: IntCounter::new(
+                "retrace_failures_total",
+                "Amount of stack traces that couldn't be retraced",
+            )?,
+            db_pool_connections: IntGauge::new(
+                "db_pool_connections",
+                "Current amount of database connections",
+            )?,
+            db_pool_idle_connections: IntGauge::new(
+                "db_pool_idle_connections",
+                "Current amount of idle database connections",
+            )?,
+            db_pool_max_connections: IntGauge::new(
+                "db_pool_max_connections",
+                "Maximum amount of database connections",
+            )?,
+            raw_storage_bytes: IntGauge::new(
+                "raw_storage_bytes",
+                "Total size of all stored raw reports",
+            )?,
+            background_tasks: IntGauge::new(
+                "background_tasks",
+                "Current amount of unfinished background tasks of accepted reports",
+            )?,
+            raw_storage_size: Mutex::new(None),
+            registry,
+        };
+
+        let collectors: [Box<dyn Collector>; 11] = [
+            Box::new(metrics.reports_received.clone()),
+            Box::new(metrics.reports_accepted.clone()),
+            Box::new(metrics.reports_rejected.clone()),
+            Box::new(metrics.ingestion_duration.clone()),
+            Box::new(metrics.retrace_duration.clone()),
+            Box::new(metrics.retrace_failures.clone()),
+            Box::new(metrics.db_pool_connections.clone()),
+            Box::new(metrics.db_pool_idle_connections.clone()),
+            Box::new(metrics.db_pool_max_connections.clone()),
+            Box::new(metrics.raw_storage_bytes.clone()),
+            Box::new(metrics.background_tasks.clone()),
+        ];
+
+        for collector in collectors {
+            metrics.registry.register(collector)?;
+        }
+
+        Ok(metrics)
+    }
+
+    /// Increment the counter for refused reports of the given app.
+    pub fn reject(&self, app: &str, reason: &str) {
+        self.reports_rejected
+            .with_label_values(&[app, reason])
+            .inc();
+    }
+
+    /// Count a background task of an accepted report, until the returned guard is dropped.
+    pub fn background_task(&self) -> BackgroundTask {
+        self.background_tasks.inc();
+        BackgroundTask(self.background_tasks.clone())
+    }
+
+    /// Refresh all metrics that are only sampled on request, and encode the current state of all
+    /// metrics in the Prometheus text format.
+    async fn render(&self, pool: &DbConnPool, storage: &Storage) -> Result<Vec<u8>> {
+        let state = pool.state();
+        self.db_pool_connections.set(i64::from(state.connections));
+        self.db_pool_idle_connections
+            .set(i64::from(state.idle_connections));
+        self.db_pool_max_connections.set(i64::from(pool.max_size()));
+
+        match self.raw_storage_size(storage).await {
+            Ok(size) => self
+                .raw_storage_bytes
+                .set(i64::try_from(size).unwrap_or(i64::MAX)),
+            Err(e) => warn!("failed calculating raw storage size: {e:?}"),
+        }
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+
+        Ok(buf)
+    }
+
+    async fn raw_storage_size(&self, storage: &Storage) -> Result<u64> {
+        // Held while calculating, so concurrent scrapes don't list all reports at once.
+        let mut cached = self.raw_storage_size.lock().await;
+        if let Some((at, size)) = *cached
+            && at.elapsed() < RAW_STORAGE_TTL
+        {
+            return Ok(size);
+        }
+
+        let size = storage
+            .list_reports()
+            .await?
+            .iter()
+            .map(|stored| stored.object.size)
+            .sum();
+        *cached = Some((Instant::now(), size));
+
+        Ok(size)
+    }
+}
+
+/// A running background task, see [`Metrics::background_task`].
+pub struct BackgroundTask(IntGauge);
+
+impl Drop for BackgroundTask {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn handler(
+    State(settings): State<Arc<settings::Metrics>>,
+    State(pool): State<DbConnPool>,
+    State(storage): State<Storage>,
+    auth: Option<TypedHeader<Authorization<Bearer>>>,
+) -> Response {
+    if let Some(token) = &settings.token {
+        match auth {
+            // Compare in constant time, to not leak how much of the token was guessed right.
+            Some(TypedHeader(Authorization(bearer)))
+                if bool::from(bearer.token().as_bytes().ct_eq(token.as_bytes())) => {}
+            _ => return StatusCode::UNAUTHORIZED.into_response(),
+        }
+    }
+
+    match METRICS.render(&pool, &storage).await {
+        Ok(buf) => ([(CONTENT_TYPE, TEXT_FORMAT)], buf).into_response(),
+        Err(e) => {
+            warn!("failed rendering metrics: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{body, extract::State, http::StatusCode, response::Response};
+    use axum_extra::TypedHeader;
+    use headers::Authorization;
+    use serde_json::json;
+
+    use super::{METRICS, handler};
+    use crate::{db::DbConnPool, fixtures, settings, storage::Storage};
+
+    async fn scrape(pool: &DbConnPool, storage: &Storage, token: Option<&str>) -> Response {
+        let settings = settings::Metrics {
+            token: Some("secret".to_owned()),
+        };
+        let auth = token.map(|token| TypedHeader(Authorization::bearer(token).unwrap()));
+
+        handler(
+            State(Arc::new(settings)),
+            State(pool.clone()),
+            State(storage.clone()),
+            auth,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn bearer_token() {
+        let dir = std::env::temp_dir().join(format!("acralite-metrics-{}", std::process::id()));
+        let storage = Storage::new(&settings::Storage {
+            dir: Some(dir.clone()),
+            ..settings::Storage::default()
+        })
+        .unwrap();
+        storage
+            .save_report(1, "a1", &json!({ "REPORT_ID": "a1" }))
+            .await
+            .unwrap();
+        let pool = fixtures::memory_pool();
+
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            scrape(&pool, &storage, None).await.status()
+        );
+        assert_eq!(
+            StatusCode::UNAUTHORIZED,
+            scrape(&pool, &storage, Some("wrong")).await.status()
+        );
+
+        METRICS.reject("notes", "invalid");
+        let _task = METRICS.background_task();
+        let response = scrape(&pool, &storage, Some("secret")).await;
+        assert_eq!(StatusCode::OK, response.status());
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        for name in [
+            "acralite_reports_rejected_total{app=\"notes\",reason=\"invalid\"}",
+            "acralite_ingestion_duration_seconds_bucket",
+            "acralite_retrace_duration_seconds_bucket",
+            "acralite_retrace_failures_total",
+            "acralite_db_pool_connections",
+            "acralite_db_pool_idle_connections",
+            "acralite_db_pool_max_connections 1",
+            "acralite_raw_storage_bytes 18",
+            "acralite_background_tasks",
+        ] {
+            assert!(body.contains(name), "{name} is missing in {body}");
+        }
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
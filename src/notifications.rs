@@ -0,0 +1,450 @@
+//! Email notifications about new issues, and a daily digest of the overall crash situation.
+//!
+//! Alerts about new issues go to the users that picked the issue's app. The digest is an overview
+//! of all apps, and goes to every user that asked for it.
+//!
+//! Both only cover the `production` environment, as crashes of developers' own builds are seen
+//! by them anyway, and would drown out the ones of users.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use askama::Template;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+};
+use time::{OffsetDateTime, Time};
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    db::{DbConnPool, repositories},
+    environments,
+    settings::{Smtp, SmtpEncryption},
+    templates::mail,
+};
+
+/// Time frame that is covered by a single digest, and at the same time the interval in which
+/// digests are sent. Each digest goes out at the configured hour of the day.
+const DIGEST_PERIOD: Duration = Duration::from_hours(24);
+/// Maximum amount of issues listed in the top issues section of a digest.
+const DIGEST_TOP_ISSUES: u32 = 10;
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    base_url: String,
+    /// Hour of the day in UTC at which digests are sent.
+    digest_hour: u8,
+}
+
+impl Mailer {
+    pub fn new(settings: &Smtp) -> Result<Self> {
+        let mut builder = match settings.encryption {
+            SmtpEncryption::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpEncryption::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpEncryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+        };
+
+        if let Some(port) = settings.port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: settings.from.parse().context("invalid sender address")?,
+            base_url: settings.base_url.trim_end_matches('/').to_owned(),
+            digest_hour: settings.digest_hour,
+        })
+    }
+
+    async fn send(&self, to: &str, subject: String, text: String, html: String) -> Result<()> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().context("invalid recipient address")?)
+            .subject(subject)
+            .multipart(MultiPart::alternative_plain_html(text, html))?;
+
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Inform all users that opted in for immediate alerts about the app of a newly discovered issue.
+#[instrument(skip_all, fields(issue_id = issue_id))]
+pub async fn notify_new_issue(pool: DbConnPool, mailer: Arc<Mailer>, issue_id: i64) -> Result<()> {
+    let issue = repositories::issue_repo(pool.clone()).get(issue_id).await?;
+    let recipients = repositories::notification_repo(pool.clone())
+        .list_immediate(issue.app_id)
+        .await?;
+
+    if recipients.is_empty() {
+        return Ok(());
+    }
+
+    let app = repositories::app_repo(pool).get(issue.app_id).await?;
+
+    let content = mail::NewIssue {
+        base_url: mailer.base_url.clone(),
+        app_name: app.name,
+        issue,
+    };
+    let subject = format!("[{}] New issue: {}", content.app_name, content.issue.title);
+    let text = mail::NewIssueText { mail: &content }.render()?;
+    let html = mail::NewIssueHtml { mail: &content }.render()?;
+
+    for recipient in recipients {
+        if let Err(e) = mailer
+            .send(
+                &recipient.email,
+                subject.clone(),
+                text.clone(),
+                html.clone(),
+            )
+            .await
+        {
+            warn!(
+                user_id = recipient.user_id,
+                "failed sending new issue alert: {e:?}"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Start a background task that sends out digests to all users that opted in, every day at the
+/// configured hour.
+pub fn spawn_digest(pool: DbConnPool, mailer: Arc<Mailer>) {
+    tokio::spawn(async move {
+        loop {
+            let delay = until_next_digest(OffsetDateTime::now_utc(), mailer.digest_hour);
+            tokio::time::sleep(delay).await;
+
+            if let Err(e) = send_digest(pool.clone(), &mailer).await {
+                error!("failed sending digest: {e:?}");
+            }
+        }
+    });
+}
+
+/// Time left until the given hour of the day is reached next, which is always in the future.
+fn until_next_digest(now: OffsetDateTime, hour: u8) -> Duration {
+    let at = Time::from_hms(hour % 24, 0, 0).unwrap_or(Time::MIDNIGHT);
+    let mut next = now.replace_time(at);
+    if next <= now {
+        next += DIGEST_PERIOD;
+    }
+
+    (next - now).try_into().unwrap_or(DIGEST_PERIOD)
+}
+
+#[instrument(skip_all)]
+async fn send_digest(pool: DbConnPool, mailer: &Mailer) -> Result<()> {
+    let recipients = repositories::notification_repo(pool.clone())
+        .list_digest()
+        .await?;
+
+    if recipients.is_empty() {
+        return Ok(());
+    }
+
+    let issue_repo = repositories::issue_repo(pool.clone());
+    let version_repo = repositories::version_repo(pool);
+
+    let environment = || Some(environments::PRODUCTION.to_owned());
+
+    let content = mail::Digest {
+        base_url: mailer.base_url.clone(),
+        new_issues: issue_repo.list_new(DIGEST_PERIOD, environment()).await?,
+        top_issues: issue_repo
+            .list_top(DIGEST_PERIOD, environment(), DIGEST_TOP_ISSUES)
+            .await?,
+        rising_versions: version_repo
+            .list_rising(DIGEST_PERIOD, environment())
+            .await?,
+    };
+
+    if content.is_empty() {
+        info!("nothing happened since the last digest, skipping it");
+        return Ok(());
+    }
+
+    let subject = format!("Daily digest: {} new issues", content.new_issues.len());
+    let text = mail::DigestText { mail: &content }.render()?;
+    let html = mail::DigestHtml { mail: &content }.render()?;
+
+    for recipient in recipients {
+        if let Err(e) = mailer
+            .send(
+                &recipient.email,
+                subject.clone(),
+                text.clone(),
+                html.clone(),
+            )
+            .await
+        {
+            warn!(user_id = recipient.user_id, "failed sending digest: {e:?}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use time::macros::datetime;
+
+    use super::{Mailer, notify_new_issue, send_digest, until_next_digest};
+    use crate::{
+        db::{
+            DbConnPool,
+            models::{CrashType, NewIssue, NewReport, NewUser, NewVersion, NotificationSettings},
+            repositories,
+        },
+        fixtures,
+        settings::{Smtp, SmtpEncryption},
+    };
+
+    /// A mail as received by the [`Sink`].
+    #[derive(Debug)]
+    struct Mail {
+        recipients: Vec<String>,
+        data: String,
+    }
+
+    impl Mail {
+        fn subject(&self) -> Option<&str> {
+            self.data
+                .lines()
+                .find_map(|line| line.strip_prefix("Subject: "))
+        }
+    }
+
+    /// A local SMTP server that accepts all mails and keeps them for inspection.
+    struct Sink {
+        port: u16,
+        mails: Arc<Mutex<Vec<Mail>>>,
+    }
+
+    impl Sink {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let mails = Arc::new(Mutex::new(Vec::new()));
+
+            let received = Arc::clone(&mails);
+            std::thread::spawn(move || {
+                for stream in listener.incoming().map_while(Result::ok) {
+                    let received = Arc::clone(&received);
+                    std::thread::spawn(move || Self::serve(stream, &received));
+                }
+            });
+
+            Self { port, mails }
+        }
+
+        /// Speak just enough SMTP for a client that sends plain mails.
+        fn serve(mut stream: TcpStream, mails: &Mutex<Vec<Mail>>) -> std::io::Result<()> {
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut recipients = Vec::new();
+            stream.write_all(b"220 localhost ESMTP sink\r\n")?;
+
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 {
+                let command = line.trim_end().to_ascii_uppercase();
+                if let Some(to) = command.strip_prefix("RCPT TO:") {
+                    recipients.push(to.trim_matches(['<', '>', ' ']).to_ascii_lowercase());
+                    stream.write_all(b"250 OK\r\n")?;
+                } else if command == "DATA" {
+                    stream.write_all(b"354 Go ahead\r\n")?;
+                    let mut data = String::new();
+                    loop {
+                        line.clear();
+                        reader.read_line(&mut line)?;
+                        if line.trim_end() == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    mails.lock().unwrap().push(Mail {
+                        recipients: std::mem::take(&mut recipients),
+                        data,
+                    });
+                    stream.write_all(b"250 OK\r\n")?;
+                } else if command == "QUIT" {
+                    stream.write_all(b"221 Bye\r\n")?;
+                    break;
+                } else {
+                    stream.write_all(b"250 OK\r\n")?;
+                }
+                line.clear();
+            }
+
+            Ok(())
+        }
+
+        fn mailer(&self) -> Arc<Mailer> {
+            Arc::new(
+                Mailer::new(&Smtp {
+                    host: "127.0.0.1".to_owned(),
+                    port: Some(self.port),
+                    encryption: SmtpEncryption::None,
+                    username: None,
+                    password: None,
+                    from: "ACRAlite <acralite@example.com>".to_owned(),
+                    base_url: "http://localhost:8080/".to_owned(),
+                    digest_hour: 6,
+                })
+                .unwrap(),
+            )
+        }
+
+        fn take(&self) -> Vec<Mail> {
+            std::mem::take(&mut self.mails.lock().unwrap())
+        }
+    }
+
+    /// A database with one user for each kind of notification, and a single new issue in
+    /// production. Another one only happened in development.
+    async fn setup() -> (DbConnPool, i64) {
+        let pool = fixtures::memory_pool();
+        crate::db::run_migrations(&pool).unwrap();
+        fixtures::test_app(&pool).await;
+
+        let users = repositories::user_repo(pool.clone());
+        let notifications = repositories::notification_repo(pool.clone());
+        // Carol wants alerts, but not for any of the apps.
+        for (name, immediate, digest, apps) in [
+            ("alice", true, false, vec![1]),
+            ("bob", false, true, vec![1]),
+            ("carol", true, false, vec![]),
+        ] {
+            let user_id = users
+                .save(NewUser {
+                    username: name.to_owned(),
+                    password: "secret".to_owned(),
+                })
+                .await
+                .unwrap();
+            notifications
+                .save(
+                    NotificationSettings {
+                        user_id,
+                        email: format!("{name}@example.com"),
+                        immediate,
+                        digest,
+                    },
+                    apps,
+                )
+                .await
+                .unwrap();
+        }
+
+        let version_id = repositories::version_repo(pool.clone())
+            .save(NewVersion {
+                app_id: 1,
+                name: "1.0.0".to_owned(),
+                code: 1,
+            })
+            .await
+            .unwrap();
+        let mut issue_ids = Vec::new();
+        for (title, environment) in [
+            ("NullPointerException in onCreate", "production"),
+            ("AssertionError in onResume", "development"),
+        ] {
+            let (issue_id, _) = repositories::issue_repo(pool.clone())
+                .get_or_create(NewIssue {
+                    app_id: 1,
+                    fingerprint: format!("{environment:0>16}"),
+                    title: title.to_owned(),
+                    crash_type: CrashType::Fatal,
+                })
+                .await
+                .unwrap();
+            repositories::report_repo(pool.clone())
+                .save(NewReport {
+                    version_id,
+                    issue_id,
+                    report_id: format!("{environment}-report"),
+                    crash_date: "2025-02-20T12:00:00.000+01:00".to_owned(),
+                    crash_type: CrashType::Fatal,
+                    config_changes: Vec::new(),
+                    low_memory: false,
+                    environment: environment.to_owned(),
+                })
+                .await
+                .unwrap();
+            issue_ids.push(issue_id);
+        }
+
+        (pool, issue_ids[0])
+    }
+
+    #[tokio::test]
+    async fn new_issue_and_digest() {
+        let sink = Sink::start();
+        let mailer = sink.mailer();
+        let (pool, issue_id) = setup().await;
+
+        notify_new_issue(pool.clone(), Arc::clone(&mailer), issue_id)
+            .await
+            .unwrap();
+        let mails = sink.take();
+        assert_eq!(1, mails.len(), "{mails:?}");
+        assert_eq!(vec!["alice@example.com"], mails[0].recipients);
+        assert_eq!(
+            Some("[Test] New issue: NullPointerException in onCreate"),
+            mails[0].subject()
+        );
+        assert!(
+            mails[0].data.contains("http://localhost:8080/apps/1"),
+            "{mails:?}"
+        );
+
+        send_digest(pool, &mailer).await.unwrap();
+        let mails = sink.take();
+        assert_eq!(1, mails.len(), "{mails:?}");
+        assert_eq!(vec!["bob@example.com"], mails[0].recipients);
+        assert_eq!(Some("Daily digest: 1 new issues"), mails[0].subject());
+    }
+
+    #[test]
+    fn digest_schedule() {
+        let hours = |hours| Duration::from_hours(hours);
+
+        assert_eq!(
+            hours(2),
+            until_next_digest(datetime!(2025-02-20 04:00 UTC), 6)
+        );
+        assert_eq!(
+            hours(24),
+            until_next_digest(datetime!(2025-02-20 06:00 UTC), 6)
+        );
+        assert_eq!(
+            hours(23) + Duration::from_mins(30),
+            until_next_digest(datetime!(2025-02-20 06:30 UTC), 6)
+        );
+        assert_eq!(
+            hours(1),
+            until_next_digest(datetime!(2025-02-20 23:00 UTC), 0)
+        );
+    }
+}
@@ -131,7 +131,7 @@ pub struct Configuration {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub struct ColorMode: u32 {
         const WIDE_COLOR_GAMUT_NO  = 1;
         const WIDE_COLOR_GAMUT_YES = 2;
@@ -242,7 +242,7 @@ pub enum Orientation {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub struct ScreenLayout: i32 {
         const SIZE_SMALL  = 1;
         const SIZE_NORMAL = 2;
@@ -309,7 +309,7 @@ pub enum Touchscreen {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub struct UiMode: i32 {
         const TYPE_NORMAL     = 1;
         const TYPE_DESK       = 2;
@@ -349,7 +349,7 @@ impl<'de> Deserialize<'de> for UiMode {
                         "UI_MODE_TYPE_CAR" => UiMode::TYPE_CAR,
                         "UI_MODE_TYPE_TELEVISION" => UiMode::TYPE_TELEVISION,
                         "UI_MODE_TYPE_APPLIANCE" => UiMode::TYPE_APPLIANCE,
-                        "UI_MODE_TYPE_WATCHL" => UiMode::TYPE_WATCH,
+                        "UI_MODE_TYPE_WATCH" => UiMode::TYPE_WATCH,
                         "UI_MODE_TYPE_VR_HEADSET" => UiMode::TYPE_VR_HEADSET,
                         "UI_MODE_NIGHT_NO" => UiMode::NIGHT_NO,
                         "UI_MODE_NIGHT_YES" => UiMode::NIGHT_YES,
@@ -390,7 +390,7 @@ pub struct SizeRange {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub struct DisplayFlags: u32 {
         const SUPPORTS_PROTECTED_BUFFERS = 1;
         const SECURE                     = 2;
@@ -449,7 +449,7 @@ pub struct DisplayMetrics {
     pub ydpi: f32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Rotation {
     Zero,
     Ninety,
@@ -512,7 +512,7 @@ impl<'de> Deserialize<'de> for Rotation {
     }
 }
 
-#[derive(Debug, Deserialize_repr)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize_repr)]
 #[repr(i32)]
 pub enum PixelFormat {
     Translucent = -3,
@@ -560,3 +560,314 @@ pub struct ThreadDetails {
     pub name: String,
     pub priority: i8,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, fmt::Debug, fs};
+
+    use bitflags::Flags;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use serde::de::DeserializeOwned;
+    use serde_json::{Value, json};
+
+    use super::{ColorMode, DisplayFlags, PixelFormat, Report, Rotation, ScreenLayout, UiMode};
+
+    /// Anonymized reports of real devices, named `api<level>-acra<version>-<description>.json`.
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reports");
+
+    fn fixtures() -> Vec<(String, Value)> {
+        let mut fixtures = fs::read_dir(FIXTURES)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                let value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+                (name, value)
+            })
+            .collect::<Vec<_>>();
+        fixtures.sort_by(|a, b| a.0.cmp(&b.0));
+        fixtures
+    }
+
+    fn parse<T: DeserializeOwned>(value: Value) -> serde_json::Result<T> {
+        serde_json::from_value(value)
+    }
+
+    /// Each single token must result in its flag, and all tokens joined with `+` in the union of
+    /// all flags.
+    fn round_trip<T>(tokens: &[(&str, T)])
+    where
+        T: Flags + DeserializeOwned + Debug + PartialEq + Copy,
+    {
+        for &(token, flag) in tokens {
+            assert_eq!(flag, parse::<T>(json!(token)).unwrap(), "{token}");
+        }
+
+        let all = tokens.iter().map(|(token, _)| *token).collect::<Vec<_>>();
+        let union = tokens
+            .iter()
+            .fold(T::empty(), |union, &(_, flag)| union.union(flag));
+        assert_eq!(union, parse::<T>(json!(all.join("+"))).unwrap());
+
+        assert_eq!(T::empty(), parse::<T>(json!("")).unwrap());
+        assert_eq!(T::empty(), parse::<T>(json!("UNKNOWN+")).unwrap());
+        assert!(parse::<T>(json!(1)).is_err());
+        assert!(parse::<T>(json!(null)).is_err());
+    }
+
+    #[test]
+    fn fixtures_cover_api_levels() {
+        let mut levels = BTreeSet::new();
+
+        for (name, raw) in fixtures() {
+            let report =
+                parse::<Report>(raw).unwrap_or_else(|e| panic!("failed parsing {name}: {e}"));
+            let level = report.build.version.sdk_int;
+
+            assert!(name.starts_with(&format!("api{level}-")), "{name}");
+            assert!(!report.display.is_empty(), "{name}");
+            levels.insert(level);
+        }
+
+        assert_eq!((21..=35).collect::<BTreeSet<_>>(), levels);
+    }
+
+    #[test]
+    fn color_mode() {
+        assert_eq!(
+            ColorMode::WIDE_COLOR_GAMUT_NO | ColorMode::HDR_NO,
+            parse::<ColorMode>(json!(5)).unwrap()
+        );
+        assert_eq!(
+            ColorMode::WIDE_COLOR_GAMUT_YES | ColorMode::HDR_YES,
+            parse::<ColorMode>(json!(10)).unwrap()
+        );
+        // Undefined bits are silently dropped.
+        assert_eq!(ColorMode::empty(), parse::<ColorMode>(json!(0)).unwrap());
+        assert_eq!(ColorMode::HDR_NO, parse::<ColorMode>(json!(0x104)).unwrap());
+
+        assert!(parse::<ColorMode>(json!(-1)).is_err());
+        assert!(parse::<ColorMode>(json!(u64::MAX)).is_err());
+        assert!(parse::<ColorMode>(json!("5")).is_err());
+    }
+
+    #[test]
+    fn screen_layout() {
+        round_trip(&[
+            ("SCREENLAYOUT_SIZE_SMALL", ScreenLayout::SIZE_SMALL),
+            ("SCREENLAYOUT_SIZE_NORMAL", ScreenLayout::SIZE_NORMAL),
+            ("SCREENLAYOUT_SIZE_LARGE", ScreenLayout::SIZE_LARGE),
+            ("SCREENLAYOUT_SIZE_XLARGE", ScreenLayout::SIZE_XLARGE),
+            ("SCREENLAYOUT_LONG_NO", ScreenLayout::LONG_NO),
+            ("SCREENLAYOUT_LONG_YES", ScreenLayout::LONG_YES),
+            ("SCREENLAYOUT_LAYOUTDIR_LTR", ScreenLayout::LAYOUTDIR_LTR),
+            ("SCREENLAYOUT_LAYOUTDIR_RTL", ScreenLayout::LAYOUTDIR_RTL),
+            ("SCREENLAYOUT_ROUND_NO", ScreenLayout::ROUND_NO),
+            ("SCREENLAYOUT_ROUND_YES", ScreenLayout::ROUND_YES),
+        ]);
+    }
+
+    #[test]
+    fn ui_mode() {
+        round_trip(&[
+            ("UI_MODE_TYPE_NORMAL", UiMode::TYPE_NORMAL),
+            ("UI_MODE_TYPE_DESK", UiMode::TYPE_DESK),
+            ("UI_MODE_TYPE_CAR", UiMode::TYPE_CAR),
+            ("UI_MODE_TYPE_TELEVISION", UiMode::TYPE_TELEVISION),
+            ("UI_MODE_TYPE_APPLIANCE", UiMode::TYPE_APPLIANCE),
+            ("UI_MODE_TYPE_WATCH", UiMode::TYPE_WATCH),
+            ("UI_MODE_TYPE_VR_HEADSET", UiMode::TYPE_VR_HEADSET),
+            ("UI_MODE_NIGHT_NO", UiMode::NIGHT_NO),
+            ("UI_MODE_NIGHT_YES", UiMode::NIGHT_YES),
+        ]);
+        assert_eq!(
+            UiMode::TYPE_WATCH | UiMode::NIGHT_YES,
+            parse::<UiMode>(json!("UI_MODE_TYPE_WATCH+UI_MODE_NIGHT_YES")).unwrap()
+        );
+    }
+
+    #[test]
+    fn display_flags() {
+        round_trip(&[
+            (
+                "FLAG_SUPPORTS_PROTECTED_BUFFERS",
+                DisplayFlags::SUPPORTS_PROTECTED_BUFFERS,
+            ),
+            ("FLAG_SECURE", DisplayFlags::SECURE),
+            ("FLAG_PRIVATE", DisplayFlags::PRIVATE),
+            ("FLAG_PRESENTATION", DisplayFlags::PRESENTATION),
+            ("FLAG_ROUND", DisplayFlags::ROUND),
+        ]);
+    }
+
+    #[test]
+    fn rotation() {
+        let rotations = [
+            Rotation::Zero,
+            Rotation::Ninety,
+            Rotation::OneHundredEighty,
+            Rotation::TwoHundredSeventy,
+        ];
+
+        for (i, (name, rotation)) in ["ROTATION_0", "ROTATION_90", "ROTATION_180", "ROTATION_270"]
+            .into_iter()
+            .zip(rotations)
+            .enumerate()
+        {
+            assert_eq!(rotation, parse::<Rotation>(json!(i)).unwrap());
+            assert_eq!(rotation, parse::<Rotation>(json!(name)).unwrap());
+        }
+
+        for invalid in [
+            json!(4),
+            json!(-1),
+            json!("ROTATION_45"),
+            json!(0.5),
+            json!(null),
+        ] {
+            assert!(parse::<Rotation>(invalid.clone()).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn pixel_format() {
+        for (value, format) in [
+            (-3, PixelFormat::Translucent),
+            (-2, PixelFormat::Transparent),
+            (-1, PixelFormat::Opaque),
+            (0, PixelFormat::Unknown),
+            (1, PixelFormat::Rgba8888),
+            (2, PixelFormat::Rgbx8888),
+            (3, PixelFormat::Rgb888),
+            (4, PixelFormat::Rgb565),
+            (6, PixelFormat::Rgba5551),
+            (7, PixelFormat::Rgba4444),
+            (8, PixelFormat::A8),
+            (9, PixelFormat::L8),
+            (10, PixelFormat::La88),
+            (11, PixelFormat::Rgb332),
+            (16, PixelFormat::YCbCr422Sp),
+            (17, PixelFormat::YCbCr420Sp),
+            (20, PixelFormat::YCbCr442l),
+            (22, PixelFormat::RgbaF16),
+            (43, PixelFormat::Rgba1010102),
+            (256, PixelFormat::Jpeg),
+        ] {
+            assert_eq!(format, parse::<PixelFormat>(json!(value)).unwrap());
+        }
+
+        for invalid in [json!(5), json!(i64::MAX), json!("1")] {
+            assert!(parse::<PixelFormat>(invalid.clone()).is_err(), "{invalid}");
+        }
+    }
+
+    /// Feed random values into every custom deserializer. Any outcome is fine, as long as it
+    /// doesn't panic.
+    #[test]
+    fn random_values() {
+        let mut rng = StdRng::seed_from_u64(38);
+
+        for _ in 0..5000 {
+            let value = random_value(&mut rng, 2);
+
+            parse::<ColorMode>(value.clone()).ok();
+            parse::<ScreenLayout>(value.clone()).ok();
+            parse::<UiMode>(value.clone()).ok();
+            parse::<DisplayFlags>(value.clone()).ok();
+            parse::<Rotation>(value.clone()).ok();
+            parse::<PixelFormat>(value).ok();
+        }
+    }
+
+    /// Damage the fixtures, either on the byte level or by swapping out single values, and make
+    /// sure parsing them fails with an error instead of a panic.
+    #[test]
+    fn mutated_reports() {
+        let mut rng = StdRng::seed_from_u64(38);
+
+        for (_, raw) in fixtures() {
+            let bytes = raw.to_string().into_bytes();
+
+            for _ in 0..100 {
+                let mut bytes = bytes.clone();
+                let pos = rng.random_range(0..bytes.len());
+
+                match rng.random_range(0..3) {
+                    0 => bytes.truncate(pos),
+                    1 => bytes[pos] = rng.random(),
+                    _ => bytes.insert(pos, rng.random()),
+                }
+
+                serde_json::from_slice::<Report>(&bytes).ok();
+            }
+
+            for _ in 0..100 {
+                let mut value = raw.clone();
+                mutate(&mut value, &mut rng);
+
+                parse::<Report>(value).ok();
+            }
+        }
+    }
+
+    /// Replace or remove a single value somewhere deep inside the given one.
+    fn mutate(value: &mut Value, rng: &mut StdRng) {
+        let child = match value {
+            Value::Object(map) if !map.is_empty() => {
+                let index = rng.random_range(0..map.len());
+                let key = map.keys().nth(index).cloned().unwrap();
+
+                if rng.random_bool(0.1) {
+                    map.remove(&key);
+                    return;
+                }
+
+                map.get_mut(&key)
+            }
+            Value::Array(list) if !list.is_empty() => {
+                let index = rng.random_range(0..list.len());
+                list.get_mut(index)
+            }
+            _ => None,
+        };
+
+        match child {
+            Some(child) if rng.random_bool(0.7) => mutate(child, rng),
+            Some(child) => *child = random_value(rng, 1),
+            None => *value = random_value(rng, 1),
+        }
+    }
+
+    fn random_value(rng: &mut StdRng, depth: u8) -> Value {
+        const TOKENS: &[&str] = &[
+            "UI_MODE_TYPE_WATCH",
+            "SCREENLAYOUT_SIZE_LARGE",
+            "FLAG_ROUND",
+            "ROTATION_90",
+            "+",
+            "",
+            "\u{0}",
+            "ü",
+        ];
+
+        match rng.random_range(0..if depth == 0 { 6 } else { 8 }) {
+            0 => Value::Null,
+            1 => Value::Bool(rng.random()),
+            2 => json!(rng.random::<i64>() >> rng.random_range(0..64)),
+            3 => json!(rng.random::<u64>() >> rng.random_range(0..64)),
+            4 => json!(rng.random::<f64>() * 1e6 - 5e5),
+            5 => {
+                let parts = (0..rng.random_range(0..4))
+                    .map(|_| TOKENS[rng.random_range(0..TOKENS.len())])
+                    .collect::<Vec<_>>();
+                Value::String(parts.join("+"))
+            }
+            6 => (0..rng.random_range(0..4))
+                .map(|_| random_value(rng, depth - 1))
+                .collect(),
+            _ => (0..rng.random_range(0..4))
+                .map(|i| (format!("key{i}"), random_value(rng, depth - 1)))
+                .collect(),
+        }
+    }
+}
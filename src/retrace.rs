@@ -1,46 +1,257 @@
-use anyhow::Result;
-use proguard::ProguardMapper;
-use tokio::fs;
+use anyhow::{Context, Result};
+use proguard::{ProguardMapper, StackFrame};
+use serde::Serialize;
 
-use crate::dirs::DIRS;
+use crate::{
+    metrics::METRICS,
+    report::Report,
+    stacktrace::{InApp, StackTrace},
+    storage::Storage,
+};
 
-pub async fn retrace(stacktrace: &str) -> Result<String> {
-    let mapping = fs::read_to_string(DIRS.mapping_file()).await?;
-    ProguardMapper::from(mapping.as_ref())
-        .remap_stacktrace(stacktrace)
-        .map_err(Into::into)
+/// The fields of a report that can contain class names, either as sent or deobfuscated.
+pub struct ReportText {
+    pub stack_trace: String,
+    pub logcat: String,
+    pub application_log: Option<String>,
+    pub thread_name: Option<String>,
+}
+
+impl ReportText {
+    pub fn original(report: &Report) -> Self {
+        Self {
+            stack_trace: report.stack_trace.clone(),
+            logcat: report.logcat.clone(),
+            application_log: report.application_log.clone(),
+            thread_name: report.thread_details.as_ref().map(|t| t.name.clone()),
+        }
+    }
+
+    fn remap(&self, mapper: &ProguardMapper<'_>) -> Result<Self> {
+        Ok(Self {
+            stack_trace: remap_text(mapper, &mapper.remap_stacktrace(&self.stack_trace)?),
+            logcat: remap_text(mapper, &self.logcat),
+            application_log: self
+                .application_log
+                .as_deref()
+                .map(|log| remap_text(mapper, log)),
+            thread_name: self
+                .thread_name
+                .as_deref()
+                .map(|name| remap_text(mapper, name)),
+        })
+    }
+}
+
+/// A deobfuscated stack trace, together with the origin of each frame.
+#[derive(Serialize)]
+pub struct Retraced {
+    pub stack_trace: String,
+    pub frames: Vec<FrameExpansion>,
+}
+
+/// A single frame of the obfuscated stack trace and the frames it stands for.
+#[derive(Serialize)]
+pub struct FrameExpansion {
+    pub original: String,
+    /// Deobfuscated frames, innermost first. If there is more than one, all but the last were
+    /// inlined into the last one by the optimizer.
+    pub remapped: Vec<String>,
+}
+
+impl FrameExpansion {
+    /// Frames that were inlined into the last remapped frame.
+    pub fn inlined(&self) -> &[String] {
+        self.remapped
+            .split_last()
+            .map_or(&[], |(_, inlined)| inlined)
+    }
+}
+
+/// Deobfuscate a stack trace with the mapping of the given app version.
+pub async fn retrace(
+    storage: &Storage,
+    app_id: i64,
+    version_code: i64,
+    stacktrace: &str,
+) -> Result<String> {
+    measure(async {
+        let mapping = self::mapping(storage, app_id, version_code).await?;
+        let mapper = ProguardMapper::from(mapping.as_str());
+        Ok(remap_text(&mapper, &mapper.remap_stacktrace(stacktrace)?))
+    })
+    .await
+}
+
+/// Deobfuscate all text fields of a report, with the mapping of the version it was sent from.
+pub async fn retrace_report(storage: &Storage, app_id: i64, report: &Report) -> Result<ReportText> {
+    measure(async {
+        let mapping = self::mapping(storage, app_id, report.app_version_code.into()).await?;
+        ReportText::original(report).remap(&ProguardMapper::from(mapping.as_str()))
+    })
+    .await
+}
+
+/// Deobfuscate a stack trace with the given mapping, expanding each frame separately as well.
+pub async fn retrace_frames(mapping: &str, stacktrace: &str) -> Result<Retraced> {
+    measure(async {
+        let mapper = ProguardMapper::from(mapping);
+        let trace = StackTrace::parse(stacktrace, &InApp::default());
+
+        let frames = trace
+            .frames()
+            .map(|frame| {
+                let line = frame.line.map_or(0, |line| line as usize);
+                let frame = match &frame.file {
+                    Some(file) => StackFrame::with_file(&frame.class, &frame.method, line, file),
+                    None => StackFrame::new(&frame.class, &frame.method, line),
+                };
+                let original = format_frame(&frame);
+                let mut remapped = mapper
+                    .remap_frame(&frame)
+                    .map(|frame| format_frame(&frame))
+                    .collect::<Vec<_>>();
+
+                if remapped.is_empty() {
+                    remapped.push(original.clone());
+                }
+
+                FrameExpansion { original, remapped }
+            })
+            .collect();
+
+        Ok(Retraced {
+            stack_trace: remap_text(&mapper, &mapper.remap_stacktrace(stacktrace)?),
+            frames,
+        })
+    })
+    .await
+}
+
+fn format_frame(frame: &StackFrame<'_>) -> String {
+    let file = frame.file().unwrap_or("Unknown Source");
+
+    match frame.line() {
+        0 => format!("{}.{}({file})", frame.class(), frame.method()),
+        line => format!("{}.{}({file}:{line})", frame.class(), frame.method()),
+    }
+}
+
+async fn measure<T>(f: impl Future<Output = Result<T>>) -> Result<T> {
+    let _timer = METRICS.retrace_duration.start_timer();
+    let result = f.await;
+
+    if result.is_err() {
+        METRICS.retrace_failures.inc();
+    }
+
+    result
+}
+
+async fn mapping(storage: &Storage, app_id: i64, version_code: i64) -> Result<String> {
+    storage
+        .version_mapping(app_id, version_code)
+        .await?
+        .context("no mapping file available")
+}
+
+/// Replace all obfuscated class names in free text, like exception messages and log lines.
+///
+/// Only fully qualified names are considered, as a single short name like `a` is too likely to be
+/// a regular word.
+fn remap_text(mapper: &ProguardMapper<'_>, text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(is_name_char) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        // A trailing dot is more likely the end of a sentence than part of the name.
+        let name = token.trim_end_matches('.');
+
+        let original = if name.contains('.') {
+            mapper.remap_class(name)
+        } else {
+            None
+        };
+        output.push_str(original.unwrap_or(name));
+        output.push_str(&token[name.len()..]);
+
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '$' | '_')
 }
 
 #[cfg(test)]
 mod tests {
     use proguard::ProguardMapper;
 
-    // const MAPPING: &str = include_str!("../mapping.txt");
-    const MAPPING: &str = "";
+    const OBFUSCATED: &str = "\
+com.example.notes.ui.NoteFragment -> a.b.c:
+    void onClick() -> a
+com.example.notes.data.Note -> a.b.d:
+";
+
+    #[test]
+    fn remap_text() {
+        let mapper = ProguardMapper::from(OBFUSCATED);
+
+        assert_eq!(
+            "java.lang.ClassCastException: com.example.notes.ui.NoteFragment cannot be cast to \
+             com.example.notes.data.Note",
+            super::remap_text(
+                &mapper,
+                "java.lang.ClassCastException: a.b.c cannot be cast to a.b.d"
+            )
+        );
+        assert_eq!(
+            "E/Notes(1234): failed loading com.example.notes.data.Note.\nI/Notes(1234): a b c ab.c",
+            super::remap_text(
+                &mapper,
+                "E/Notes(1234): failed loading a.b.d.\nI/Notes(1234): a b c ab.c"
+            )
+        );
+        assert_eq!(
+            "Thread-3 (ümlaut)",
+            super::remap_text(&mapper, "Thread-3 (ümlaut)")
+        );
+        assert_eq!("\t... 3 more", super::remap_text(&mapper, "\t... 3 more"));
+    }
+
+    const MAPPING: &str = include_str!("../fixtures/mappings/reciply.txt");
 
     #[test]
     fn retrace_class() {
         let mapper = ProguardMapper::from(MAPPING);
 
-        println!(
-            "{}",
+        // Frames that were inlined are expanded, and ones of unmapped classes are kept as they are.
+        assert_eq!(
+            "java.lang.IllegalStateException: boom
+    at rocks.dnaka91.reciply.fragment.RecipeFragment.openEditor(RecipeFragment.kt:142)
+    at rocks.dnaka91.reciply.fragment.RecipeFragment$onViewCreated$3.onClick(RecipeFragment.kt:87)
+    at android.view.View.performClick(View.java:7393)
+    at com.google.android.material.button.MaterialButton.toggle(MaterialButton.java:1133)
+    at com.google.android.material.button.MaterialButton.performClick(MaterialButton.java:1145)
+    at rocks.dnaka91.reciply.fragment.RecipeFragment$onViewCreated$3.onClick(RecipeFragment.kt:89)
+    at java.lang.reflect.Method.invoke(Native Method)
+",
             mapper
                 .remap_stacktrace(
-                    "
+                    "java.lang.IllegalStateException: boom
     at rocks.dnaka91.reciply.fragment.RecipeFragment$f.onClick(SourceFile:1)
     at android.view.View.performClick(View.java:7393)
-    at com.google.android.material.button.MaterialButton.performClick(SourceFile:2)
-    at android.view.View.performClickInternal(View.java:7370)
-    at android.view.View.access$3700(View.java:815)
-    at android.view.View$PerformClick.run(View.java:28508)
-    at android.os.Handler.handleCallback(Handler.java:938)
-    at android.os.Handler.dispatchMessage(Handler.java:99)
-    at android.os.Looper.loopOnce(Looper.java:201)
-    at android.os.Looper.loop(Looper.java:288)
-    at android.app.ActivityThread.main(ActivityThread.java:7729)
-    at java.lang.reflect.Method.invoke(Native Method)
-    at com.android.internal.os.RuntimeInit$MethodAndArgsCaller.run(RuntimeInit.java:548)
-    at com.android.internal.os.ZygoteInit.main(ZygoteInit.java:974)"
+    at com.google.android.material.button.MaterialButton.performClick(SourceFile:1)
+    at rocks.dnaka91.reciply.fragment.RecipeFragment$f.onClick(SourceFile:2)
+    at java.lang.reflect.Method.invoke(Native Method)"
                 )
                 .unwrap()
         );
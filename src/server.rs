@@ -0,0 +1,293 @@
+//! Binding of all configured listeners, optionally secured with TLS, and serving the application on
+//! each of them.
+
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    Router,
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+};
+use parking_lot::RwLock;
+use tokio::{
+    net::{TcpListener, TcpStream, UnixListener},
+    sync::mpsc,
+    task::JoinSet,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+    server::TlsStream,
+};
+use tokio_shutdown::Shutdown;
+use tracing::{debug, info, warn};
+
+use crate::settings::{Listen, Server, Tls};
+
+/// Interval in which the certificate and key files are checked for changes.
+const TLS_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+/// Maximum time a client may take to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Address of the remote side of a connection.
+#[derive(Clone, Copy, Debug)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// Unix sockets are only reachable locally, usually by a reverse proxy running on the same
+    /// host, and don't carry a meaningful remote address.
+    Unix,
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self::Tcp(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self::Tcp(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for PeerAddr {
+    fn connect_info(_stream: IncomingStream<'_, UnixListener>) -> Self {
+        Self::Unix
+    }
+}
+
+/// Serve the application on all configured listeners, until the shutdown signal is received.
+pub async fn serve(settings: &Server, app: Router, shutdown: &Shutdown) -> Result<()> {
+    let tls = settings
+        .tls
+        .clone()
+        .map(TlsConfig::load)
+        .transpose()?
+        .map(Arc::new);
+
+    if let Some(tls) = &tls {
+        tls.watch();
+    }
+
+    let mut tasks = JoinSet::new();
+
+    for listen in &settings.listen {
+        let service = app
+            .clone()
+            .into_make_service_with_connect_info::<PeerAddr>();
+        let signal = shutdown.handle();
+
+        match (listen, &tls) {
+            (Listen::Tcp(addr), None) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("failed binding to {listen}"))?;
+                info!("listening on http://{addr}");
+                tasks.spawn(
+                    axum::serve(listener, service)
+                        .with_graceful_shutdown(signal)
+                        .into_future(),
+                );
+            }
+            (Listen::Tcp(addr), Some(tls)) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("failed binding to {listen}"))?;
+                let listener = TlsListener::new(listener, Arc::clone(tls))?;
+                info!("listening on https://{addr}");
+                tasks.spawn(
+                    axum::serve(listener, service)
+                        .with_graceful_shutdown(signal)
+                        .into_future(),
+                );
+            }
+            (Listen::Unix(path), _) => {
+                let listener =
+                    bind_unix(path).with_context(|| format!("failed binding to {listen}"))?;
+                info!("listening on {listen}");
+                tasks.spawn(
+                    axum::serve(listener, service)
+                        .with_graceful_shutdown(signal)
+                        .into_future(),
+                );
+            }
+        }
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        result??;
+    }
+
+    Ok(())
+}
+
+fn bind_unix(path: &Path) -> io::Result<UnixListener> {
+    // A socket file left over from a previous run would make the bind fail.
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    UnixListener::bind(path)
+}
+
+/// TLS settings that are reloaded whenever the certificate or key file changes.
+struct TlsConfig {
+    files: Tls,
+    config: RwLock<Arc<ServerConfig>>,
+    modified: RwLock<Option<(SystemTime, SystemTime)>>,
+}
+
+impl TlsConfig {
+    fn load(files: Tls) -> Result<Self> {
+        let config = load_server_config(&files)?;
+        let modified = modified_times(&files).ok();
+
+        Ok(Self {
+            files,
+            config: RwLock::new(Arc::new(config)),
+            modified: RwLock::new(modified),
+        })
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.config.read()))
+    }
+
+    /// Start a background task that periodically checks the certificate and key for changes, and
+    /// reloads them. Invalid files are reported but otherwise ignored, to keep the server running
+    /// with the last working configuration.
+    fn watch(self: &Arc<Self>) {
+        let this = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TLS_RELOAD_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let modified = match modified_times(&this.files) {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        warn!("failed checking TLS files for changes: {e:?}");
+                        continue;
+                    }
+                };
+
+                if *this.modified.read() == Some(modified) {
+                    continue;
+                }
+
+                match load_server_config(&this.files) {
+                    Ok(config) => {
+                        *this.config.write() = Arc::new(config);
+                        *this.modified.write() = Some(modified);
+                        info!("reloaded TLS certificate");
+                    }
+                    Err(e) => warn!("failed reloading TLS certificate: {e:?}"),
+                }
+            }
+        });
+    }
+}
+
+fn load_server_config(files: &Tls) -> Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&files.certificate)
+        .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+        .with_context(|| format!("failed loading certificate {}", files.certificate.display()))?;
+    let key = PrivateKeyDer::from_pem_file(&files.key)
+        .with_context(|| format!("failed loading key {}", files.key.display()))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("invalid certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+fn modified_times(files: &Tls) -> io::Result<(SystemTime, SystemTime)> {
+    Ok((
+        fs::metadata(&files.certificate)?.modified()?,
+        fs::metadata(&files.key)?.modified()?,
+    ))
+}
+
+/// Listener that performs the TLS handshake for each accepted TCP connection.
+///
+/// Handshakes run in separate tasks, so a slow client can't hold up other incoming connections.
+pub struct TlsListener {
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    fn new(listener: TcpListener, config: Arc<TlsConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, addr) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        warn!("failed accepting connection: {e}");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                let acceptor = config.acceptor();
+                let tx = tx.clone();
+
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                    {
+                        Ok(Ok(stream)) => {
+                            tx.send((stream, addr)).await.ok();
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {addr} failed: {e}"),
+                        Err(_) => debug!("TLS handshake with {addr} timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            connections: rx,
+            local_addr,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Addr = SocketAddr;
+    type Io = TlsStream<TcpStream>;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(conn) => conn,
+            // The accepting task only stops once this listener is dropped, so the channel can't
+            // be closed while we're still waiting on it.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
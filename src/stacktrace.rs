@@ -0,0 +1,444 @@
+//! Parsing of Java and Kotlin stack traces, as printed by `Throwable.printStackTrace`, into a
+//! structured form.
+//!
+//! ```text
+//! java.lang.IllegalStateException: outer
+//!     at com.example.Foo.bar(Foo.kt:12)
+//!     Suppressed: java.io.IOException: while closing
+//!         at com.example.Foo.close(Foo.kt:30)
+//!         ... 1 more
+//! Caused by: java.lang.NullPointerException
+//!     at com.example.Baz.qux(Baz.java:5)
+//!     ... 3 more
+//! ```
+//!
+//! Parsing never fails. Lines that don't fit the format are added to the message of the exception
+//! they belong to, so nothing of the original text is lost.
+
+use std::iter::Peekable;
+
+/// A full stack trace, starting with the thrown exception and followed by its chain of causes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StackTrace {
+    pub exceptions: Vec<Exception>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exception {
+    /// Fully qualified class name, like `java.lang.IllegalStateException`.
+    pub class: String,
+    pub message: Option<String>,
+    pub frames: Vec<Frame>,
+    /// Amount of frames left out at the end, because they're the same as in the enclosing trace.
+    pub omitted: usize,
+    /// Exceptions that were suppressed while handling this one, each with its own chain of causes.
+    pub suppressed: Vec<StackTrace>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Fully qualified class name, including any nested classes like `com.example.Foo$Bar`.
+    pub class: String,
+    pub method: String,
+    /// Source file name, or `None` for native methods and unknown sources.
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    /// Whether the frame belongs to the app itself, instead of the framework or a library.
+    pub in_app: bool,
+}
+
+/// Rules to tell frames of the app apart from framework and library frames. The default marks no
+/// frame as in-app, for traces that are only looked at for their structure.
+#[derive(Default)]
+pub struct InApp<'a> {
+    prefixes: Vec<&'a str>,
+}
+
+impl<'a> InApp<'a> {
+    /// Consider all classes in the app's package as in-app, plus any of the given extra package
+    /// prefixes.
+    pub fn new(package_name: &'a str, prefixes: &'a [String]) -> Self {
+        Self {
+            prefixes: std::iter::once(package_name)
+                .chain(prefixes.iter().map(String::as_str))
+                .map(|prefix| prefix.trim_end_matches('.'))
+                .filter(|prefix| !prefix.is_empty())
+                .collect(),
+        }
+    }
+
+    fn matches(&self, class: &str) -> bool {
+        // A prefix only matches whole package segments, so `com.example` doesn't match
+        // `com.example2.Foo`.
+        self.prefixes.iter().any(|prefix| {
+            class
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '$']))
+        })
+    }
+}
+
+impl StackTrace {
+    pub fn parse(text: &str, in_app: &InApp<'_>) -> Self {
+        let mut lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let content = line.trim_start();
+                (line.len() - content.len(), content.trim_end())
+            })
+            .peekable();
+
+        let Some((indent, header)) = lines.next() else {
+            return Self::default();
+        };
+
+        parse_chain(&mut lines, indent, header, in_app)
+    }
+
+    /// The innermost cause, which is usually the most helpful to understand the problem.
+    pub fn root_cause(&self) -> Option<&Exception> {
+        self.exceptions.last()
+    }
+
+    /// All frames of all exceptions in the chain, excluding suppressed exceptions.
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.exceptions
+            .iter()
+            .flat_map(|exception| &exception.frames)
+    }
+}
+
+/// Parse an exception and all its causes, which are on the same indentation as the exception.
+fn parse_chain<'a, I>(
+    lines: &mut Peekable<I>,
+    indent: usize,
+    header: &'a str,
+    in_app: &InApp<'_>,
+) -> StackTrace
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let mut exceptions = vec![parse_exception(lines, indent, header, in_app)];
+
+    while let Some(&(next_indent, line)) = lines.peek() {
+        let Some(header) = line.strip_prefix("Caused by: ") else {
+            break;
+        };
+        if next_indent != indent {
+            break;
+        }
+
+        lines.next();
+        exceptions.push(parse_exception(lines, indent, header, in_app));
+    }
+
+    StackTrace { exceptions }
+}
+
+fn parse_exception<'a, I>(
+    lines: &mut Peekable<I>,
+    indent: usize,
+    header: &'a str,
+    in_app: &InApp<'_>,
+) -> Exception
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    let (class, message) = match header.split_once(':') {
+        Some((class, message)) => (class, Some(message.trim_start().to_owned())),
+        None => (header, None),
+    };
+    let mut exception = Exception {
+        class: class.to_owned(),
+        message,
+        ..Exception::default()
+    };
+
+    while let Some(&(next_indent, line)) = lines.peek() {
+        if let Some(frame) = line.strip_prefix("at ") {
+            exception.frames.push(parse_frame(frame, in_app));
+        } else if let Some(count) = parse_omitted(line) {
+            exception.omitted = count;
+        } else if let Some(header) = line.strip_prefix("Suppressed: ") {
+            if next_indent <= indent {
+                break;
+            }
+
+            lines.next();
+            let suppressed = parse_chain(lines, next_indent, header, in_app);
+            exception.suppressed.push(suppressed);
+            continue;
+        } else if line.starts_with("Caused by: ") && next_indent <= indent {
+            break;
+        } else if exception.frames.is_empty() {
+            // Messages can span multiple lines, up until the first frame.
+            let message = exception.message.get_or_insert_default();
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(line);
+        } else {
+            break;
+        }
+
+        lines.next();
+    }
+
+    exception
+}
+
+/// Parse a frame like `com.example.Foo.bar(Foo.kt:12)`, without the leading `at `.
+fn parse_frame(frame: &str, in_app: &InApp<'_>) -> Frame {
+    let (name, location) = match frame.split_once('(') {
+        Some((name, location)) => (name, location.strip_suffix(')').unwrap_or(location)),
+        None => (frame, ""),
+    };
+    // Newer Java versions prefix the class with its module or class loader, like
+    // `java.base/java.lang.Thread.run`.
+    let name = name.rsplit_once('/').map_or(name, |(_, name)| name);
+    let (class, method) = name.rsplit_once('.').unwrap_or(("", name));
+
+    let (file, line) = match location.rsplit_once(':') {
+        Some((file, line)) => (file, line.parse().ok()),
+        None => (location, None),
+    };
+    let file = (!matches!(file, "" | "Native Method" | "Unknown Source")).then(|| file.to_owned());
+
+    Frame {
+        class: class.to_owned(),
+        method: method.to_owned(),
+        file,
+        line,
+        in_app: in_app.matches(class),
+    }
+}
+
+/// A part of the original text of a stack trace, as shown in views.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Section<'a> {
+    /// Lines that are shown as is, like exception headers and in-app frames.
+    Text(&'a str),
+    /// Consecutive frames of the framework and libraries, that are collapsed by default.
+    Framework(Vec<&'a str>),
+}
+
+/// Split the text of a stack trace into sections, so views can collapse the frames outside of the
+/// app. Nothing is collapsed if no frame is in-app, as the app is most likely not known then.
+pub fn sections<'a>(text: &'a str, in_app: &InApp<'_>) -> Vec<Section<'a>> {
+    let frames = text
+        .lines()
+        .map(|line| {
+            let frame = line.trim().strip_prefix("at ");
+            (line, frame.map(|frame| parse_frame(frame, in_app).in_app))
+        })
+        .collect::<Vec<_>>();
+
+    if !frames.iter().any(|&(_, in_app)| in_app == Some(true)) {
+        return text.lines().map(Section::Text).collect();
+    }
+
+    let mut sections = Vec::new();
+    for (line, in_app) in frames {
+        match (in_app, sections.last_mut()) {
+            (Some(false), Some(Section::Framework(lines))) => lines.push(line),
+            (Some(false), _) => sections.push(Section::Framework(vec![line])),
+            _ => sections.push(Section::Text(line)),
+        }
+    }
+
+    // A single frame takes as much space as the line that would reveal it.
+    sections
+        .into_iter()
+        .map(|section| match section {
+            Section::Framework(lines) if lines.len() == 1 => Section::Text(lines[0]),
+            section => section,
+        })
+        .collect()
+}
+
+/// Parse the amount of a line like `... 12 more`.
+fn parse_omitted(line: &str) -> Option<usize> {
+    line.strip_prefix("... ")?
+        .strip_suffix(" more")?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frame, InApp, Section, StackTrace, sections};
+
+    const TRACE: &str = "\
+java.lang.RuntimeException: Unable to start activity: java.lang.IllegalStateException: boom
+\tat android.app.ActivityThread.performLaunchActivity(ActivityThread.java:3270)
+\tat java.lang.reflect.Method.invoke(Native Method)
+\tat com.example.notes.ui.MainActivity$onCreate$1.invoke(Unknown Source:4)
+\tSuppressed: java.io.IOException: failed closing
+\t\tat com.example.notes.data.Db.close(Db.kt:12)
+\t\t... 3 more
+\tCaused by: java.io.EOFException
+\t\t... 4 more
+Caused by: java.lang.IllegalStateException: boom
+second line of the message
+\tat com.example.notes.data.NoteRepository.require(NoteRepository.kt:57)
+\tat com.example.shared.Checks.check(Checks.java:9)
+\tat com.example.notesextra.Other.run(Other.java:1)
+\tat java.base/java.lang.Thread.run(Thread.java:833)
+\t... 11 more
+";
+
+    fn frame(class: &str, method: &str, file: Option<&str>, line: Option<u32>) -> Frame {
+        Frame {
+            class: class.to_owned(),
+            method: method.to_owned(),
+            file: file.map(ToOwned::to_owned),
+            line,
+            in_app: false,
+        }
+    }
+
+    #[test]
+    fn parse_chain() {
+        let prefixes = ["com.example.shared.".to_owned()];
+        let trace = StackTrace::parse(TRACE, &InApp::new("com.example.notes", &prefixes));
+
+        assert_eq!(2, trace.exceptions.len());
+
+        let outer = &trace.exceptions[0];
+        assert_eq!("java.lang.RuntimeException", outer.class);
+        assert_eq!(
+            Some("Unable to start activity: java.lang.IllegalStateException: boom"),
+            outer.message.as_deref()
+        );
+        assert_eq!(
+            vec![
+                frame(
+                    "android.app.ActivityThread",
+                    "performLaunchActivity",
+                    Some("ActivityThread.java"),
+                    Some(3270)
+                ),
+                frame("java.lang.reflect.Method", "invoke", None, None),
+                Frame {
+                    in_app: true,
+                    ..frame(
+                        "com.example.notes.ui.MainActivity$onCreate$1",
+                        "invoke",
+                        None,
+                        Some(4)
+                    )
+                },
+            ],
+            outer.frames
+        );
+        assert_eq!(0, outer.omitted);
+
+        assert_eq!(1, outer.suppressed.len());
+        let suppressed = &outer.suppressed[0].exceptions;
+        assert_eq!(2, suppressed.len());
+        assert_eq!("java.io.IOException", suppressed[0].class);
+        assert_eq!(3, suppressed[0].omitted);
+        assert!(suppressed[0].frames[0].in_app);
+        assert_eq!("java.io.EOFException", suppressed[1].class);
+        assert_eq!(None, suppressed[1].message);
+        assert_eq!(4, suppressed[1].omitted);
+
+        let cause = trace.root_cause().unwrap();
+        assert_eq!("java.lang.IllegalStateException", cause.class);
+        assert_eq!(
+            Some("boom\nsecond line of the message"),
+            cause.message.as_deref()
+        );
+        assert_eq!(11, cause.omitted);
+        assert_eq!(
+            vec![
+                ("com.example.notes.data.NoteRepository", true),
+                ("com.example.shared.Checks", true),
+                ("com.example.notesextra.Other", false),
+                ("java.lang.Thread", false),
+            ],
+            cause
+                .frames
+                .iter()
+                .map(|f| (f.class.as_str(), f.in_app))
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(7, trace.frames().count());
+    }
+
+    #[test]
+    fn parse_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reports");
+
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let report =
+                serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path).unwrap())
+                    .unwrap();
+            let text = report["STACK_TRACE"].as_str().unwrap();
+            let trace = StackTrace::parse(text, &InApp::new("com.example.notes", &[]));
+
+            // Every line is either a header, a frame or an elision.
+            let lines = text.lines().filter(|l| !l.trim().is_empty()).count();
+            let parsed = trace
+                .exceptions
+                .iter()
+                .map(|e| 1 + e.frames.len() + usize::from(e.omitted > 0))
+                .sum::<usize>();
+            assert_eq!(lines, parsed, "{}", path.display());
+            assert!(
+                trace.frames().any(|f| f.in_app),
+                "no in-app frame in {}",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn collapse_framework_frames() {
+        let text = "\
+java.lang.IllegalStateException: boom
+\tat com.example.notes.Editor.save(Editor.kt:3)
+\tat android.view.View.performClick(View.java:7448)
+\tat android.os.Handler.handleCallback(Handler.java:938)
+\tat com.example.notes.Main.run(Main.kt:1)
+\tat java.lang.Thread.run(Thread.java:1012)";
+
+        assert_eq!(
+            vec![
+                Section::Text("java.lang.IllegalStateException: boom"),
+                Section::Text("\tat com.example.notes.Editor.save(Editor.kt:3)"),
+                Section::Framework(vec![
+                    "\tat android.view.View.performClick(View.java:7448)",
+                    "\tat android.os.Handler.handleCallback(Handler.java:938)",
+                ]),
+                Section::Text("\tat com.example.notes.Main.run(Main.kt:1)"),
+                Section::Text("\tat java.lang.Thread.run(Thread.java:1012)"),
+            ],
+            sections(text, &InApp::new("com.example.notes", &[]))
+        );
+        assert!(
+            sections(text, &InApp::new("org.other", &[]))
+                .iter()
+                .all(|section| matches!(section, Section::Text(_)))
+        );
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert_eq!(
+            StackTrace::default(),
+            StackTrace::parse("", &InApp::new("a", &[]))
+        );
+
+        let trace = StackTrace::parse("\tat \n... x more\n:\nCaused by: ", &InApp::default());
+        assert_eq!(1, trace.exceptions.len());
+        assert_eq!("at", trace.exceptions[0].class);
+        assert_eq!(
+            Some("... x more\n:\nCaused by:"),
+            trace.exceptions[0].message.as_deref()
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use tokio::fs;
+
+use super::{Backend, Object};
+
+pub(super) struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub(super) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl LocalBackend {
+    /// Write the file of a key through the given function, which receives a hidden file next to
+    /// it. That file is moved into place afterwards, so readers never see a partially written file.
+    async fn write<F>(&self, key: &str, write: impl FnOnce(PathBuf) -> F) -> Result<()>
+    where
+        F: Future<Output = io::Result<()>>,
+    {
+        let path = self.root.join(key);
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            bail!("invalid key `{key}`");
+        };
+
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("failed creating directory {}", dir.display()))?;
+
+        let temp = dir.join(format!(".{}.tmp", name.to_string_lossy()));
+
+        write(temp.clone())
+            .await
+            .with_context(|| format!("failed writing {}", path.display()))?;
+        fs::rename(&temp, &path)
+            .await
+            .with_context(|| format!("failed writing {}", path.display()))
+    }
+}
+
+#[async_trait]
+impl Backend for LocalBackend {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        self.write(key, |temp| fs::write(temp, data)).await
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()> {
+        self.write(
+            key,
+            |temp| async move { fs::copy(path, temp).await.map(|_| ()) },
+        )
+        .await
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.root.join(key);
+
+        match fs::read(&path).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed reading {}", path.display())),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut prefixes = vec![prefix.to_owned()];
+
+        while let Some(prefix) = prefixes.pop() {
+            let dir = self.root.join(&prefix);
+            let mut entries = match fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed listing {}", dir.display()));
+                }
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                let Some(name) = entry.file_name().to_str().map(ToOwned::to_owned) else {
+                    continue;
+                };
+
+                if name.starts_with('.') {
+                    continue;
+                }
+
+                if metadata.is_dir() {
+                    prefixes.push(format!("{prefix}{name}/"));
+                } else if metadata.is_file() {
+                    objects.push(Object {
+                        key: format!("{prefix}{name}"),
+                        size: metadata.len(),
+                    });
+                }
+            }
+        }
+
+        Ok(objects)
+    }
+}
@@ -0,0 +1,447 @@
+//! Storage of raw reports, their attachments and mapping files, either in a local directory or in
+//! an S3 compatible object storage.
+//!
+//! Both backends use the same layout of keys, which for a local directory is the same as it used
+//! to be in the data directory:
+//!
+//! - `reports/<app id>/<report id>.json`, or `.json.zst` if compressed. Reports stored before they
+//!   were kept per app are directly in `reports/`.
+//! - `attachments/<app id>/<report id>/<file name>`.
+//! - `mapping.txt`, used for all versions without a mapping of their own.
+//! - `mappings/<app id>/<version code>.txt`.
+//! - `symbols/<app id>/<build id>.debug`, the unstripped native libraries.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result, ensure};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::settings;
+
+mod local;
+mod s3;
+
+const REPORTS_PREFIX: &str = "reports/";
+const ATTACHMENTS_PREFIX: &str = "attachments/";
+const MAPPING_KEY: &str = "mapping.txt";
+const MAPPINGS_PREFIX: &str = "mappings/";
+const SYMBOLS_PREFIX: &str = "symbols/";
+const REPORT_SUFFIX: &str = ".json";
+const COMPRESSED_SUFFIX: &str = ".zst";
+/// Compression level for raw reports. Higher levels barely shrink the rather small reports further,
+/// but cost considerably more time.
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// A single stored file.
+pub struct Object {
+    pub key: String,
+    /// Size in bytes.
+    pub size: u64,
+}
+
+/// A stored raw report, see [`Storage::list_reports`].
+pub struct StoredReport {
+    /// App that the report was sent for, or `None` if it was stored before reports were kept per
+    /// app.
+    pub app_id: Option<i64>,
+    pub object: Object,
+}
+
+/// Low level access to a storage location. Keys are relative paths, separated by `/`.
+#[async_trait]
+trait Backend: Send + Sync {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+    /// Store the content of a local file, without loading all of it into memory.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()>;
+    /// Load the content of an object, or `None` if it doesn't exist.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// List all objects below the given prefix, which must end with a `/`.
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>>;
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    backend: Arc<dyn Backend>,
+    local_dir: Option<PathBuf>,
+    compress: bool,
+}
+
+impl Storage {
+    pub fn new(settings: &settings::Storage) -> Result<Self> {
+        let (backend, local_dir): (Arc<dyn Backend>, _) = if let Some(s3) = &settings.s3 {
+            (Arc::new(s3::S3Backend::new(s3)?), None)
+        } else {
+            let dir = settings.local_dir();
+            (Arc::new(local::LocalBackend::new(dir.clone())), Some(dir))
+        };
+
+        Ok(Self {
+            backend,
+            local_dir,
+            compress: settings.compress,
+        })
+    }
+
+    /// Directory that contains all files, or `None` if they're kept in an object storage.
+    pub fn local_dir(&self) -> Option<&Path> {
+        self.local_dir.as_deref()
+    }
+
+    /// Keep the original payload of a report that was sent for an app, replacing any previously
+    /// stored one.
+    pub async fn save_report(&self, app_id: i64, report_id: &str, raw: &Value) -> Result<()> {
+        check_name(report_id)?;
+
+        let mut key = format!("{REPORTS_PREFIX}{app_id}/{report_id}{REPORT_SUFFIX}");
+        let mut data = serde_json::to_vec(raw)?;
+
+        if self.compress {
+            data = zstd::encode_all(data.as_slice(), COMPRESSION_LEVEL)?;
+            key.push_str(COMPRESSED_SUFFIX);
+        }
+
+        self.backend.put(&key, data).await
+    }
+
+    /// List all stored raw reports, compressed or not.
+    pub async fn list_reports(&self) -> Result<Vec<StoredReport>> {
+        let objects = self.backend.list(REPORTS_PREFIX).await?;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|object| {
+                let path = object.key.strip_prefix(REPORTS_PREFIX)?;
+                let name = path.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(path);
+                if !name.ends_with(REPORT_SUFFIX) {
+                    return None;
+                }
+
+                let app_id = match path.split_once('/') {
+                    Some((app_id, _)) => Some(app_id.parse().ok()?),
+                    None => None,
+                };
+                Some(StoredReport { app_id, object })
+            })
+            .collect())
+    }
+
+    /// Load a raw report by the key returned from [`Self::list_reports`].
+    pub async fn load_report(&self, key: &str) -> Result<Value> {
+        let data = self
+            .backend
+            .get(key)
+            .await?
+            .with_context(|| format!("report {key} doesn't exist"))?;
+
+        decode_report(key, data)
+    }
+
+    /// Load a raw report of an app by its ID, or `None` if it doesn't exist.
+    pub async fn report(&self, app_id: i64, report_id: &str) -> Result<Option<Value>> {
+        if check_name(report_id).is_err() {
+            return Ok(None);
+        }
+
+        // Look for the current format first, but the report may have been saved before the
+        // compression setting was changed, or before reports were kept per app.
+        let suffixes = if self.compress {
+            [COMPRESSED_SUFFIX, ""]
+        } else {
+            ["", COMPRESSED_SUFFIX]
+        };
+        let dirs = [format!("{app_id}/"), String::new()];
+
+        for dir in &dirs {
+            for suffix in suffixes {
+                let key = format!("{REPORTS_PREFIX}{dir}{report_id}{REPORT_SUFFIX}{suffix}");
+                if let Some(data) = self.backend.get(&key).await? {
+                    return decode_report(&key, data).map(Some);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Keep a file that was sent together with a report. Names are reduced to a safe set of
+    /// characters, so they can't escape the directory of the report.
+    pub async fn save_attachment(
+        &self,
+        app_id: i64,
+        report_id: &str,
+        name: &str,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        check_name(report_id)?;
+
+        let key = format!(
+            "{ATTACHMENTS_PREFIX}{app_id}/{report_id}/{}",
+            sanitize(name)
+        );
+        self.backend.put(&key, data).await
+    }
+
+    pub async fn list_attachments(&self, app_id: i64, report_id: &str) -> Result<Vec<Object>> {
+        check_name(report_id)?;
+        self.backend
+            .list(&format!("{ATTACHMENTS_PREFIX}{app_id}/{report_id}/"))
+            .await
+    }
+
+    /// Load the ProGuard mapping file, or `None` if none was uploaded yet.
+    pub async fn mapping(&self) -> Result<Option<String>> {
+        self.backend
+            .get(MAPPING_KEY)
+            .await?
+            .map(String::from_utf8)
+            .transpose()
+            .context("mapping file isn't valid UTF-8")
+    }
+
+    pub async fn save_mapping(&self, content: String) -> Result<()> {
+        self.backend.put(MAPPING_KEY, content.into_bytes()).await
+    }
+
+    /// Load the ProGuard mapping file of a single app version, falling back to the shared one if
+    /// the version has none.
+    pub async fn version_mapping(&self, app_id: i64, version_code: i64) -> Result<Option<String>> {
+        let key = format!("{MAPPINGS_PREFIX}{app_id}/{version_code}.txt");
+
+        match self.backend.get(&key).await? {
+            Some(data) => String::from_utf8(data)
+                .map(Some)
+                .context("mapping file isn't valid UTF-8"),
+            None => self.mapping().await,
+        }
+    }
+
+    /// Store the mapping file of an app version, read from the given file.
+    pub async fn save_version_mapping(
+        &self,
+        app_id: i64,
+        version_code: i64,
+        file: &Path,
+    ) -> Result<()> {
+        let key = format!("{MAPPINGS_PREFIX}{app_id}/{version_code}.txt");
+        self.backend.put_file(&key, file).await
+    }
+
+    /// Load the debug file of a native library, identified by its build ID, or `None` if it wasn't
+    /// uploaded.
+    pub async fn debug_file(&self, app_id: i64, build_id: &str) -> Result<Option<Vec<u8>>> {
+        check_name(build_id)?;
+        self.backend
+            .get(&format!("{SYMBOLS_PREFIX}{app_id}/{build_id}.debug"))
+            .await
+    }
+
+    /// Store the debug file of a native library, read from the given file.
+    pub async fn save_debug_file(&self, app_id: i64, build_id: &str, file: &Path) -> Result<()> {
+        check_name(build_id)?;
+        self.backend
+            .put_file(&format!("{SYMBOLS_PREFIX}{app_id}/{build_id}.debug"), file)
+            .await
+    }
+}
+
+fn decode_report(key: &str, mut data: Vec<u8>) -> Result<Value> {
+    if key.ends_with(COMPRESSED_SUFFIX) {
+        data = zstd::decode_all(data.as_slice())
+            .with_context(|| format!("failed decompressing report {key}"))?;
+    }
+
+    serde_json::from_slice(&data).map_err(Into::into)
+}
+
+/// Make sure a name taken from a report can be safely used as a single segment of a key.
+fn check_name(name: &str) -> Result<()> {
+    ensure!(
+        !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+        "invalid name `{name}`"
+    );
+
+    Ok(())
+}
+
+/// Turn an arbitrary file name into one that passes [`check_name`].
+fn sanitize(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_start_matches('.');
+
+    if name.is_empty() {
+        "attachment".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::Storage;
+    use crate::settings;
+
+    #[tokio::test]
+    async fn local() {
+        let dir = std::env::temp_dir().join(format!("acralite-storage-{}", std::process::id()));
+
+        for compress in [false, true] {
+            let storage = Storage::new(&settings::Storage {
+                dir: Some(dir.clone()),
+                compress,
+                ..settings::Storage::default()
+            })
+            .unwrap();
+
+            suite(&storage, "local", compress).await;
+        }
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    /// Runs against the S3 compatible service given in `ACRALITE_TEST_S3_ENDPOINT`, with an
+    /// existing bucket named `acralite-test`. A local MinIO instance is a good fit:
+    ///
+    /// ```sh
+    /// docker run --rm -p 9000:9000 minio/minio server /data
+    /// mc mb local/acralite-test
+    /// ACRALITE_TEST_S3_ENDPOINT=http://localhost:9000 cargo test
+    /// ```
+    #[tokio::test]
+    async fn s3() {
+        let Ok(endpoint) = std::env::var("ACRALITE_TEST_S3_ENDPOINT") else {
+            eprintln!("ACRALITE_TEST_S3_ENDPOINT not set, skipping");
+            return;
+        };
+
+        let storage = Storage::new(&settings::Storage {
+            s3: Some(settings::S3 {
+                endpoint,
+                bucket: "acralite-test".to_owned(),
+                region: "us-east-1".to_owned(),
+                access_key: "minioadmin".to_owned(),
+                secret_key: "minioadmin".to_owned(),
+                path_style: true,
+            }),
+            compress: true,
+            ..settings::Storage::default()
+        })
+        .unwrap();
+
+        suite(&storage, "s3", true).await;
+    }
+
+    async fn suite(storage: &Storage, name: &str, compress: bool) {
+        let upload = std::env::temp_dir().join(format!(
+            "acralite-storage-upload-{name}-{}",
+            std::process::id()
+        ));
+        let report = json!({ "REPORT_ID": "a1", "STACK_TRACE": "boom" });
+        let other = json!({ "REPORT_ID": "a1", "STACK_TRACE": "other app" });
+        storage.save_report(1, "a1", &report).await.unwrap();
+        // Report IDs are only unique within an app, so another app's report is kept apart.
+        storage.save_report(2, "a1", &other).await.unwrap();
+        assert!(storage.save_report(1, "../a1", &report).await.is_err());
+        // Reports stored before they were kept per app are still found.
+        storage
+            .backend
+            .put("reports/b2.json", serde_json::to_vec(&report).unwrap())
+            .await
+            .unwrap();
+
+        let reports = storage
+            .list_reports()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.app_id, r.object.key))
+            .collect::<Vec<_>>();
+        let suffix = if compress { ".json.zst" } else { ".json" };
+        for expected in [
+            (None, "reports/b2.json".to_owned()),
+            (Some(1), format!("reports/1/a1{suffix}")),
+            (Some(2), format!("reports/2/a1{suffix}")),
+        ] {
+            assert!(reports.contains(&expected), "{expected:?} in {reports:?}");
+        }
+        assert!(reports.iter().all(|(_, key)| key.contains(".json")));
+        assert_eq!(
+            other,
+            storage
+                .load_report(&format!("reports/2/a1{suffix}"))
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            Some(&report),
+            storage.report(1, "a1").await.unwrap().as_ref()
+        );
+        assert_eq!(
+            Some(&other),
+            storage.report(2, "a1").await.unwrap().as_ref()
+        );
+        assert_eq!(
+            Some(&report),
+            storage.report(3, "b2").await.unwrap().as_ref()
+        );
+        assert_eq!(None, storage.report(1, "c3").await.unwrap());
+        assert_eq!(None, storage.report(1, "../a1").await.unwrap());
+
+        storage
+            .save_attachment(1, "a1", "../../log file.txt", b"hello".to_vec())
+            .await
+            .unwrap();
+        let attachments = storage.list_attachments(1, "a1").await.unwrap();
+        assert_eq!(1, attachments.len());
+        assert_eq!("attachments/1/a1/_.._log_file.txt", attachments[0].key);
+        assert_eq!(5, attachments[0].size);
+        assert!(storage.list_attachments(2, "a1").await.unwrap().is_empty());
+
+        storage.save_mapping("a -> b:\n".to_owned()).await.unwrap();
+        assert_eq!(
+            Some("a -> b:\n"),
+            storage.mapping().await.unwrap().as_deref()
+        );
+
+        std::fs::write(&upload, "c -> d:\n").unwrap();
+        storage.save_version_mapping(1, 2, &upload).await.unwrap();
+        assert_eq!(
+            Some("c -> d:\n"),
+            storage.version_mapping(1, 2).await.unwrap().as_deref()
+        );
+        assert_eq!(
+            Some("a -> b:\n"),
+            storage.version_mapping(1, 3).await.unwrap().as_deref()
+        );
+
+        std::fs::write(&upload, "elf").unwrap();
+        storage.save_debug_file(1, "6f1e2d", &upload).await.unwrap();
+        assert_eq!(
+            Some(b"elf".to_vec()),
+            storage.debug_file(1, "6f1e2d").await.unwrap()
+        );
+        assert_eq!(None, storage.debug_file(2, "6f1e2d").await.unwrap());
+        assert!(storage.debug_file(1, "../6f1e2d").await.is_err());
+
+        std::fs::remove_file(upload).ok();
+    }
+}
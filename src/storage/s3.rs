@@ -0,0 +1,143 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use reqwest::{Client, Response, StatusCode, Url, header::CONTENT_LENGTH};
+use rusty_s3::{
+    Bucket, Credentials, S3Action, UrlStyle,
+    actions::{GetObject, ListObjectsV2, PutObject},
+};
+use tokio::fs::File;
+
+use super::{Backend, Object};
+use crate::settings;
+
+/// Time that signed request URLs stay valid. They're used right away, so this only has to cover
+/// slight differences between the clocks of both sides.
+const SIGNATURE_DURATION: Duration = Duration::from_mins(1);
+/// Maximum time a single request may take, including the transfer of the content.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Maximum time the upload of a file may take, which can be as large as a debug file.
+const FILE_TIMEOUT: Duration = Duration::from_mins(10);
+
+pub(super) struct S3Backend {
+    client: Client,
+    bucket: Bucket,
+    credentials: Credentials,
+}
+
+impl S3Backend {
+    pub(super) fn new(settings: &settings::S3) -> Result<Self> {
+        let endpoint = settings
+            .endpoint
+            .parse::<Url>()
+            .context("invalid S3 endpoint")?;
+        let style = if settings.path_style {
+            UrlStyle::Path
+        } else {
+            UrlStyle::VirtualHost
+        };
+
+        Ok(Self {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            bucket: Bucket::new(
+                endpoint,
+                style,
+                settings.bucket.clone(),
+                settings.region.clone(),
+            )
+            .context("invalid S3 bucket")?,
+            credentials: Credentials::new(&settings.access_key, &settings.secret_key),
+        })
+    }
+}
+
+#[async_trait]
+impl Backend for S3Backend {
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let url =
+            PutObject::new(&self.bucket, Some(&self.credentials), key).sign(SIGNATURE_DURATION);
+        let response = self.client.put(url).body(data).send().await?;
+
+        check_status(response, key).await?;
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()> {
+        let file = File::open(path)
+            .await
+            .with_context(|| format!("failed opening {}", path.display()))?;
+        let len = file.metadata().await?.len();
+        // S3 doesn't accept chunked transfers, so the length has to be sent up front.
+        let url =
+            PutObject::new(&self.bucket, Some(&self.credentials), key).sign(SIGNATURE_DURATION);
+        let response = self
+            .client
+            .put(url)
+            .header(CONTENT_LENGTH, len)
+            .timeout(FILE_TIMEOUT)
+            .body(file)
+            .send()
+            .await?;
+
+        check_status(response, key).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let url =
+            GetObject::new(&self.bucket, Some(&self.credentials), key).sign(SIGNATURE_DURATION);
+        let response = self.client.get(url).send().await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let data = check_status(response, key).await?.bytes().await?;
+        Ok(Some(data.into()))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut token = None;
+
+        loop {
+            let mut action = ListObjectsV2::new(&self.bucket, Some(&self.credentials));
+            action.with_prefix(prefix);
+            if let Some(token) = token.take() {
+                action.with_continuation_token(token);
+            }
+
+            let response = self
+                .client
+                .get(action.sign(SIGNATURE_DURATION))
+                .send()
+                .await?;
+            let body = check_status(response, prefix).await?.bytes().await?;
+            let list = ListObjectsV2::parse_response(&body).context("invalid S3 list response")?;
+
+            // Keys come URL encoded, but all keys written by us consist of URL safe characters.
+            objects.extend(list.contents.into_iter().map(|content| Object {
+                key: content.key,
+                size: content.size,
+            }));
+
+            match list.next_continuation_token {
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(objects)
+    }
+}
+
+async fn check_status(response: Response, key: &str) -> Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    bail!("S3 request for `{key}` failed with {status}: {body}");
+}
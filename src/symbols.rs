@@ -0,0 +1,281 @@
+//! Symbolication of native frames with uploaded debug files, which are the unstripped versions of
+//! an app's native libraries. Files are identified by their GNU build ID, which tombstones print
+//! for every frame.
+
+use std::{borrow::Cow, collections::HashMap, fmt, fmt::Write, sync::Arc};
+
+use anyhow::{Context, Result};
+use object::{Object, ObjectSection, ObjectSymbol, ReadRef, SymbolKind};
+use tracing::warn;
+
+use crate::{
+    dropbox::{Content, Entry},
+    storage::Storage,
+};
+
+type Reader = gimli::EndianArcSlice<gimli::RunTimeEndian>;
+
+/// A location in the source code, resolved from the debug information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFrame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for SourceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.function.as_deref().unwrap_or("??"))?;
+
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " ({file}:{line})"),
+            (Some(file), None) => write!(f, " ({file})"),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// A parsed debug file, ready for lookups.
+pub struct DebugFile {
+    context: addr2line::Context<Reader>,
+    /// Function symbols as `(address, size, name)`, sorted by address. Used for code without DWARF
+    /// information.
+    symbols: Vec<(u64, u64, String)>,
+}
+
+impl DebugFile {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let file = object::File::parse(data).context("not a valid ELF file")?;
+        let endian = if file.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_> {
+            let data = match file.section_by_name(id.name()) {
+                Some(section) => section
+                    .uncompressed_data()
+                    .with_context(|| format!("failed decompressing section {}", id.name()))?,
+                None => Cow::Borrowed(&[][..]),
+            };
+            Ok(Reader::new(Arc::from(&*data), endian))
+        })?;
+        let context =
+            addr2line::Context::from_dwarf(dwarf).context("invalid DWARF debug information")?;
+
+        let mut symbols = file
+            .symbols()
+            .chain(file.dynamic_symbols())
+            .filter(|symbol| symbol.kind() == SymbolKind::Text && symbol.address() != 0)
+            .filter_map(|symbol| {
+                let name = addr2line::demangle_auto(Cow::Borrowed(symbol.name().ok()?), None);
+                Some((symbol.address(), symbol.size(), name.into_owned()))
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_unstable();
+        symbols.dedup_by_key(|(address, ..)| *address);
+
+        Ok(Self { context, symbols })
+    }
+
+    /// Resolve an address, relative to the start of the library, into its source frames. The
+    /// innermost frame comes first, and if there is more than one, all but the last were inlined
+    /// into the last one. Empty if the address isn't part of any known function.
+    pub fn resolve(&self, pc: u64) -> Result<Vec<SourceFrame>> {
+        let mut frames = Vec::new();
+        let mut iter = self.context.find_frames(pc).skip_all_loads()?;
+
+        while let Some(frame) = iter.next()? {
+            let location = frame.location.as_ref();
+            frames.push(SourceFrame {
+                function: frame
+                    .function
+                    .as_ref()
+                    .map(|function| function.demangle().map(Cow::into_owned))
+                    .transpose()?,
+                file: location.and_then(|l| l.file).map(ToOwned::to_owned),
+                line: location.and_then(|l| l.line),
+            });
+        }
+
+        // Without DWARF information, at least the function is known from the symbol table.
+        if frames.last().is_none_or(|frame| frame.function.is_none())
+            && let Some(name) = self.symbol(pc)
+        {
+            match frames.last_mut() {
+                Some(frame) => frame.function = Some(name.to_owned()),
+                None => frames.push(SourceFrame {
+                    function: Some(name.to_owned()),
+                    file: None,
+                    line: None,
+                }),
+            }
+        }
+
+        Ok(frames)
+    }
+
+    fn symbol(&self, pc: u64) -> Option<&str> {
+        let index = self.symbols.partition_point(|(address, ..)| *address <= pc);
+        let (address, size, name) = self.symbols.get(index.checked_sub(1)?)?;
+        (*size == 0 || pc < address + size).then_some(name.as_str())
+    }
+}
+
+/// Read the GNU build ID of an ELF file, hex encoded like in tombstones.
+pub fn build_id<'data>(data: impl ReadRef<'data>) -> Result<Option<String>> {
+    let file = object::File::parse(data).context("not a valid ELF file")?;
+    let id = file.build_id().context("invalid build ID")?;
+
+    Ok(id.map(|id| {
+        id.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+    }))
+}
+
+/// Whether the text looks like a build ID, which is used as part of the storage key.
+pub fn is_build_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Resolve the frames of all native crashes with the uploaded debug files of the app. Frames of
+/// libraries without a debug file, or with one that can't be read, are left as they are.
+pub async fn symbolicate(storage: &Storage, app_id: i64, entries: &mut [Entry]) {
+    let mut files = HashMap::<String, Option<DebugFile>>::new();
+
+    for entry in entries {
+        let Content::NativeCrash(crash) = &mut entry.content else {
+            continue;
+        };
+
+        for frame in &mut crash.frames {
+            let Some(build_id) = frame.build_id.as_deref().filter(|id| is_build_id(id)) else {
+                continue;
+            };
+            let build_id = build_id.to_ascii_lowercase();
+
+            if !files.contains_key(&build_id) {
+                // A broken file only leaves the frames of its own library unresolved.
+                let file = load_debug_file(storage, app_id, &build_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("skipping debug file {build_id}: {e:#}");
+                        None
+                    });
+                files.insert(build_id.clone(), file);
+            }
+
+            if let Some(file) = &files[&build_id] {
+                match file.resolve(frame.pc) {
+                    Ok(resolved) => frame.resolved = resolved,
+                    Err(e) => warn!("failed resolving {:#x} in {build_id}: {e:#}", frame.pc),
+                }
+            }
+        }
+    }
+}
+
+async fn load_debug_file(
+    storage: &Storage,
+    app_id: i64,
+    build_id: &str,
+) -> Result<Option<DebugFile>> {
+    storage
+        .debug_file(app_id, build_id)
+        .await?
+        .map(|data| DebugFile::parse(&data).context("failed parsing"))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DebugFile, SourceFrame, build_id, symbolicate};
+    use crate::{
+        dropbox::{Content, Entry, NativeCrash, NativeFrame},
+        settings,
+        storage::Storage,
+    };
+
+    const LIBNOTES: &[u8] = include_bytes!("../fixtures/symbols/libnotes.so");
+
+    fn frame(function: &str, line: u32) -> SourceFrame {
+        SourceFrame {
+            function: Some(function.to_owned()),
+            file: Some("/src/libnotes.c".to_owned()),
+            line: Some(line),
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let file = DebugFile::parse(LIBNOTES).unwrap();
+
+        assert_eq!(
+            vec![frame("title_length", 11), frame("render_note", 19)],
+            file.resolve(0x1127).unwrap()
+        );
+        assert_eq!(vec![frame("draw", 26)], file.resolve(0x1177).unwrap());
+        assert!(file.resolve(0x10).unwrap().is_empty());
+
+        assert_eq!(
+            "render_note (/src/libnotes.c:19)",
+            frame("render_note", 19).to_string()
+        );
+    }
+
+    #[test]
+    fn read_build_id() {
+        assert_eq!(
+            Some("ad2eed1ef99b9282ba94339466faec60d4babb86".to_owned()),
+            build_id(LIBNOTES).unwrap()
+        );
+        assert!(build_id(&b"not an elf file"[..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn skip_broken_debug_file() {
+        let dir = std::env::temp_dir().join(format!("acralite-symbols-{}", std::process::id()));
+        let storage = Storage::new(&settings::Storage {
+            dir: Some(dir.clone()),
+            ..settings::Storage::default()
+        })
+        .unwrap();
+
+        let id = build_id(LIBNOTES).unwrap().unwrap();
+        let upload = dir.join("upload");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&upload, "not an elf file").unwrap();
+        storage.save_debug_file(1, "ab12", &upload).await.unwrap();
+        std::fs::write(&upload, LIBNOTES).unwrap();
+        storage.save_debug_file(1, &id, &upload).await.unwrap();
+
+        let native = |build_id: &str, pc| NativeFrame {
+            pc,
+            library: "libnotes.so".to_owned(),
+            symbol: None,
+            build_id: Some(build_id.to_owned()),
+            resolved: Vec::new(),
+        };
+        let mut entries = [Entry {
+            tag: "data_app_native_crash".to_owned(),
+            time: String::new(),
+            content: Content::NativeCrash(NativeCrash {
+                frames: vec![native("ab12", 0x10), native(&id, 0x1177)],
+                ..NativeCrash::default()
+            }),
+        }];
+
+        symbolicate(&storage, 1, &mut entries).await;
+
+        let Content::NativeCrash(crash) = &entries[0].content else {
+            unreachable!();
+        };
+        assert!(crash.frames[0].resolved.is_empty());
+        assert_eq!(vec![frame("draw", 26)], crash.frames[1].resolved);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,295 @@
+//! Tags that are extracted from the `CUSTOM_DATA` and `BUILD_CONFIG` fields of reports, so reports
+//! can be filtered by values like the user tier or the build flavor.
+//!
+//! Each app configures the keys to extract, written like `CUSTOM_DATA.tier`. The key alone is the
+//! name of the tag, and filters are written like `tier:premium`.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::{
+    db::models::{Tag, TagKey, TagSource},
+    report::Report,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("invalid tag key `{0}`, expected CUSTOM_DATA.<key> or BUILD_CONFIG.<key>")]
+    Key(String),
+    #[error("invalid tag filter `{0}`, expected <tag>:<value>")]
+    Filter(String),
+}
+
+/// A single value of `CUSTOM_DATA` or `BUILD_CONFIG`, as shown on the report page.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub source: TagSource,
+    pub key: String,
+    pub value: String,
+    /// Whether the key is extracted as a tag.
+    pub tag: bool,
+}
+
+/// Format a key like `CUSTOM_DATA.tier`, the way it's configured.
+pub fn format_key(key: &TagKey) -> String {
+    format!("{}.{}", key.source.field(), key.key)
+}
+
+/// Parse keys as formatted by [`format_key`], one per line. Empty lines are skipped.
+pub fn parse_keys(text: &str) -> Result<Vec<TagKey>, ParseError> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.split_once('.')
+                .and_then(|(field, key)| {
+                    let source = TagSource::ALL
+                        .into_iter()
+                        .find(|source| source.field().eq_ignore_ascii_case(field))?;
+                    let key = key.trim();
+
+                    (!key.is_empty()).then(|| TagKey {
+                        source,
+                        key: key.to_owned(),
+                    })
+                })
+                .ok_or_else(|| ParseError::Key(line.to_owned()))
+        })
+        .collect()
+}
+
+/// Parse filters like `tier:premium FLAVOR:free`, of which all must match. Values with spaces are
+/// quoted, like `device:"Pixel 6"`.
+pub fn parse_filter(text: &str) -> Result<Vec<Tag>, ParseError> {
+    split_terms(text)
+        .into_iter()
+        .map(|term| {
+            term.split_once(':')
+                .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+                .map(|(key, value)| Tag {
+                    key: key.to_owned(),
+                    value: value.to_owned(),
+                })
+                .ok_or_else(|| ParseError::Filter(term.clone()))
+        })
+        .collect()
+}
+
+/// Format a tag as a filter that [`parse_filter`] understands.
+pub fn format_filter(tag: &Tag) -> String {
+    if tag.value.contains(char::is_whitespace) {
+        format!("{}:\"{}\"", tag.key, tag.value)
+    } else {
+        format!("{}:{}", tag.key, tag.value)
+    }
+}
+
+/// Split filters at whitespace outside of quotes, removing the quotes.
+fn split_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !term.is_empty() {
+                    terms.push(std::mem::take(&mut term));
+                }
+            }
+            c => term.push(c),
+        }
+    }
+    if !term.is_empty() {
+        terms.push(term);
+    }
+
+    terms
+}
+
+/// Extract the tags of the given keys from a report. Keys that are missing or `null` are skipped,
+/// and if a key exists in both fields, `CUSTOM_DATA` wins.
+pub fn extract(keys: &[TagKey], report: &Report) -> Vec<Tag> {
+    let mut tags = Vec::<Tag>::new();
+
+    for source in TagSource::ALL {
+        for key in keys.iter().filter(|key| key.source == source) {
+            let Some(value) = fields(report, source).get(&key.key).and_then(format_value) else {
+                continue;
+            };
+            if tags.iter().any(|tag| tag.key == key.key) {
+                continue;
+            }
+
+            tags.push(Tag {
+                key: key.key.clone(),
+                value,
+            });
+        }
+    }
+
+    tags
+}
+
+/// All values of `CUSTOM_DATA` and `BUILD_CONFIG`, sorted by key, marking the ones that are
+/// extracted as tags.
+pub fn entries(keys: &[TagKey], report: &Report) -> Vec<Entry> {
+    TagSource::ALL
+        .into_iter()
+        .flat_map(|source| {
+            let mut entries = fields(report, source)
+                .iter()
+                .map(|(key, value)| Entry {
+                    source,
+                    key: key.clone(),
+                    value: format_value(value).unwrap_or_else(|| "null".to_owned()),
+                    tag: keys.iter().any(|k| k.source == source && &k.key == key),
+                })
+                .collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.key.cmp(&b.key));
+            entries
+        })
+        .collect()
+}
+
+/// Value of a single key of a report, or `None` if it's missing or `null`.
+pub fn value(report: &Report, key: &TagKey) -> Option<String> {
+    fields(report, key.source)
+        .get(&key.key)
+        .and_then(format_value)
+}
+
+fn fields(report: &Report, source: TagSource) -> &HashMap<String, Value> {
+    match source {
+        TagSource::CustomData => &report.custom_data,
+        TagSource::BuildConfig => &report.build_config,
+    }
+}
+
+/// Format a value as text, with strings unquoted.
+fn format_value(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{entries, extract, format_filter, format_key, parse_filter, parse_keys};
+    use crate::{
+        db::models::{Tag, TagKey, TagSource},
+        fixtures,
+        report::Report,
+    };
+
+    fn report() -> Report {
+        fixtures::report(|report| {
+            report["CUSTOM_DATA"] = json!({ "tier": "premium", "beta": true, "FLAVOR": "custom" });
+            report["BUILD_CONFIG"] =
+                json!({ "FLAVOR": "free", "VERSION_CODE": 42, "GIT_SHA": null });
+        })
+    }
+
+    fn key(source: TagSource, key: &str) -> TagKey {
+        TagKey {
+            source,
+            key: key.to_owned(),
+        }
+    }
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn keys() {
+        let keys = parse_keys("CUSTOM_DATA.tier\n\n  build_config.FLAVOR \n").unwrap();
+
+        assert_eq!(
+            vec![
+                key(TagSource::CustomData, "tier"),
+                key(TagSource::BuildConfig, "FLAVOR")
+            ],
+            keys
+        );
+        assert_eq!("BUILD_CONFIG.FLAVOR", format_key(&keys[1]));
+        assert!(parse_keys("tier").is_err());
+        assert!(parse_keys("CUSTOM_DATA.").is_err());
+        assert!(parse_keys("ENVIRONMENT.HOME").is_err());
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(
+            vec![tag("tier", "premium"), tag("FLAVOR", "free")],
+            parse_filter(" tier:premium  FLAVOR:free").unwrap()
+        );
+        assert!(parse_filter("").unwrap().is_empty());
+        assert_eq!(
+            vec![tag("device", "Pixel 6"), tag("tier", "free")],
+            parse_filter("device:\"Pixel 6\" tier:free").unwrap()
+        );
+        assert_eq!(
+            "device:\"Pixel 6\"",
+            format_filter(&tag("device", "Pixel 6"))
+        );
+        assert_eq!("tier:free", format_filter(&tag("tier", "free")));
+        assert!(parse_filter("tier").is_err());
+        assert!(parse_filter("tier:").is_err());
+    }
+
+    #[test]
+    fn extract_tags() {
+        let report = report();
+        let keys = [
+            key(TagSource::BuildConfig, "FLAVOR"),
+            key(TagSource::BuildConfig, "VERSION_CODE"),
+            key(TagSource::BuildConfig, "GIT_SHA"),
+            key(TagSource::CustomData, "tier"),
+            key(TagSource::CustomData, "FLAVOR"),
+            key(TagSource::CustomData, "missing"),
+        ];
+
+        assert_eq!(
+            vec![
+                tag("tier", "premium"),
+                tag("FLAVOR", "custom"),
+                tag("VERSION_CODE", "42")
+            ],
+            extract(&keys, &report)
+        );
+
+        let entries = entries(&keys[..1], &report);
+        assert_eq!(6, entries.len());
+        assert_eq!(
+            ("FLAVOR", "custom", false),
+            (
+                entries[0].key.as_str(),
+                entries[0].value.as_str(),
+                entries[0].tag
+            )
+        );
+        assert_eq!(
+            ("beta", "true"),
+            (entries[1].key.as_str(), entries[1].value.as_str())
+        );
+        assert_eq!(
+            ("FLAVOR", "free", true),
+            (
+                entries[3].key.as_str(),
+                entries[3].value.as_str(),
+                entries[3].tag
+            )
+        );
+        assert_eq!("null", entries[4].value);
+    }
+}
@@ -0,0 +1,130 @@
+//! Setup of logging and tracing, optionally exporting all spans to an OpenTelemetry collector.
+
+use std::env;
+
+use anyhow::{Context, Result};
+use opentelemetry::{KeyValue, trace::TracerProvider as _};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tracing::warn;
+use tracing_subscriber::{EnvFilter, prelude::*};
+
+use crate::settings::Tracing;
+
+/// Keeps the OpenTelemetry exporter alive. Pending spans are flushed out once it's dropped, so it
+/// must be held until the server stops, no matter whether that's with an error or not.
+pub struct Guard(Option<SdkTracerProvider>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let Some(provider) = self.0.take() else {
+            return;
+        };
+
+        // This blocks until all spans are exported, which happens on the exporter's own thread.
+        if let Err(e) = provider.shutdown() {
+            warn!("failed flushing remaining spans: {e}");
+        }
+    }
+}
+
+pub fn init(settings: Option<&Tracing>) -> Result<Guard> {
+    let filter = create_filter(settings.and_then(|t| t.filter.as_deref()))?;
+    let provider = create_provider(settings)?;
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer())
+        .with(provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        }))
+        .with(filter)
+        .init();
+
+    Ok(Guard(provider))
+}
+
+/// Create the log filter, taking the first available source in order of:
+///
+/// 1. The `RUST_LOG` environment variable.
+/// 2. The filter from the settings file.
+/// 3. A default that logs everything of this crate and only important messages of dependencies.
+fn create_filter(directives: Option<&str>) -> Result<EnvFilter> {
+    if env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return EnvFilter::try_from_default_env().context("invalid log filter in environment");
+    }
+
+    match directives {
+        Some(directives) => {
+            EnvFilter::try_new(directives).context("invalid log filter in settings")
+        }
+        None => Ok(EnvFilter::new(concat!(
+            "info,",
+            env!("CARGO_CRATE_NAME"),
+            "=trace,tower_http=trace"
+        ))),
+    }
+}
+
+/// Create the tracer provider that exports all spans, or `None` if no OTLP endpoint is configured.
+fn create_provider(settings: Option<&Tracing>) -> Result<Option<SdkTracerProvider>> {
+    let Some(otlp) = settings.and_then(|t| t.otlp.as_ref()) else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&otlp.endpoint)
+        .build()
+        .context("failed creating OTLP exporter")?;
+
+    Ok(Some(
+        SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource())
+            .build(),
+    ))
+}
+
+/// Attributes that identify this service in all exported spans.
+fn resource() -> Resource {
+    Resource::builder()
+        .with_service_name(env!("CARGO_PKG_NAME"))
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{Key, Value};
+
+    use super::{create_provider, resource};
+    use crate::settings::{Otlp, Tracing};
+
+    #[test]
+    fn resource_attributes() {
+        let resource = resource();
+
+        assert_eq!(
+            Some(Value::from("acralite")),
+            resource.get(&Key::new("service.name"))
+        );
+        assert_eq!(
+            Some(Value::from(env!("CARGO_PKG_VERSION"))),
+            resource.get(&Key::new("service.version"))
+        );
+    }
+
+    #[test]
+    fn exporter_only_with_endpoint() {
+        let tracing = |otlp| Tracing { filter: None, otlp };
+
+        assert!(create_provider(None).unwrap().is_none());
+        assert!(create_provider(Some(&tracing(None))).unwrap().is_none());
+
+        let provider = create_provider(Some(&tracing(Some(Otlp {
+            endpoint: "http://localhost:4318/v1/traces".to_owned(),
+        }))))
+        .unwrap();
+        assert!(provider.is_some());
+    }
+}
@@ -2,12 +2,15 @@ use askama::Template;
 use askama_web::WebTemplate;
 use axum::http::StatusCode;
 
+use crate::environments;
+
 pub mod apps {
     use anyhow::Result;
     use askama::Template;
     use askama_web::WebTemplate;
 
-    use crate::db::models::{App, Version};
+    use super::{EnvironmentTab, encode, environment_tabs};
+    use crate::db::models::{App, CrashType, InboundFilter, IssueSummary, Version};
 
     #[derive(Template, WebTemplate)]
     #[template(path = "apps/index.html")]
@@ -25,11 +28,288 @@ pub mod apps {
         pub result: Result<App>,
     }
 
+    #[derive(Template, WebTemplate)]
+    #[template(path = "apps/reprocess.html")]
+    pub struct Reprocess {}
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "apps/settings.html")]
+    pub struct Settings {
+        pub app: App,
+        /// Keys that are extracted as tags, one per line.
+        pub tag_keys: String,
+        /// Rules that put reports into environments, one per line.
+        pub environment_rules: String,
+        /// Inbound filters that drop unwanted reports, one per line.
+        pub filter_rules: String,
+        /// Inbound filters with the amount of reports they dropped.
+        pub inbound_filters: Vec<InboundFilter>,
+    }
+
     #[derive(Template, WebTemplate)]
     #[template(path = "apps/details.html")]
     pub struct Details {
         pub app: App,
         pub versions: Vec<Version>,
+        /// Crash type of the selected tab.
+        pub crash_type: CrashType,
+        /// Issues of the selected crash type.
+        pub issues: Vec<IssueSummary>,
+        /// Selected environment, or `all`.
+        pub environment: String,
+        /// Environments that the app received reports from.
+        pub environments: Vec<String>,
+    }
+
+    impl Details {
+        fn is_selected(&self, crash_type: CrashType) -> bool {
+            self.crash_type == crash_type
+        }
+
+        fn environment_tabs(&self) -> Vec<EnvironmentTab> {
+            environment_tabs(&self.environments, &self.environment, |environment| {
+                format!(
+                    "/apps/{}?type={}&environment={environment}",
+                    self.app.id,
+                    self.crash_type.as_str()
+                )
+            })
+        }
+
+        /// Link to the given crash type, in the selected environment.
+        fn crash_type_url(&self, crash_type: CrashType) -> String {
+            format!(
+                "/apps/{}?type={}&environment={}",
+                self.app.id,
+                crash_type.as_str(),
+                encode(&self.environment)
+            )
+        }
+    }
+}
+
+pub mod issues {
+    use askama::Template;
+    use askama_web::WebTemplate;
+
+    use super::{EnvironmentTab, encode, environment_tabs};
+    use crate::{
+        db::models::{App, Issue, IssueConditions, ReportEntry, Tag, TagCount},
+        tags,
+    };
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "issues/details.html")]
+    pub struct Details {
+        pub app: App,
+        pub issue: Issue,
+        pub reports: Vec<ReportEntry>,
+        /// Conditions of the devices over all reports of the issue.
+        pub conditions: IssueConditions,
+        /// Tags that the listed reports are filtered by, as entered.
+        pub tags: String,
+        /// Report counts per tag value over all reports of the issue.
+        pub tag_counts: Vec<TagCount>,
+        /// Selected environment, or `all`.
+        pub environment: String,
+        /// Environments that the app received reports from.
+        pub environments: Vec<String>,
+    }
+
+    impl Details {
+        /// Link to the reports that have the given tag value, on top of the current filter.
+        fn filter_url(&self, count: &TagCount) -> String {
+            let tag = Tag {
+                key: count.key.clone(),
+                value: count.value.clone(),
+            };
+            let filter = format!("{} {}", self.tags, tags::format_filter(&tag));
+
+            format!(
+                "/issues/{}?environment={}&tags={}",
+                self.issue.id,
+                encode(&self.environment),
+                encode(filter.trim())
+            )
+        }
+
+        fn environment_tabs(&self) -> Vec<EnvironmentTab> {
+            environment_tabs(&self.environments, &self.environment, |environment| {
+                if self.tags.is_empty() {
+                    format!("/issues/{}?environment={environment}", self.issue.id)
+                } else {
+                    format!(
+                        "/issues/{}?environment={environment}&tags={}",
+                        self.issue.id,
+                        encode(&self.tags)
+                    )
+                }
+            })
+        }
+
+        /// Amount and share of the reports with the given tag value.
+        fn tag_share(&self, count: &TagCount) -> String {
+            format!(
+                "{} ({}%)",
+                count.count,
+                self.conditions.percent(count.count)
+            )
+        }
+
+        /// Labels and report shares of the conditions, with rows for unchanged settings left out.
+        fn condition_rows(&self) -> Vec<(String, String)> {
+            let share = |count| {
+                format!(
+                    "{count} of {} reports ({}%)",
+                    self.conditions.reports,
+                    self.conditions.percent(count)
+                )
+            };
+
+            std::iter::once(("Low memory".to_owned(), share(self.conditions.low_memory)))
+                .chain(
+                    self.conditions
+                        .config_changes
+                        .iter()
+                        .map(|(change, count)| {
+                            (format!("{} changed", change.label()), share(*count))
+                        }),
+                )
+                .collect()
+        }
+    }
+}
+
+pub mod reports {
+    use askama::Template;
+    use askama_web::WebTemplate;
+
+    use crate::{
+        conditions::{Change, MemInfo, Memory, format_size},
+        db::models::App,
+        dropbox::{Content, Entry},
+        logcat::{Level, Line, Log, Source},
+        report::Report,
+        retrace::ReportText,
+        stacktrace::{self, InApp, Section},
+        tags,
+    };
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "reports/details.html")]
+    pub struct Details {
+        pub app: App,
+        pub report: Report,
+        /// Text fields of the report, deobfuscated if `retraced` is set and it succeeded.
+        pub text: ReportText,
+        pub retraced: bool,
+        pub retrace_failed: bool,
+        /// Parsed entries of the `DROPBOX` field.
+        pub dropbox: Vec<Entry>,
+        /// Settings that changed between the start of the app and the crash.
+        pub config_changes: Vec<Change>,
+        pub memory: Memory,
+        /// Parsed `DUMPSYS_MEMINFO` field, if it was collected.
+        pub meminfo: Option<MemInfo>,
+        /// Values of `CUSTOM_DATA` and `BUILD_CONFIG`.
+        pub entries: Vec<tags::Entry>,
+        /// Package prefixes of in-app classes, in addition to the package of the app.
+        pub in_app: Vec<String>,
+    }
+
+    impl Details {
+        /// The stack trace with the frames outside of the app collapsed.
+        fn stack_trace(&self) -> Vec<Section<'_>> {
+            stacktrace::sections(
+                &self.text.stack_trace,
+                &InApp::new(&self.report.package_name, &self.in_app),
+            )
+        }
+
+        fn available_memory(&self) -> String {
+            format!(
+                "{} of {} ({}%)",
+                format_size(self.memory.available),
+                format_size(self.memory.total),
+                self.memory.available_percent()
+            )
+        }
+    }
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "reports/logs.html")]
+    pub struct Logs {
+        pub app: App,
+        pub report: Report,
+        /// Logs that the report contains.
+        pub sources: Vec<Source>,
+        pub source: Source,
+        /// Minimum level of the shown lines, if filtered.
+        pub level: Option<Level>,
+        /// Tags of the shown lines as entered, if filtered.
+        pub tag: String,
+        /// The filtered log.
+        pub log: Log,
+        /// Index of the last line before the crash.
+        pub crash_line: Option<usize>,
+    }
+
+    impl Logs {
+        // Loop variables are references, which can't be dereferenced the same way with every
+        // version of the template engine.
+        #[allow(clippy::trivially_copy_pass_by_ref)]
+        fn is_source(&self, source: &Source) -> bool {
+            self.source == *source
+        }
+
+        fn is_level(&self, level: Level) -> bool {
+            self.level == Some(level)
+        }
+
+        fn is_crash_line(&self, line: &Line) -> bool {
+            self.crash_line
+                .and_then(|index| self.log.lines.get(index))
+                .is_some_and(|crash| std::ptr::eq(crash, line))
+        }
+
+        /// Bulma classes of a line, which highlight the crashed process and thread.
+        fn line_class(&self, line: &Line) -> &'static str {
+            if line.pid.is_none() || line.pid != self.log.pid {
+                ""
+            } else if line.tid.is_some() && line.tid == self.log.tid {
+                "has-background-danger-light"
+            } else {
+                "has-background-warning-light"
+            }
+        }
+    }
+}
+
+pub mod tools {
+    use askama::Template;
+    use askama_web::WebTemplate;
+
+    use crate::{
+        db::models::{App, Issue, Version},
+        retrace::Retraced,
+    };
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "tools/retrace.html")]
+    pub struct Retrace {
+        pub apps: Vec<(App, Vec<Version>)>,
+        pub stack_trace: String,
+        pub version_id: Option<i64>,
+        pub retraced: Option<Retraced>,
+        /// Existing issue with the same fingerprint as the input.
+        pub issue: Option<Issue>,
+        pub error: Option<String>,
+    }
+
+    impl Retrace {
+        fn is_selected(&self, version: &Version) -> bool {
+            self.version_id == Some(version.id)
+        }
     }
 }
 
@@ -37,7 +317,7 @@ pub mod users {
     use askama::Template;
     use askama_web::WebTemplate;
 
-    use crate::db::models::User;
+    use crate::db::models::{App, NotificationSettings, User};
 
     #[derive(Template, WebTemplate)]
     #[template(path = "users/list.html")]
@@ -48,6 +328,105 @@ pub mod users {
     #[derive(Template, WebTemplate)]
     #[template(path = "users/create.html")]
     pub struct Create {}
+
+    #[derive(Template, WebTemplate)]
+    #[template(path = "users/notifications.html")]
+    pub struct Notifications {
+        pub user: User,
+        pub settings: Option<NotificationSettings>,
+        pub apps: Vec<App>,
+        /// IDs of the apps that the user gets alerts about new issues for.
+        pub alert_apps: Vec<i64>,
+    }
+}
+
+pub mod mail {
+    use askama::Template;
+
+    use crate::db::models::{Issue, IssueSummary, VersionTrend};
+
+    pub struct NewIssue {
+        pub base_url: String,
+        pub app_name: String,
+        pub issue: Issue,
+    }
+
+    #[derive(Template)]
+    #[template(path = "mail/new_issue.html")]
+    pub struct NewIssueHtml<'a> {
+        pub mail: &'a NewIssue,
+    }
+
+    #[derive(Template)]
+    #[template(path = "mail/new_issue.txt")]
+    pub struct NewIssueText<'a> {
+        pub mail: &'a NewIssue,
+    }
+
+    pub struct Digest {
+        pub base_url: String,
+        pub new_issues: Vec<IssueSummary>,
+        pub top_issues: Vec<IssueSummary>,
+        pub rising_versions: Vec<VersionTrend>,
+    }
+
+    impl Digest {
+        pub fn is_empty(&self) -> bool {
+            self.new_issues.is_empty()
+                && self.top_issues.is_empty()
+                && self.rising_versions.is_empty()
+        }
+    }
+
+    #[derive(Template)]
+    #[template(path = "mail/digest.html")]
+    pub struct DigestHtml<'a> {
+        pub mail: &'a Digest,
+    }
+
+    #[derive(Template)]
+    #[template(path = "mail/digest.txt")]
+    pub struct DigestText<'a> {
+        pub mail: &'a Digest,
+    }
+}
+
+/// A link to the same view in another environment.
+pub struct EnvironmentTab {
+    pub label: String,
+    pub url: String,
+    pub selected: bool,
+}
+
+/// Links to the given environments and to all of them, with the selected one marked.
+fn environment_tabs(
+    environments: &[String],
+    selected: &str,
+    url: impl Fn(&str) -> String,
+) -> Vec<EnvironmentTab> {
+    environments
+        .iter()
+        .map(String::as_str)
+        .chain([environments::ALL])
+        .map(|environment| EnvironmentTab {
+            label: environment.to_owned(),
+            url: url(&encode(environment)),
+            selected: environment == selected,
+        })
+        .collect()
+}
+
+/// Percent-encode a value for use in the query of a link.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(b).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
 }
 
 #[derive(Template, WebTemplate)]